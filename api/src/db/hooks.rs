@@ -0,0 +1,354 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use r2d2_sqlite::rusqlite;
+
+use crate::db::models::feature::Feature;
+use crate::db::models::file::File;
+use crate::db::models::project::Project;
+use crate::db::processor::Processor;
+use crate::db::Connection;
+use crate::errors::FownerError;
+use crate::git::backend::GitBackendKind;
+use crate::git::manager::GitManager;
+
+/// The hooks `install` writes, each runs `fowner hooks <name>`
+pub const HOOKS: [&str; 3] = ["pre-commit", "commit-msg", "post-commit"];
+
+/// Marks the hooks fowner wrote so reinstalling can replace them without `--force`
+const HOOK_MARKER: &str = "# Installed by fowner, reinstall with `fowner hooks install`";
+
+/// Git keeps everything below this line of a verbose commit message out of the commit
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// How the installed hooks call back into fowner
+pub struct HookCommand {
+    pub executable: PathBuf,
+    pub database_path: PathBuf,
+    pub git_backend: GitBackendKind,
+    /// The hooks change to this directory so relative repository and database paths resolve
+    pub working_dir: PathBuf,
+}
+
+impl HookCommand {
+    fn script(&self, hook: &str, repo_path: &Path) -> String {
+        let mut script = format!("#!/bin/sh\n{}\n", HOOK_MARKER);
+        let mut args = String::new();
+        if hook == "commit-msg" {
+            // The message file is relative to the working tree the hook starts in
+            script
+                .push_str("case \"$1\" in /*) message=\"$1\" ;; *) message=\"$PWD/$1\" ;; esac\n");
+            args.push_str(" \"$message\"");
+        }
+        // A missing or failing fowner must never block a commit, including failures to open
+        // the database before the hook itself runs
+        script.push_str(&format!(
+            "[ -x {exe} ] || exit 0\ncd {dir} || exit 0\n{exe} --database-path {db} --git-backend {backend} hooks {hook} --repo-path {repo}{args} || exit 0\n",
+            exe = quote(&self.executable),
+            dir = quote(&self.working_dir),
+            db = quote(&self.database_path),
+            backend = self.git_backend.as_str(),
+            hook = hook,
+            repo = quote(repo_path),
+            args = args,
+        ));
+        script
+    }
+}
+
+/// Quotes a path for a POSIX shell
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// A file staged for the next commit with the features and owners it already has
+#[derive(Debug, PartialEq)]
+pub struct StagedFile {
+    pub path: String,
+    pub features: Vec<String>,
+    pub owners: Vec<String>,
+    /// The file has not been ingested yet
+    pub is_new: bool,
+}
+
+/// Runs the git hooks for a local repository that was ingested with `fowner history`,
+/// once for each project of the repository
+pub struct Hooks<'a> {
+    conn: &'a Connection<'a>,
+    git_manager: GitManager,
+    projects: Vec<Project>,
+}
+
+impl<'a> Hooks<'a> {
+    pub fn load(
+        repo_path: &Path,
+        backend: GitBackendKind,
+        timeout: Duration,
+        conn: &'a Connection,
+    ) -> Result<Self, FownerError> {
+        let projects = Project::load_all_by_path(repo_path, conn)?;
+        // The projects of a repository share its clone
+        let project = projects.first().ok_or_else(|| {
+            FownerError::NotFound(format!(
+                "No project for {}, run `fowner history` first",
+                repo_path.display()
+            ))
+        })?;
+        let git_manager = GitManager {
+            path: repo_path.to_path_buf(),
+            url: project.repo_url.clone(),
+            backend,
+            credentials: project.credentials.clone(),
+            remotes: project.remote_config(),
+            timeout,
+        };
+        Ok(Self {
+            conn,
+            git_manager,
+            projects,
+        })
+    }
+
+    /// Writes the hooks into the repository's hooks directory, hooks that fowner did not write
+    /// are only replaced with `force`
+    pub fn install(&self, command: &HookCommand, force: bool) -> Result<Vec<PathBuf>, FownerError> {
+        let hooks_dir = self.git_manager.hooks_dir()?;
+        let paths: Vec<PathBuf> = HOOKS.iter().map(|hook| hooks_dir.join(hook)).collect();
+        // Checked up front so a conflict does not leave the hooks half installed
+        for path in &paths {
+            if !force && path.exists() && !fs::read_to_string(path)?.contains(HOOK_MARKER) {
+                return Err(FownerError::HookExists(path.display().to_string()));
+            }
+        }
+        fs::create_dir_all(&hooks_dir)?;
+        for (hook, path) in HOOKS.iter().zip(&paths) {
+            fs::write(path, command.script(hook, &self.git_manager.path))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+            }
+        }
+        Ok(paths)
+    }
+
+    /// The staged files with the features and owners they already have, files outside a
+    /// project's subpaths or excluded by its globs are skipped for that project
+    pub fn staged_files(&self) -> Result<Vec<StagedFile>, FownerError> {
+        let paths = self.git_manager.staged_files()?;
+        let mut staged = vec![];
+        for project in &self.projects {
+            let subpaths = project.subpaths();
+            let path_filter = project.path_filter()?;
+            for path in &paths {
+                let project_path = match subpaths.to_project(path) {
+                    Some(project_path) if path_filter.is_included(&project_path) => project_path,
+                    _ => continue,
+                };
+                match File::load_by_path(project.id, project_path, self.conn) {
+                    Ok(file) => staged.push(StagedFile {
+                        path: path.clone(),
+                        features: file.feature_names,
+                        owners: file.owners,
+                        is_new: false,
+                    }),
+                    Err(FownerError::Rusqlite(rusqlite::Error::QueryReturnedNoRows)) => staged
+                        .push(StagedFile {
+                            path: path.clone(),
+                            features: vec![],
+                            owners: vec![],
+                            is_new: true,
+                        }),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(staged)
+    }
+
+    /// The features tagged in a commit message that none of the projects have yet
+    pub fn unknown_features(&self, message: &str) -> Result<Vec<String>, FownerError> {
+        // Git drops the comment lines and everything below the scissors before committing
+        let message = message
+            .lines()
+            .take_while(|line| *line != SCISSORS)
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let mut known = vec![];
+        let mut tagged: Vec<String> = vec![];
+        for project in &self.projects {
+            known.extend(
+                Feature::load_by_project(project.id, self.conn)?
+                    .into_iter()
+                    .map(|f| f.name.to_lowercase()),
+            );
+            for feature in project
+                .commit_feature_extractor()?
+                .extract_from_message(&message)
+            {
+                if !tagged.iter().any(|t| t.eq_ignore_ascii_case(&feature)) {
+                    tagged.push(feature);
+                }
+            }
+        }
+        Ok(tagged
+            .into_iter()
+            .filter(|feature| !known.contains(&feature.to_lowercase()))
+            .collect())
+    }
+
+    /// Ingests the commits made since the last ingest into each project, returns how many were
+    /// stored. Commits an amend or rebase dropped are flagged first, as the watcher does
+    pub async fn ingest(self) -> Result<usize, FownerError> {
+        let mut stored = 0;
+        for project in self.projects {
+            let processor = Processor {
+                conn: self.conn,
+                // `GitManager::clone` clones the repository, this copies the manager
+                git_manager: Clone::clone(&self.git_manager),
+                project,
+            };
+            processor.reconcile_unreachable(false)?;
+            let (commits, _) = processor.fetch_commits_and_update_db(None, true).await?;
+            stored += commits;
+        }
+        Ok(stored)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::db::hooks::{HookCommand, Hooks, StagedFile, HOOKS};
+    use crate::db::models::commit::Commit;
+    use crate::db::models::project::UpdateProject;
+    use crate::db::processor::Processor;
+    use crate::db::Connection;
+    use crate::git::backend::{GitBackendKind, DEFAULT_GIT_TIMEOUT};
+    use crate::test::builders::repo_builder::RepoBuilder;
+    use crate::test::tests::TestHandler;
+
+    #[actix_web::test]
+    async fn hooks() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/billing.rs", "fn bill() {}\n");
+        repo.commit("Add billing [Billing]");
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        // `GitManager::clone` clones the repository, this copies the manager
+        let processor = Processor::new(Clone::clone(&git_manager), None, &conn).unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let load = || Hooks::load(&repo.path, GitBackendKind::Cli, DEFAULT_GIT_TIMEOUT, &conn);
+        assert!(Hooks::load(
+            &handler.tmp_dir.join("missing"),
+            GitBackendKind::Cli,
+            DEFAULT_GIT_TIMEOUT,
+            &conn
+        )
+        .is_err());
+        // Only the exact path, not every path ending with it
+        assert!(Hooks::load(
+            &PathBuf::from("repo"),
+            GitBackendKind::Cli,
+            DEFAULT_GIT_TIMEOUT,
+            &conn
+        )
+        .is_err());
+        let hooks = load().unwrap();
+        let command = HookCommand {
+            executable: PathBuf::from("/usr/local/bin/fowner"),
+            database_path: handler.tmp_dir.join("db.sqlite3"),
+            git_backend: GitBackendKind::Cli,
+            working_dir: handler.tmp_dir.clone(),
+        };
+        let paths = hooks.install(&command, false).unwrap();
+        assert_eq!(paths.len(), HOOKS.len());
+        let commit_msg = fs::read_to_string(repo.path.join(".git/hooks/commit-msg")).unwrap();
+        assert!(commit_msg.contains("hooks commit-msg --repo-path"));
+        assert!(commit_msg.ends_with(" \"$message\" || exit 0\n"));
+        assert!(!commit_msg.contains("exec "));
+        // Reinstalling replaces fowner's own hooks but not anyone else's
+        assert!(hooks.install(&command, false).is_ok());
+        fs::write(repo.path.join(".git/hooks/pre-commit"), "#!/bin/sh\n").unwrap();
+        assert!(hooks.install(&command, false).is_err());
+        assert!(hooks.install(&command, true).is_ok());
+
+        assert_eq!(
+            hooks
+                .unknown_features("Bill more [billing, Invoices]\n# [Ignored]\n")
+                .unwrap(),
+            vec!["Invoices".to_string()]
+        );
+
+        // A second project of the repository that only covers `src` and excludes invoices
+        let src = Processor::new(git_manager, Some(vec!["src".to_string()]), &conn).unwrap();
+        src.fetch_commits_and_update_db(None, true).await.unwrap();
+        src.project
+            .clone()
+            .update(
+                UpdateProject {
+                    exclude_globs: Some(Some(vec!["invoice.rs".to_string()])),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        let hooks = load().unwrap();
+
+        repo.write("README.md", "# Billing\n");
+        repo.write("src/billing.rs", "fn bill() {\n    invoice()\n}\n");
+        repo.write("src/invoice.rs", "fn invoice() {}\n");
+        repo.git(&["add", "-A"]);
+        assert_eq!(
+            hooks.staged_files().unwrap(),
+            vec![
+                StagedFile {
+                    path: "README.md".to_string(),
+                    features: vec![],
+                    owners: vec![],
+                    is_new: true,
+                },
+                StagedFile {
+                    path: "src/billing.rs".to_string(),
+                    features: vec!["Billing".to_string()],
+                    owners: vec!["Krakaw".to_string()],
+                    is_new: false,
+                },
+                StagedFile {
+                    path: "src/invoice.rs".to_string(),
+                    features: vec![],
+                    owners: vec![],
+                    is_new: true,
+                },
+                StagedFile {
+                    path: "src/billing.rs".to_string(),
+                    features: vec!["Billing".to_string()],
+                    owners: vec!["Krakaw".to_string()],
+                    is_new: false,
+                },
+            ]
+        );
+
+        // Every project of the repository is ingested
+        let invoices = repo.commit("Add invoices [Invoices]");
+        assert_eq!(hooks.ingest().await.unwrap(), 2);
+        // Amending replaces the commit, the old one is flagged rather than left tracked
+        repo.git(&["commit", "-q", "--amend", "-m", "Add invoicing [Invoices]"]);
+        assert_eq!(load().unwrap().ingest().await.unwrap(), 2);
+        assert!(Commit::load_by_sha(invoices, &conn).unwrap().unreachable);
+        assert!(load()
+            .unwrap()
+            .unknown_features("[Invoices]")
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -155,6 +155,275 @@ pub fn migrations() -> Migrations<'static> {
             ALTER TABLE owners ADD COLUMN github_url TEXT NULL;
         "#,
         ),
+        M::up(
+            r#"
+            CREATE TABLE IF NOT EXISTS file_renames
+            (
+                file_id       INTEGER,
+                previous_path TEXT NOT NULL,
+                path          TEXT NOT NULL,
+                sha           TEXT NOT NULL,
+                created_at    INT  NOT NULL,
+                updated_at    INT  NOT NULL,
+                CONSTRAINT fk_files
+                    FOREIGN KEY (file_id)
+                    REFERENCES files(id)
+                    ON DELETE CASCADE
+            );
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE file_commits ADD COLUMN additions INT DEFAULT 0 NOT NULL;
+            ALTER TABLE file_commits ADD COLUMN deletions INT DEFAULT 0 NOT NULL;
+        "#,
+        ),
+        M::up(
+            r#"
+            CREATE TABLE IF NOT EXISTS file_current_owners
+            (
+                file_id     INTEGER,
+                owner_id    INTEGER,
+                line_count  INT  NOT NULL,
+                sha         TEXT NOT NULL,
+                created_at  INT  NOT NULL,
+                updated_at  INT  NOT NULL,
+                CONSTRAINT fk_files
+                    FOREIGN KEY (file_id)
+                    REFERENCES files(id)
+                    ON DELETE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_file_current_owners_file_id_owner_id ON file_current_owners (file_id, owner_id);
+        "#,
+        ),
+        M::up(
+            r#"
+            CREATE TABLE owners_by_email
+            (
+                id               INTEGER PRIMARY KEY AUTOINCREMENT,
+                handle           TEXT    NOT NULL,
+                email            TEXT    NULL,
+                name             TEXT    NULL,
+                primary_owner_id INTEGER NULL,
+                github_url       TEXT    NULL,
+                created_at       INT     NOT NULL,
+                updated_at       INT     NOT NULL
+            );
+            INSERT INTO owners_by_email (id, handle, name, primary_owner_id, github_url, created_at, updated_at)
+                SELECT id, handle, name, primary_owner_id, github_url, created_at, updated_at FROM owners;
+            DROP TABLE owners;
+            ALTER TABLE owners_by_email RENAME TO owners;
+            CREATE UNIQUE INDEX idx_owners_email ON owners (LOWER(email));
+            CREATE INDEX idx_owners_handle ON owners (handle);
+            ALTER TABLE commits ADD COLUMN committer_handle TEXT NULL;
+            ALTER TABLE commits ADD COLUMN committer_email TEXT NULL;
+            ALTER TABLE commits ADD COLUMN committer_time INT NULL;
+        "#,
+        ),
+        M::up(
+            r#"
+            CREATE TABLE IF NOT EXISTS commit_co_authors
+            (
+                commit_id  INTEGER,
+                owner_id   INTEGER,
+                created_at INT NOT NULL,
+                updated_at INT NOT NULL,
+                CONSTRAINT fk_commits
+                    FOREIGN KEY (commit_id)
+                    REFERENCES commits(id)
+                    ON DELETE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_commit_co_authors_commit_id_owner_id ON commit_co_authors (commit_id, owner_id);
+            ALTER TABLE file_owners ADD COLUMN co_author INT DEFAULT 0 NOT NULL;
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE commits ADD COLUMN body TEXT NULL;
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN commit_feature_patterns TEXT NULL;
+            ALTER TABLE projects ADD COLUMN label_feature_patterns TEXT NULL;
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN conventional_commits INT DEFAULT 0 NOT NULL;
+            ALTER TABLE commits ADD COLUMN commit_type TEXT NULL;
+            CREATE INDEX idx_commits_commit_type ON commits (commit_type);
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN branches TEXT NULL;
+            CREATE TABLE IF NOT EXISTS commit_refs
+            (
+                commit_id  INTEGER,
+                ref_name   TEXT NOT NULL,
+                created_at INT  NOT NULL,
+                updated_at INT  NOT NULL,
+                CONSTRAINT fk_commits
+                    FOREIGN KEY (commit_id)
+                    REFERENCES commits(id)
+                    ON DELETE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_commit_refs_commit_id_ref_name ON commit_refs (commit_id, ref_name);
+            CREATE INDEX idx_commit_refs_ref_name ON commit_refs (ref_name);
+        "#,
+        ),
+        M::up(
+            r#"
+            CREATE TABLE IF NOT EXISTS tags
+            (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id  INTEGER NOT NULL,
+                name        TEXT    NOT NULL,
+                sha         TEXT    NOT NULL,
+                tagged_at   INT     NOT NULL,
+                created_at  INT     NOT NULL,
+                updated_at  INT     NOT NULL,
+                CONSTRAINT fk_projects
+                    FOREIGN KEY (project_id)
+                    REFERENCES projects(id)
+                    ON DELETE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_tags_project_id_name ON tags (project_id, name);
+        "#,
+        ),
+        M::up(
+            r#"
+            CREATE TABLE IF NOT EXISTS project_refs
+            (
+                project_id INTEGER NOT NULL,
+                ref_name   TEXT    NOT NULL,
+                sha        TEXT    NOT NULL,
+                created_at INT     NOT NULL,
+                updated_at INT     NOT NULL,
+                CONSTRAINT fk_projects
+                    FOREIGN KEY (project_id)
+                    REFERENCES projects(id)
+                    ON DELETE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_project_refs_project_id_ref_name ON project_refs (project_id, ref_name);
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE commits ADD COLUMN unreachable INT DEFAULT 0 NOT NULL;
+            CREATE TABLE IF NOT EXISTS commit_features
+            (
+                commit_id  INTEGER,
+                feature_id INTEGER,
+                created_at INT NOT NULL,
+                updated_at INT NOT NULL,
+                CONSTRAINT fk_commits
+                    FOREIGN KEY (commit_id)
+                    REFERENCES commits(id)
+                    ON DELETE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_commit_features_commit_id_feature_id ON commit_features (commit_id, feature_id);
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN merge_policy TEXT NULL;
+            ALTER TABLE commits ADD COLUMN is_merge INT DEFAULT 0 NOT NULL;
+            UPDATE commits SET is_merge = 1 WHERE parent_sha LIKE '%,%';
+        "#,
+        ),
+        // Projects sharing a clone have the same path, so the table is rebuilt without the
+        // unique constraint on it
+        M::up(
+            r#"
+            CREATE TABLE projects_new
+            (
+                id                      INTEGER PRIMARY KEY AUTOINCREMENT,
+                path                    TEXT NOT NULL,
+                name                    TEXT NULL,
+                repo_url                TEXT NULL,
+                github_api_token        TEXT NULL,
+                github_labels_only      INT DEFAULT 0 NOT NULL,
+                created_at              INT NOT NULL,
+                updated_at              INT NOT NULL,
+                commit_feature_patterns TEXT NULL,
+                label_feature_patterns  TEXT NULL,
+                conventional_commits    INT DEFAULT 0 NOT NULL,
+                branches                TEXT NULL,
+                merge_policy            TEXT NULL,
+                subpaths                TEXT NULL
+            );
+            INSERT INTO projects_new (id, path, name, repo_url, github_api_token, github_labels_only, created_at, updated_at, commit_feature_patterns, label_feature_patterns, conventional_commits, branches, merge_policy)
+                SELECT id, path, name, repo_url, github_api_token, github_labels_only, created_at, updated_at, commit_feature_patterns, label_feature_patterns, conventional_commits, branches, merge_policy
+                FROM projects;
+            DROP TABLE projects;
+            ALTER TABLE projects_new RENAME TO projects;
+            CREATE UNIQUE INDEX idx_projects_path_subpaths ON projects (path, coalesce(subpaths, ''));
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN include_globs TEXT NULL;
+            ALTER TABLE projects ADD COLUMN exclude_globs TEXT NULL;
+            ALTER TABLE files ADD COLUMN excluded INT DEFAULT 0 NOT NULL;
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE owners ADD COLUMN is_bot INT DEFAULT 0 NOT NULL;
+            ALTER TABLE owners ADD COLUMN is_bot_manual INT DEFAULT 0 NOT NULL;
+            CREATE TABLE bot_patterns (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                pattern     TEXT NOT NULL UNIQUE,
+                created_at  INT NOT NULL,
+                updated_at  INT NOT NULL
+            );
+            INSERT INTO bot_patterns (pattern, created_at, updated_at) VALUES
+                ('(?i)\[bot\]', strftime('%s','now'), strftime('%s','now')),
+                ('(?i)^renovate', strftime('%s','now'), strftime('%s','now')),
+                ('(?i)^dependabot', strftime('%s','now'), strftime('%s','now'));
+            UPDATE owners SET is_bot = 1
+                WHERE handle LIKE '%[bot]%' OR email LIKE '%[bot]%'
+                    OR handle LIKE 'renovate%' OR email LIKE 'renovate%'
+                    OR handle LIKE 'dependabot%' OR email LIKE 'dependabot%';
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN mailmap TEXT NULL;
+            CREATE TABLE owner_merges (
+                id                          INTEGER PRIMARY KEY AUTOINCREMENT,
+                owner_id                    INTEGER NOT NULL,
+                primary_owner_id            INTEGER NULL,
+                previous_primary_owner_id   INTEGER NULL,
+                source                      TEXT NOT NULL,
+                project_id                  INTEGER NULL,
+                created_at                  INT NOT NULL,
+                CONSTRAINT fk_owners
+                    FOREIGN KEY (owner_id)
+                    REFERENCES owners(id)
+                    ON DELETE CASCADE,
+                CONSTRAINT fk_projects
+                    FOREIGN KEY (project_id)
+                    REFERENCES projects(id)
+                    ON DELETE SET NULL
+            );
+            CREATE INDEX idx_owner_merges_owner_id ON owner_merges (owner_id);
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN credentials TEXT NULL;
+        "#,
+        ),
+        M::up(
+            r#"
+            ALTER TABLE projects ADD COLUMN remote_name TEXT NOT NULL DEFAULT 'origin';
+            ALTER TABLE projects ADD COLUMN clone_mode TEXT NOT NULL DEFAULT 'checkout';
+            ALTER TABLE projects ADD COLUMN remotes TEXT NULL;
+        "#,
+        ),
     ])
 }
 
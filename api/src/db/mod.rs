@@ -9,10 +9,12 @@ use r2d2_sqlite::SqliteConnectionManager;
 
 use crate::FownerError;
 
+pub mod hooks;
 mod migrations;
 pub mod models;
 pub mod processor;
 pub mod stats;
+pub mod watcher;
 
 #[derive(Debug, Clone)]
 pub struct Db {
@@ -43,7 +45,11 @@ impl Db {
     pub fn init(&self) -> Result<(), FownerError> {
         let mut connection = self.pool.get()?;
         let migrations = migrations::migrations();
-        migrations.to_latest(&mut connection)?;
+        // Rebuilding a table drops it, which would cascade to the rows referencing it
+        connection.execute_batch("PRAGMA foreign_keys = OFF;")?;
+        let result = migrations.to_latest(&mut connection);
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        result?;
         Ok(())
     }
 }
@@ -69,7 +75,7 @@ impl<'a> From<Transaction<'a>> for Connection<'a> {
 
 impl<'a> Connection<'a> {
     #[inline]
-    pub fn prepare(&self, query: &str) -> Result<Statement, FownerError> {
+    pub fn prepare(&self, query: &str) -> Result<Statement<'_>, FownerError> {
         match self {
             Connection::Pooled(client) => Ok(client.prepare(query)?),
             Connection::Transaction(transaction) => Ok(transaction.prepare(query)?),
@@ -0,0 +1,95 @@
+use chrono::NaiveDateTime;
+use r2d2_sqlite::rusqlite::{params, Row};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::owner::Owner;
+use crate::db::models::{extract_all, extract_first};
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+/// A regex matched against owner handles and emails to recognise bots and service accounts
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BotPattern {
+    pub id: u32,
+    pub pattern: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewBotPattern {
+    pub pattern: String,
+}
+
+impl BotPattern {
+    fn sql(where_clause: &str) -> String {
+        format!(
+            "SELECT id, pattern, created_at, updated_at FROM bot_patterns WHERE {}",
+            where_clause
+        )
+    }
+
+    pub fn all(conn: &Connection) -> Result<Vec<Self>, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql("1 = 1 ORDER BY id"))?;
+        extract_all!(params![], stmt)
+    }
+
+    pub fn load(id: u32, conn: &Connection) -> Result<Self, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql("id = ?1"))?;
+        extract_first!(params![id], stmt)
+    }
+
+    /// The compiled patterns, stored patterns were validated when they were saved
+    pub fn regexes(conn: &Connection) -> Result<Vec<Regex>, FownerError> {
+        Self::all(conn)?
+            .iter()
+            .map(|p| Regex::new(&p.pattern).map_err(FownerError::from))
+            .collect()
+    }
+
+    /// Deletes the pattern and unflags the owners only it matched
+    pub fn destroy(self, conn: &Connection) -> Result<usize, FownerError> {
+        let mut stmt = conn.prepare("DELETE FROM bot_patterns WHERE id = ?1")?;
+        let result = stmt.execute(params![self.id])?;
+        Owner::flag_bots(conn)?;
+        Ok(result)
+    }
+}
+
+impl NewBotPattern {
+    /// Saves the pattern and flags the existing owners it matches
+    pub fn save(&self, conn: &Connection) -> Result<BotPattern, FownerError> {
+        let pattern = self.pattern.trim();
+        if pattern.is_empty() {
+            return Err(FownerError::InvalidBotPattern(
+                "Patterns cannot be empty".to_string(),
+            ));
+        }
+        Regex::new(pattern)
+            .map_err(|e| FownerError::InvalidBotPattern(format!("{}: {}", pattern, e)))?;
+        let mut stmt = conn.prepare(
+            r#"
+        INSERT INTO bot_patterns (pattern, created_at, updated_at)
+        VALUES (?1, strftime('%s', 'now'), strftime('%s', 'now'))
+        ON CONFLICT DO NOTHING;
+        "#,
+        )?;
+        stmt.execute(params![pattern])?;
+        Owner::flag_bots(conn)?;
+        let mut stmt = conn.prepare(&BotPattern::sql("pattern = ?1"))?;
+        let bot_pattern = stmt.query_row(params![pattern], |r| Ok(BotPattern::from(r)))?;
+        Ok(bot_pattern)
+    }
+}
+
+impl<'stmt> From<&Row<'stmt>> for BotPattern {
+    fn from(row: &Row) -> Self {
+        Self {
+            id: row.get(0).unwrap(),
+            pattern: row.get(1).unwrap(),
+            created_at: NaiveDateTime::from_timestamp(row.get(2).unwrap(), 0),
+            updated_at: NaiveDateTime::from_timestamp(row.get(3).unwrap(), 0),
+        }
+    }
+}
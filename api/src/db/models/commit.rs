@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use chrono::NaiveDateTime;
 use r2d2_sqlite::rusqlite::{params, Row};
@@ -9,6 +9,26 @@ use crate::db::Connection;
 use crate::errors::FownerError;
 use crate::server::paging::SortDir;
 
+const UNREACHABLE_FILE_COMMITS: &str =
+    "commit_id IN (SELECT id FROM commits WHERE project_id = ?1 AND unreachable = 1)";
+const UNREACHABLE_FILE_OWNERS: &str = r#"file_id IN (SELECT id FROM files WHERE project_id = ?1)
+    AND sha IN (SELECT sha FROM commits WHERE project_id = ?1 AND unreachable = 1)"#;
+/// A file keeps a feature while any reachable commit that touched it carried the feature
+const UNREACHABLE_FILE_FEATURES: &str = r#"EXISTS (
+        SELECT 1
+        FROM file_commits fc
+            INNER JOIN commits c ON c.id = fc.commit_id
+            INNER JOIN commit_features cf ON cf.commit_id = c.id
+        WHERE c.project_id = ?1 AND c.unreachable = 1
+          AND fc.file_id = file_features.file_id AND cf.feature_id = file_features.feature_id)
+    AND NOT EXISTS (
+        SELECT 1
+        FROM file_commits fc
+            INNER JOIN commits c ON c.id = fc.commit_id
+            INNER JOIN commit_features cf ON cf.commit_id = c.id
+        WHERE c.unreachable = 0
+          AND fc.file_id = file_features.file_id AND cf.feature_id = file_features.feature_id)"#;
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Commit {
     pub id: u32,
@@ -18,10 +38,31 @@ pub struct Commit {
     pub sha: String,
     pub parent_sha: Option<Vec<String>>,
     pub description: String,
+    pub body: Option<String>,
+    pub commit_type: Option<String>,
     pub commit_time: NaiveDateTime,
     pub feature_names: Vec<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub committer_handle: Option<String>,
+    pub committer_email: Option<String>,
+    pub committer_time: Option<NaiveDateTime>,
+    pub co_author_handles: Vec<String>,
+    /// The tracked branches that contain this commit
+    pub refs: Vec<String>,
+    /// Set when a rewrite upstream left the commit outside every tracked branch
+    pub unreachable: bool,
+    /// The commit has more than one parent
+    pub is_merge: bool,
+}
+
+/// The file links held by the unreachable commits of a project
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnreachableLinks {
+    pub file_commits: usize,
+    pub file_owners: usize,
+    /// Features that no reachable commit attached to the file
+    pub file_features: usize,
 }
 
 #[derive(Debug)]
@@ -31,20 +72,32 @@ pub struct NewCommit {
     pub sha: String,
     pub parent_sha: Option<Vec<String>>,
     pub description: String,
+    pub body: Option<String>,
+    pub commit_type: Option<String>,
     pub commit_time: NaiveDateTime,
+    pub committer_handle: Option<String>,
+    pub committer_email: Option<String>,
+    pub committer_time: Option<NaiveDateTime>,
+    pub is_merge: bool,
 }
 
 impl NewCommit {
     pub fn save(&self, conn: &Connection) -> Result<Commit, FownerError> {
         let mut stmt = conn.prepare(r#"
-        INSERT INTO commits (owner_id, project_id, sha, parent_sha, description, commit_time, created_at, updated_at)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, strftime('%s', 'now'), strftime('%s', 'now'))
+        INSERT INTO commits (owner_id, project_id, sha, parent_sha, description, commit_time, committer_handle, committer_email, committer_time, body, commit_type, is_merge, created_at, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, strftime('%s', 'now'), strftime('%s', 'now'))
         ON CONFLICT
-            DO UPDATE SET owner_id    = EXCLUDED.owner_id,
-                          parent_sha  = EXCLUDED.parent_sha,
-                          description = EXCLUDED.description,
-                          commit_time = EXCLUDED.commit_time,
-                          updated_at  = strftime('%s', 'now');
+            DO UPDATE SET owner_id         = EXCLUDED.owner_id,
+                          parent_sha       = EXCLUDED.parent_sha,
+                          description      = EXCLUDED.description,
+                          commit_time      = EXCLUDED.commit_time,
+                          committer_handle = EXCLUDED.committer_handle,
+                          committer_email  = EXCLUDED.committer_email,
+                          committer_time   = EXCLUDED.committer_time,
+                          body             = EXCLUDED.body,
+                          commit_type      = EXCLUDED.commit_type,
+                          is_merge         = EXCLUDED.is_merge,
+                          updated_at       = strftime('%s', 'now');
         "#)?;
 
         let _res = stmt.execute(params![
@@ -53,7 +106,13 @@ impl NewCommit {
             self.sha,
             self.parent_sha.clone().map(|s| s.join(",")),
             self.description,
-            self.commit_time.timestamp()
+            self.commit_time.timestamp(),
+            self.committer_handle,
+            self.committer_email,
+            self.committer_time.map(|t| t.timestamp()),
+            self.body,
+            self.commit_type,
+            self.is_merge
         ])?;
         let id = conn.last_insert_rowid();
         Commit::load(id, conn)
@@ -63,7 +122,7 @@ impl NewCommit {
 impl Commit {
     fn sort_by_field(field: Option<String>) -> String {
         if let Some(field) = field {
-            if vec!["description", "commit_time"].contains(&field.as_str()) {
+            if ["description", "commit_time"].contains(&field.as_str()) {
                 return field;
             }
         }
@@ -96,6 +155,21 @@ impl Commit {
 
                    c.created_at,
                    c.updated_at,
+                   c.committer_handle,
+                   c.committer_email,
+                   c.committer_time,
+                   (SELECT GROUP_CONCAT(coalesce(cpo.handle, co.handle), ',')
+                    FROM commit_co_authors cca
+                        INNER JOIN owners co ON cca.owner_id = co.id
+                        LEFT JOIN owners cpo ON cpo.id = co.primary_owner_id
+                    WHERE cca.commit_id = c.id)                    AS co_author_handles,
+                   c.body,
+                   c.commit_type,
+                   (SELECT GROUP_CONCAT(cr.ref_name, ',')
+                    FROM commit_refs cr
+                    WHERE cr.commit_id = c.id)                     AS refs,
+                   c.unreachable,
+                   c.is_merge,
                    -- This must always be the last column
                    COUNT(*) OVER () AS total_count
             FROM commits c
@@ -125,6 +199,7 @@ impl Commit {
         let mut stmt = conn.prepare(&Commit::sql("WHERE c.id = ?1;".to_string(), None, None))?;
         extract_first!(params![id], stmt)
     }
+    #[allow(dead_code)]
     pub fn fetch_latest_for_project(
         project_id: u32,
         conn: &Connection,
@@ -137,9 +212,126 @@ impl Commit {
         extract_first!(params![project_id], stmt)
     }
 
+    /// Returns the sha of every commit stored for the project
+    pub fn shas_for_project(
+        project_id: u32,
+        conn: &Connection,
+    ) -> Result<HashSet<String>, FownerError> {
+        let mut stmt = conn.prepare("SELECT sha FROM commits WHERE project_id = ?1")?;
+        let rows = stmt.query_map(params![project_id], |r| r.get(0))?;
+        let mut shas = HashSet::new();
+        for row in rows {
+            shas.insert(row?);
+        }
+        Ok(shas)
+    }
+
+    /// The id of the newest stored commit, `0` when the project has none
+    pub fn latest_id(project_id: u32, conn: &Connection) -> Result<i64, FownerError> {
+        let mut stmt =
+            conn.prepare("SELECT coalesce(max(id), 0) FROM commits WHERE project_id = ?1")?;
+        Ok(stmt.query_row(params![project_id], |r| r.get(0))?)
+    }
+
+    pub fn exists(project_id: u32, sha: &str, conn: &Connection) -> Result<bool, FownerError> {
+        let mut stmt = conn
+            .prepare("SELECT EXISTS(SELECT 1 FROM commits WHERE project_id = ?1 AND sha = ?2)")?;
+        Ok(stmt.query_row(params![project_id, sha], |r| r.get(0))?)
+    }
+
+    /// Flags every stored commit that is not in `reachable` and clears the flag on the rest,
+    /// returns the shas of the unreachable commits
+    pub fn flag_unreachable(
+        project_id: u32,
+        reachable: &HashSet<String>,
+        conn: &Connection,
+    ) -> Result<Vec<String>, FownerError> {
+        let unreachable: Vec<String> = Self::shas_for_project(project_id, conn)?
+            .into_iter()
+            .filter(|sha| !reachable.contains(sha))
+            .collect();
+        conn.prepare(
+            "UPDATE commits SET unreachable = 0 WHERE project_id = ?1 AND unreachable = 1",
+        )?
+        .execute(params![project_id])?;
+        let mut stmt = conn.prepare(
+            "UPDATE commits SET unreachable = 1, updated_at = strftime('%s', 'now') WHERE project_id = ?1 AND sha = ?2",
+        )?;
+        for sha in &unreachable {
+            stmt.execute(params![project_id, sha])?;
+        }
+        Ok(unreachable)
+    }
+
+    /// Paths of the files touched by the project's unreachable commits
+    pub fn unreachable_paths(
+        project_id: u32,
+        conn: &Connection,
+    ) -> Result<HashSet<String>, FownerError> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT DISTINCT f.path
+            FROM commits c
+                INNER JOIN file_commits fc ON fc.commit_id = c.id
+                INNER JOIN files f ON f.id = fc.file_id
+            WHERE c.project_id = ?1 AND c.unreachable = 1
+            "#,
+        )?;
+        let rows = stmt.query_map(params![project_id], |r| r.get(0))?;
+        let mut paths = HashSet::new();
+        for row in rows {
+            paths.insert(row?);
+        }
+        Ok(paths)
+    }
+
+    /// Counts the links held by the project's unreachable commits
+    pub fn unreachable_links(
+        project_id: u32,
+        conn: &Connection,
+    ) -> Result<UnreachableLinks, FownerError> {
+        let count = |table: &str, where_clause: &str| -> Result<usize, FownerError> {
+            let sql = format!("SELECT COUNT(*) FROM {} WHERE {}", table, where_clause);
+            let count: i64 = conn
+                .prepare(&sql)?
+                .query_row(params![project_id], |r| r.get(0))?;
+            Ok(count as usize)
+        };
+        Ok(UnreachableLinks {
+            file_commits: count("file_commits", UNREACHABLE_FILE_COMMITS)?,
+            file_owners: count("file_owners", UNREACHABLE_FILE_OWNERS)?,
+            file_features: count("file_features", UNREACHABLE_FILE_FEATURES)?,
+        })
+    }
+
+    /// Deletes the project's unreachable commits along with the links they created
+    pub fn prune_unreachable(
+        project_id: u32,
+        conn: &Connection,
+    ) -> Result<UnreachableLinks, FownerError> {
+        let delete = |table: &str, where_clause: &str| -> Result<usize, FownerError> {
+            let sql = format!("DELETE FROM {} WHERE {}", table, where_clause);
+            Ok(conn.prepare(&sql)?.execute(params![project_id])?)
+        };
+        // Features go first as they are found through the file_commits of the commits
+        let file_features = delete("file_features", UNREACHABLE_FILE_FEATURES)?;
+        let file_owners = delete("file_owners", UNREACHABLE_FILE_OWNERS)?;
+        let file_commits = delete("file_commits", UNREACHABLE_FILE_COMMITS)?;
+        // Co-authors, refs and commit features cascade
+        delete("commits", "project_id = ?1 AND unreachable = 1")?;
+        Ok(UnreachableLinks {
+            file_commits,
+            file_owners,
+            file_features,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn search(
         project_id: u32,
         query: Option<String>,
+        commit_type: Option<String>,
+        branch: Option<String>,
         limit: u32,
         offset: u32,
         sort: Option<String>,
@@ -148,7 +340,7 @@ impl Commit {
     ) -> Result<(i64, Vec<Self>), FownerError> {
         let sort_field = Self::sort_by_field(sort);
         let mut stmt = conn.prepare(&Commit::sql(
-            "WHERE c.project_id = ?1 AND (?2 IS NULL OR c.sha LIKE ?2)".to_string(),
+            "WHERE c.project_id = ?1 AND (?2 IS NULL OR c.sha LIKE ?2) AND (?5 IS NULL OR c.commit_type = LOWER(?5)) AND (?6 IS NULL OR EXISTS (SELECT 1 FROM commit_refs cr WHERE cr.commit_id = c.id AND cr.ref_name = ?6))".to_string(),
             Some(format!(
                 "ORDER BY {} {}",
                 sort_field,
@@ -158,7 +350,10 @@ impl Commit {
         ))?;
 
         let query = query.map(|query| format!("%{}%", query));
-        extract_all_and_count!(params![project_id, query, limit, offset], stmt)
+        extract_all_and_count!(
+            params![project_id, query, limit, offset, commit_type, branch],
+            stmt
+        )
     }
 
     #[allow(dead_code)]
@@ -207,6 +402,24 @@ impl<'stmt> From<&Row<'stmt>> for Commit {
             feature_names,
             created_at: NaiveDateTime::from_timestamp(row.get(9).unwrap(), 0),
             updated_at: NaiveDateTime::from_timestamp(row.get(10).unwrap(), 0),
+            committer_handle: row.get(11).unwrap(),
+            committer_email: row.get(12).unwrap(),
+            committer_time: row
+                .get(13)
+                .map(|t: Option<i64>| t.map(|t| NaiveDateTime::from_timestamp(t, 0)))
+                .unwrap_or_default(),
+            co_author_handles: row
+                .get(14)
+                .map(|s: String| s.split(',').map(|s| s.to_string()).collect())
+                .unwrap_or_default(),
+            body: row.get(15).unwrap(),
+            commit_type: row.get(16).unwrap(),
+            refs: row
+                .get(17)
+                .map(|s: String| s.split(',').map(|s| s.to_string()).collect())
+                .unwrap_or_default(),
+            unreachable: row.get(18).unwrap(),
+            is_merge: row.get(19).unwrap(),
         }
     }
 }
@@ -237,6 +450,7 @@ mod test {
             handle: "Krakaw".to_string(),
             name: None,
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -248,6 +462,12 @@ mod test {
             parent_sha: None,
             description: "Initial Commit".to_string(),
             commit_time: c1_commit_time,
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -258,6 +478,12 @@ mod test {
             parent_sha: None,
             description: "Another commit".to_string(),
             commit_time: c1_commit_time,
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -276,6 +502,7 @@ mod test {
             handle: "Krakaw".to_string(),
             name: None,
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -287,6 +514,12 @@ mod test {
             parent_sha: None,
             description: "Initial Commit".to_string(),
             commit_time: c1_commit_time,
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -304,6 +537,12 @@ mod test {
             parent_sha: Some(vec!["deadbeef".to_string()]),
             description: "Feature Commit".to_string(),
             commit_time: Utc::now().naive_utc(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -319,6 +558,12 @@ mod test {
             parent_sha: Some(vec!["deadbeefa".to_string()]),
             description: "Feature Commit Override".to_string(),
             commit_time: Utc::now().naive_utc(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -346,6 +591,7 @@ mod test {
             handle: "Krakaw".to_string(),
             name: None,
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -357,6 +603,12 @@ mod test {
             parent_sha: None,
             description: "Initial Commit".to_string(),
             commit_time: Utc::now().naive_utc(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -367,6 +619,12 @@ mod test {
             parent_sha: Some(vec!["deadbeef".to_string()]),
             description: "Feature Commit".to_string(),
             commit_time: Utc::now().naive_utc(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -380,6 +638,12 @@ mod test {
                 .naive_utc()
                 .checked_add_signed(Duration::seconds(10))
                 .unwrap(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -409,6 +673,7 @@ mod test {
             handle: "Krakaw".to_string(),
             name: None,
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -428,12 +693,20 @@ mod test {
             parent_sha: None,
             description: "Initial Commit".to_string(),
             commit_time: Utc::now().naive_utc(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
         FileCommit {
             file_id: file_1.id,
             commit_id: commit_1.id,
+            additions: 0,
+            deletions: 0,
         }
         .save(&conn)
         .unwrap();
@@ -448,6 +721,12 @@ mod test {
                 .naive_utc()
                 .checked_add_signed(Duration::seconds(5))
                 .unwrap(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
@@ -461,12 +740,18 @@ mod test {
                 .naive_utc()
                 .checked_add_signed(Duration::seconds(10))
                 .unwrap(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
         .save(&conn)
         .unwrap();
 
         let (total, commits_desc) =
-            Commit::search(project.id, None, 10, 0, None, None, &conn).unwrap();
+            Commit::search(project.id, None, None, None, 10, 0, None, None, &conn).unwrap();
         assert_eq!(total, 3);
         assert_eq!(commits_desc.len(), 3);
         assert_eq!(
@@ -477,6 +762,8 @@ mod test {
         let (total, commits) = Commit::search(
             project.id,
             None,
+            None,
+            None,
             10,
             0,
             Some("commit_time".to_string()),
@@ -490,22 +777,35 @@ mod test {
             commits,
             vec![commit_1.clone(), commit_2.clone(), commit_3.clone()]
         );
-        let (total, commits) = Commit::search(project.id, None, 2, 0, None, None, &conn).unwrap();
+        let (total, commits) =
+            Commit::search(project.id, None, None, None, 2, 0, None, None, &conn).unwrap();
         assert_eq!(total, 3);
         assert_eq!(commits.len(), 2);
         assert_eq!(commits, vec![commit_3.clone(), commit_2.clone()]);
-        let (total, commits) =
-            Commit::search(project.id, None, 2, 2, None, Some(SortDir::Asc), &conn).unwrap();
+        let (total, commits) = Commit::search(
+            project.id,
+            None,
+            None,
+            None,
+            2,
+            2,
+            None,
+            Some(SortDir::Asc),
+            &conn,
+        )
+        .unwrap();
         assert_eq!(total, 3);
         assert_eq!(commits.len(), 1);
         assert_eq!(commits, vec![commit_3.clone()]);
         assert_eq!(
-            commits.get(0).unwrap().parent_sha,
+            commits.first().unwrap().parent_sha,
             Some(vec!["deadbeef2".to_string(), "deadbeef".to_string()])
         );
         let (total, commits) = Commit::search(
             project.id,
             Some("dfe".to_string()),
+            None,
+            None,
             50,
             0,
             None,
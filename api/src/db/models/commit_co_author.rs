@@ -0,0 +1,23 @@
+use r2d2_sqlite::rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CommitCoAuthor {
+    pub commit_id: u32,
+    pub owner_id: u32,
+}
+
+impl CommitCoAuthor {
+    pub fn save(&self, conn: &Connection) -> Result<Self, FownerError> {
+        let sql = r#"
+        INSERT OR IGNORE INTO commit_co_authors (commit_id, owner_id, created_at, updated_at)
+        VALUES (?1, ?2, strftime('%s', 'now'), strftime('%s', 'now'));
+        "#;
+        let mut stmt = conn.prepare(sql)?;
+        let _res = stmt.execute(params![self.commit_id, self.owner_id])?;
+        Ok(self.clone())
+    }
+}
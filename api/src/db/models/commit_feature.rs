@@ -0,0 +1,24 @@
+use r2d2_sqlite::rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+/// Records the features a commit attached to the files it touched
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CommitFeature {
+    pub commit_id: u32,
+    pub feature_id: u32,
+}
+
+impl CommitFeature {
+    pub fn save(&self, conn: &Connection) -> Result<Self, FownerError> {
+        let sql = r#"
+        INSERT OR IGNORE INTO commit_features (commit_id, feature_id, created_at, updated_at)
+        VALUES (?1, ?2, strftime('%s', 'now'), strftime('%s', 'now'));
+        "#;
+        let mut stmt = conn.prepare(sql)?;
+        let _res = stmt.execute(params![self.commit_id, self.feature_id])?;
+        Ok(self.clone())
+    }
+}
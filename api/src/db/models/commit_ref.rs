@@ -0,0 +1,48 @@
+use r2d2_sqlite::rusqlite::params;
+
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+/// Records that a commit is reachable from a branch or other ref
+pub struct CommitRef;
+
+impl CommitRef {
+    /// Links every stored commit in `shas` to `ref_name`, returns the number of new links
+    pub fn save_for_shas(
+        project_id: u32,
+        ref_name: &str,
+        shas: &[String],
+        conn: &Connection,
+    ) -> Result<usize, FownerError> {
+        let mut stmt = conn.prepare(
+            r#"
+        INSERT OR IGNORE INTO commit_refs (commit_id, ref_name, created_at, updated_at)
+        SELECT c.id, ?2, strftime('%s', 'now'), strftime('%s', 'now')
+        FROM commits c
+        WHERE c.project_id = ?1
+          AND c.sha = ?3
+        "#,
+        )?;
+        let mut inserted = 0;
+        for sha in shas {
+            inserted += stmt.execute(params![project_id, ref_name, sha])?;
+        }
+        Ok(inserted)
+    }
+
+    /// Unlinks every commit of the project from `ref_name`
+    pub fn remove_for_ref(
+        project_id: u32,
+        ref_name: &str,
+        conn: &Connection,
+    ) -> Result<usize, FownerError> {
+        let mut stmt = conn.prepare(
+            r#"
+        DELETE FROM commit_refs
+        WHERE ref_name = ?2
+          AND commit_id IN (SELECT id FROM commits WHERE project_id = ?1)
+        "#,
+        )?;
+        Ok(stmt.execute(params![project_id, ref_name])?)
+    }
+}
@@ -67,6 +67,30 @@ impl Feature {
         let mut stmt = conn.prepare(&Feature::sql(Some("WHERE project_id = ?1;".to_string())))?;
         extract_all!(params![project_id], stmt)
     }
+
+    /// Features of the commits stored after `commit_id` and of the files those commits touched
+    pub fn load_by_commits_after(
+        project_id: u32,
+        commit_id: i64,
+        conn: &Connection,
+    ) -> Result<Vec<Feature>, FownerError> {
+        let mut stmt = conn.prepare(&Feature::sql(Some(
+            r#"WHERE project_id = ?1
+                AND id IN (SELECT cf.feature_id
+                           FROM commit_features cf
+                                    INNER JOIN commits c ON c.id = cf.commit_id
+                           WHERE c.project_id = ?1 AND c.id > ?2
+                           UNION
+                           SELECT ff.feature_id
+                           FROM file_features ff
+                                    INNER JOIN file_commits fc ON fc.file_id = ff.file_id
+                                    INNER JOIN commits c ON c.id = fc.commit_id
+                           WHERE c.project_id = ?1 AND c.id > ?2)
+            ORDER BY name"#
+                .to_string(),
+        )))?;
+        extract_all!(params![project_id, commit_id], stmt)
+    }
 }
 
 impl<'stmt> From<&Row<'stmt>> for Feature {
@@ -7,9 +7,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::db::models::feature::NewFeature;
 use crate::db::models::file_feature::{FileFeature, NewFileFeature};
+use crate::db::models::file_rename::NewFileRename;
 use crate::db::models::{extract_all, extract_first};
 use crate::db::Connection;
 use crate::errors::FownerError;
+use crate::git::path_filter::PathFilter;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct File {
@@ -22,10 +24,30 @@ pub struct File {
     pub feature_names: Vec<String>,
     pub commit_shas: Vec<String>,
     pub owners: Vec<String>,
+    pub previous_paths: Vec<String>,
+    pub additions: u32,
+    pub deletions: u32,
+    pub current_owners: Vec<String>,
+    /// Matches the project's path globs since it was ingested, hidden from searches and stats
+    pub excluded: bool,
 }
 
 impl File {
     pub fn sql(where_clause: Option<String>, limit_clause: Option<String>) -> String {
+        Self::sql_with_bots(where_clause, limit_clause, false)
+    }
+
+    /// Bots are left out of `owners` and `current_owners` unless `include_bots` is set
+    pub fn sql_with_bots(
+        where_clause: Option<String>,
+        limit_clause: Option<String>,
+        include_bots: bool,
+    ) -> String {
+        let bots_clause = if include_bots {
+            ""
+        } else {
+            "AND o.is_bot = 0 AND coalesce(po.is_bot, 0) = 0"
+        };
         format!(
             r#"
             SELECT f.id,
@@ -50,20 +72,41 @@ impl File {
                           FROM file_owners fo
                                    INNER JOIN owners o on fo.owner_id = o.id
                                    LEFT JOIN owners po ON po.id = o.primary_owner_id
-                          WHERE fo.file_id = f.id
+                          WHERE fo.file_id = f.id {bots_clause}
                           GROUP BY coalesce(coalesce(po.name, po.handle),coalesce(o.name, o.handle))
                           ORDER BY fo.created_at DESC
                           )
-                    ) AS owners
+                    ) AS owners,
+                   (SELECT GROUP_CONCAT(fr.previous_path, ',')
+                    FROM file_renames fr
+                    WHERE fr.file_id = f.id)                       AS previous_paths,
+                   (SELECT COALESCE(SUM(fc.additions), 0)
+                    FROM file_commits fc
+                    WHERE fc.file_id = f.id)                       AS additions,
+                   (SELECT COALESCE(SUM(fc.deletions), 0)
+                    FROM file_commits fc
+                    WHERE fc.file_id = f.id)                       AS deletions,
+                 (SELECT GROUP_CONCAT(handle, ',')
+                    FROM (SELECT coalesce(coalesce(po.name, po.handle),coalesce(o.name, o.handle)) AS handle
+                          FROM file_current_owners fco
+                                   INNER JOIN owners o on fco.owner_id = o.id
+                                   LEFT JOIN owners po ON po.id = o.primary_owner_id
+                          WHERE fco.file_id = f.id {bots_clause}
+                          GROUP BY coalesce(coalesce(po.name, po.handle),coalesce(o.name, o.handle))
+                          ORDER BY SUM(fco.line_count) DESC
+                          )
+                    ) AS current_owners,
+                   f.excluded
 
             FROM files f
             WHERE f.project_id = ?1
-            {}
+            {where_clause}
             GROUP BY f.id
-            {};
+            {limit_clause};
         "#,
-            where_clause.unwrap_or_default(),
-            limit_clause.unwrap_or_default()
+            bots_clause = bots_clause,
+            where_clause = where_clause.unwrap_or_default(),
+            limit_clause = limit_clause.unwrap_or_default()
         )
     }
     pub fn all(project_id: u32, conn: &Connection) -> Result<Vec<File>, FownerError> {
@@ -86,16 +129,57 @@ impl File {
     pub fn search(
         project_id: u32,
         query: String,
+        branch: Option<String>,
+        include_bots: bool,
         limit: u32,
         offset: u32,
         conn: &Connection,
     ) -> Result<Vec<File>, FownerError> {
-        let mut stmt = conn.prepare(&File::sql(
-            Some("AND path LIKE ?2".to_string()),
+        let mut stmt = conn.prepare(&File::sql_with_bots(
+            Some(
+                r#"AND path LIKE ?2
+            AND f.excluded = 0
+            AND (?5 IS NULL OR EXISTS (SELECT 1
+                                      FROM file_commits fc
+                                               INNER JOIN commit_refs cr ON cr.commit_id = fc.commit_id
+                                      WHERE fc.file_id = f.id
+                                        AND cr.ref_name = ?5))"#
+                    .to_string(),
+            ),
             Some("LIMIT ?3 OFFSET ?4".to_string()),
+            include_bots,
         ))?;
         let query = format!("%{}%", query);
-        extract_all!(params![project_id, query, limit, offset], stmt)
+        extract_all!(params![project_id, query, limit, offset, branch], stmt)
+    }
+
+    /// Flags the files of the project that `path_filter` no longer includes, and clears the
+    /// flag from those it does, returning how many files are excluded
+    pub fn flag_excluded(
+        project_id: u32,
+        path_filter: &PathFilter,
+        conn: &Connection,
+    ) -> Result<usize, FownerError> {
+        let mut stmt = conn.prepare("SELECT id, path FROM files WHERE project_id = ?1")?;
+        let files = stmt
+            .query_map(params![project_id], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<(u32, String)>, _>>()?;
+        let mut stmt = conn.prepare("UPDATE files SET excluded = ?1 WHERE id = ?2")?;
+        let mut excluded = 0;
+        for (id, path) in files {
+            let is_excluded = !path_filter.is_included(&path);
+            stmt.execute(params![is_excluded, id])?;
+            excluded += is_excluded as usize;
+        }
+        Ok(excluded)
+    }
+
+    /// Deletes the excluded files of the project along with their owners, commits and features
+    pub fn purge_excluded(project_id: u32, conn: &Connection) -> Result<usize, FownerError> {
+        let mut stmt = conn.prepare("DELETE FROM files WHERE project_id = ?1 AND excluded = 1")?;
+        Ok(stmt.execute(params![project_id])?)
     }
 
     pub fn add_feature(
@@ -113,6 +197,54 @@ impl File {
         .save(conn)
     }
 
+    /// Moves the file to `path`, keeping its features, owners and commits.
+    /// If a file already exists at `path` the two are merged into the existing file.
+    pub fn rename(
+        &self,
+        path: String,
+        sha: String,
+        conn: &Connection,
+    ) -> Result<File, FownerError> {
+        let file = match File::load_by_path(self.project_id, path.clone(), conn) {
+            Ok(existing) if existing.id != self.id => {
+                // Move everything across to the existing file, dropping any duplicate links
+                for sql in [
+                    "UPDATE file_owners SET file_id = ?1 WHERE file_id = ?2",
+                    "UPDATE file_renames SET file_id = ?1 WHERE file_id = ?2",
+                    "UPDATE OR IGNORE file_features SET file_id = ?1 WHERE file_id = ?2",
+                    "UPDATE OR IGNORE file_commits SET file_id = ?1 WHERE file_id = ?2",
+                ] {
+                    let mut stmt = conn.prepare(sql)?;
+                    stmt.execute(params![existing.id, self.id])?;
+                }
+                for sql in [
+                    "DELETE FROM file_features WHERE file_id = ?1",
+                    "DELETE FROM file_commits WHERE file_id = ?1",
+                    "DELETE FROM files WHERE id = ?1",
+                ] {
+                    let mut stmt = conn.prepare(sql)?;
+                    stmt.execute(params![self.id])?;
+                }
+                existing
+            }
+            _ => {
+                let mut stmt = conn.prepare(
+                    "UPDATE files SET path = ?1, updated_at = strftime('%s','now') WHERE id = ?2",
+                )?;
+                stmt.execute(params![path, self.id])?;
+                File::load(self.project_id, self.id, conn)?
+            }
+        };
+        NewFileRename {
+            file_id: file.id,
+            previous_path: self.path.clone(),
+            path,
+            sha,
+        }
+        .save(conn)?;
+        File::load(file.project_id, file.id, conn)
+    }
+
     pub fn remove_features(&self, conn: &Connection) -> Result<usize, FownerError> {
         let sql = "UPDATE files SET no_features = 1 WHERE id = ?1;";
         let mut stmt = conn.prepare(sql)?;
@@ -234,6 +366,16 @@ impl<'stmt> From<&Row<'stmt>> for File {
             .map(|s: String| s.split(',').map(|s| s.to_string()).collect())
             .unwrap_or_default();
 
+        let previous_paths: Vec<String> = row
+            .get(9)
+            .map(|s: String| s.split(',').map(|s| s.to_string()).collect())
+            .unwrap_or_default();
+
+        let current_owners: Vec<String> = row
+            .get(12)
+            .map(|s: String| s.split(',').map(|s| s.to_string()).collect())
+            .unwrap_or_default();
+
         Self {
             id: row.get(0).unwrap(),
             project_id: row.get(1).unwrap(),
@@ -244,6 +386,11 @@ impl<'stmt> From<&Row<'stmt>> for File {
             feature_names,
             commit_shas,
             owners,
+            previous_paths,
+            additions: row.get(10).unwrap(),
+            deletions: row.get(11).unwrap(),
+            current_owners,
+            excluded: row.get(13).unwrap(),
         }
     }
 }
@@ -275,7 +422,7 @@ mod test {
             path: "src/main.rs".to_string(),
             no_features: false,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
         assert_eq!(file.id, 1);
         assert_eq!(file.project_id, project.id);
@@ -290,7 +437,7 @@ mod test {
             path: "src/main.rs".to_string(),
             no_features: false,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
         assert_eq!(file.id, 1);
     }
@@ -307,17 +454,17 @@ mod test {
             path: "src/main.rs".to_string(),
             no_features: true,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
         let feature = NewFeature {
             project_id: project.id,
             name: "Test".to_string(),
             description: None,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
 
-        let file_feature_err = file.add_feature(feature.id, &conn);
+        let file_feature_err = file.add_feature(feature.id, conn);
         assert!(file_feature_err.is_err());
     }
 
@@ -332,8 +479,9 @@ mod test {
             handle: "Krakaw".to_string(),
             name: None,
             primary_owner_id: None,
+            email: None,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
         let owner_id = owner.id;
         let commit_1 = NewCommit {
@@ -343,15 +491,21 @@ mod test {
             parent_sha: None,
             description: "Initial Commit".to_string(),
             commit_time: Utc::now().naive_utc(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
         let feature = NewFeature {
             project_id: project.id,
             name: "Test".to_string(),
             description: None,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
 
         let file = NewFile {
@@ -359,33 +513,38 @@ mod test {
             path: "src/main.rs".to_string(),
             no_features: false,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
 
-        file.add_feature(feature.id, &conn).unwrap();
+        file.add_feature(feature.id, conn).unwrap();
 
         FileCommit {
             file_id: file.id,
             commit_id: commit_1.id,
+            additions: 10,
+            deletions: 2,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
         NewFileOwner {
             file_id: file.id,
             owner_id: owner.id,
             action_date: Utc::now().naive_utc(),
             sha: commit_1.sha,
+            co_author: false,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
 
-        let db_file = File::load_by_path(project.id, "src/main.rs".to_string(), &conn).unwrap();
+        let db_file = File::load_by_path(project.id, "src/main.rs".to_string(), conn).unwrap();
         assert_eq!(db_file.id, 1);
         assert_eq!(db_file.project_id, 1);
         assert_eq!(db_file.path, "src/main.rs".to_string());
         assert_eq!(db_file.feature_names, vec!["Test".to_string()]);
         assert_eq!(db_file.commit_shas, vec!["deadbeef".to_string()]);
         assert_eq!(db_file.owners, vec!["Krakaw".to_string()]);
+        assert_eq!(db_file.additions, 10);
+        assert_eq!(db_file.deletions, 2);
 
         let commit_2 = NewCommit {
             owner_id,
@@ -394,22 +553,31 @@ mod test {
             parent_sha: Some(vec!["deadbeef".to_string()]),
             description: "Feature Commit".to_string(),
             commit_time: Utc::now().naive_utc(),
+            committer_handle: None,
+            committer_email: None,
+            committer_time: None,
+            body: None,
+            commit_type: None,
+            is_merge: false,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
 
         FileCommit {
             file_id: file.id,
             commit_id: commit_2.id,
+            additions: 5,
+            deletions: 3,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
         let owner = NewOwner {
             handle: "NewOwner".to_string(),
             name: None,
             primary_owner_id: None,
+            email: None,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
 
         let feature = NewFeature {
@@ -417,20 +585,21 @@ mod test {
             name: "New Feature".to_string(),
             description: None,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
-        file.add_feature(feature.id, &conn).unwrap();
+        file.add_feature(feature.id, conn).unwrap();
 
         NewFileOwner {
             file_id: file.id,
             owner_id: owner.id,
             action_date: Utc::now().naive_utc(),
             sha: commit_2.sha,
+            co_author: false,
         }
-        .save(&conn)
+        .save(conn)
         .unwrap();
 
-        let db_file = File::load_by_path(project.id, "src/main.rs".to_string(), &conn).unwrap();
+        let db_file = File::load_by_path(project.id, "src/main.rs".to_string(), conn).unwrap();
         assert_eq!(
             db_file.feature_names,
             vec!["Test".to_string(), "New Feature".to_string()]
@@ -443,5 +612,59 @@ mod test {
             db_file.owners,
             vec!["NewOwner".to_string(), "Krakaw".to_string()]
         );
+        assert_eq!(db_file.additions, 15);
+        assert_eq!(db_file.deletions, 5);
+    }
+
+    #[test]
+    fn rename() {
+        let handler = TestHandler::init();
+        let db = &handler.db;
+        let conn = &Connection::try_from(db).unwrap();
+        let tmp_dir = &handler.tmp_dir;
+        let project = ProjectBuilder::with_path(tmp_dir).build(conn).unwrap();
+        let feature = NewFeature {
+            project_id: project.id,
+            name: "Test".to_string(),
+            description: None,
+        }
+        .save(conn)
+        .unwrap();
+        let file = NewFile {
+            project_id: project.id,
+            path: "src/repo.rs".to_string(),
+            no_features: false,
+        }
+        .save(conn)
+        .unwrap();
+        file.add_feature(feature.id, conn).unwrap();
+
+        let renamed = file
+            .rename("src/git/repo.rs".to_string(), "deadbeef".to_string(), conn)
+            .unwrap();
+        assert_eq!(renamed.id, file.id);
+        assert_eq!(renamed.path, "src/git/repo.rs".to_string());
+        assert_eq!(renamed.feature_names, vec!["Test".to_string()]);
+        assert_eq!(renamed.previous_paths, vec!["src/repo.rs".to_string()]);
+        assert!(File::load_by_path(project.id, "src/repo.rs".to_string(), conn).is_err());
+
+        // Renaming onto an existing file merges the two
+        let existing = NewFile {
+            project_id: project.id,
+            path: "src/lib.rs".to_string(),
+            no_features: false,
+        }
+        .save(conn)
+        .unwrap();
+        let merged = renamed
+            .rename("src/lib.rs".to_string(), "beefdead".to_string(), conn)
+            .unwrap();
+        assert_eq!(merged.id, existing.id);
+        assert_eq!(merged.feature_names, vec!["Test".to_string()]);
+        assert_eq!(
+            merged.previous_paths,
+            vec!["src/repo.rs".to_string(), "src/git/repo.rs".to_string()]
+        );
+        assert!(File::load(project.id, file.id, conn).is_err());
     }
 }
@@ -1,24 +1,59 @@
-use r2d2_sqlite::rusqlite::params;
+use r2d2_sqlite::rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
 
 use crate::db::models::commit::Commit;
+use crate::db::models::extract_all;
 use crate::db::Connection;
 use crate::errors::FownerError;
 use crate::File;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct FileCommit {
     pub file_id: u32,
     pub commit_id: u32,
+    pub additions: u32,
+    pub deletions: u32,
 }
 
 impl FileCommit {
     pub fn save(&self, conn: &Connection) -> Result<Self, FownerError> {
-        let sql = "INSERT INTO file_commits (file_id, commit_id) VALUES(?1, ?2)";
+        let sql = r#"
+        INSERT INTO file_commits (file_id, commit_id, additions, deletions) VALUES(?1, ?2, ?3, ?4)
+        ON CONFLICT
+            DO UPDATE SET additions = EXCLUDED.additions,
+                          deletions = EXCLUDED.deletions;
+        "#;
         let mut stmt = conn.prepare(sql)?;
-        let _res = stmt.execute(params![self.file_id, self.commit_id,])?;
+        let _res = stmt.execute(params![
+            self.file_id,
+            self.commit_id,
+            self.additions,
+            self.deletions
+        ])?;
         Ok(self.clone())
     }
 
+    /// Optionally limited to the commits contained in `branch`
+    pub fn load_by_file(
+        file_id: u32,
+        branch: Option<String>,
+        conn: &Connection,
+    ) -> Result<Vec<FileCommit>, FownerError> {
+        let sql = r#"
+        SELECT fc.file_id, fc.commit_id, fc.additions, fc.deletions
+        FROM file_commits fc
+                 INNER JOIN commits c ON c.id = fc.commit_id
+        WHERE fc.file_id = ?1
+          AND (?2 IS NULL OR EXISTS (SELECT 1
+                                     FROM commit_refs cr
+                                     WHERE cr.commit_id = c.id
+                                       AND cr.ref_name = ?2))
+        ORDER BY c.commit_time DESC;
+        "#;
+        let mut stmt = conn.prepare(sql)?;
+        extract_all!(params![file_id, branch], stmt)
+    }
+
     pub fn fetch_between(
         project_id: u32,
         from_commit: Commit,
@@ -61,3 +96,14 @@ impl FileCommit {
         Ok(files)
     }
 }
+
+impl<'stmt> From<&Row<'stmt>> for FileCommit {
+    fn from(row: &Row) -> Self {
+        Self {
+            file_id: row.get(0).unwrap(),
+            commit_id: row.get(1).unwrap(),
+            additions: row.get(2).unwrap(),
+            deletions: row.get(3).unwrap(),
+        }
+    }
+}
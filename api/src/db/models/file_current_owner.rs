@@ -0,0 +1,208 @@
+use r2d2_sqlite::rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::extract_all;
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+/// The share of a file's surviving lines at HEAD that belong to an owner
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileCurrentOwner {
+    pub file_id: u32,
+    pub owner_id: u32,
+    pub owner_handle: String,
+    pub line_count: u32,
+    pub share: f64,
+    pub sha: String,
+}
+
+pub struct NewFileCurrentOwner {
+    pub owner_id: u32,
+    pub line_count: u32,
+}
+
+impl FileCurrentOwner {
+    /// Loads the current owners of a file, merging aliases into their primary owner.
+    ///
+    /// Bots are left out, and out of the shares, unless `include_bots` is set.
+    pub fn load_by_file(
+        file_id: u32,
+        include_bots: bool,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, FownerError> {
+        let sql = r#"
+        SELECT fco.file_id,
+               COALESCE(po.id, o.id)                                           AS owner_id,
+               COALESCE(COALESCE(po.name, po.handle), COALESCE(o.name, o.handle)) AS owner_handle,
+               SUM(fco.line_count)                                             AS line_count,
+               CAST(SUM(fco.line_count) AS REAL) /
+               (SELECT SUM(bfco.line_count)
+                FROM file_current_owners bfco
+                         INNER JOIN owners bo ON bo.id = bfco.owner_id
+                         LEFT JOIN owners bpo ON bpo.id = bo.primary_owner_id
+                WHERE bfco.file_id = ?1
+                  AND (?2 OR (bo.is_bot = 0 AND coalesce(bpo.is_bot, 0) = 0))) AS share,
+               MAX(fco.sha)                                                    AS sha
+        FROM file_current_owners fco
+                 INNER JOIN owners o ON o.id = fco.owner_id
+                 LEFT JOIN owners po ON po.id = o.primary_owner_id
+        WHERE fco.file_id = ?1
+          AND (?2 OR (o.is_bot = 0 AND coalesce(po.is_bot, 0) = 0))
+        GROUP BY COALESCE(po.id, o.id)
+        ORDER BY line_count DESC;
+        "#;
+        let mut stmt = conn.prepare(sql)?;
+        extract_all!(params![file_id, include_bots], stmt)
+    }
+
+    /// Replaces the current owners of a file with the latest blame at `sha`
+    pub fn replace_for_file(
+        file_id: u32,
+        sha: &str,
+        owners: Vec<NewFileCurrentOwner>,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, FownerError> {
+        let mut stmt = conn.prepare("DELETE FROM file_current_owners WHERE file_id = ?1")?;
+        stmt.execute(params![file_id])?;
+        let mut stmt = conn.prepare(
+            r#"
+        INSERT INTO file_current_owners (file_id, owner_id, line_count, sha, created_at, updated_at)
+        VALUES (?1, ?2, ?3, ?4, strftime('%s','now'), strftime('%s','now'))
+        ON CONFLICT
+            DO UPDATE SET line_count = line_count + EXCLUDED.line_count;
+        "#,
+        )?;
+        for owner in owners {
+            stmt.execute(params![file_id, owner.owner_id, owner.line_count, sha])?;
+        }
+        Self::load_by_file(file_id, true, conn)
+    }
+}
+
+impl<'stmt> From<&Row<'stmt>> for FileCurrentOwner {
+    fn from(row: &Row) -> Self {
+        Self {
+            file_id: row.get(0).unwrap(),
+            owner_id: row.get(1).unwrap(),
+            owner_handle: row.get(2).unwrap(),
+            line_count: row.get(3).unwrap(),
+            share: row.get(4).unwrap(),
+            sha: row.get(5).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::models::file::NewFile;
+    use crate::db::models::file_current_owner::{FileCurrentOwner, NewFileCurrentOwner};
+    use crate::db::models::owner::{NewOwner, Owner, UpdateOwner};
+    use crate::test::builders::project_builder::ProjectBuilder;
+    use crate::test::tests::TestHandler;
+    use crate::{Connection, File};
+
+    #[test]
+    fn replace_for_file() {
+        let handler = TestHandler::init();
+        let db = &handler.db;
+        let conn = &Connection::try_from(db).unwrap();
+        let project = ProjectBuilder::with_path(&handler.tmp_dir)
+            .build(conn)
+            .unwrap();
+        let file = NewFile {
+            project_id: project.id,
+            path: "src/main.rs".to_string(),
+            no_features: false,
+        }
+        .save(conn)
+        .unwrap();
+        let krakaw = NewOwner {
+            handle: "Krakaw".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: None,
+        }
+        .save(conn)
+        .unwrap();
+        let alias = NewOwner {
+            handle: "krakaw-work".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: None,
+        }
+        .save(conn)
+        .unwrap();
+        let other = NewOwner {
+            handle: "Other".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: None,
+        }
+        .save(conn)
+        .unwrap();
+
+        let owners = FileCurrentOwner::replace_for_file(
+            file.id,
+            "deadbeef",
+            vec![
+                NewFileCurrentOwner {
+                    owner_id: krakaw.id,
+                    line_count: 30,
+                },
+                NewFileCurrentOwner {
+                    owner_id: alias.id,
+                    line_count: 30,
+                },
+                NewFileCurrentOwner {
+                    owner_id: other.id,
+                    line_count: 40,
+                },
+            ],
+            conn,
+        )
+        .unwrap();
+        assert_eq!(owners.len(), 3);
+        assert_eq!(owners[0].owner_handle, "Other".to_string());
+        assert_eq!(owners[0].share, 0.4);
+
+        // Aliased owners are merged into their primary owner
+        Owner::load(alias.id, conn)
+            .unwrap()
+            .update(
+                UpdateOwner {
+                    name: None,
+                    primary_owner_id: Some(krakaw.id),
+                    is_bot: None,
+                },
+                conn,
+            )
+            .unwrap();
+        let owners = FileCurrentOwner::load_by_file(file.id, false, conn).unwrap();
+        assert_eq!(owners.len(), 2);
+        assert_eq!(owners[0].owner_id, krakaw.id);
+        assert_eq!(owners[0].line_count, 60);
+        assert_eq!(owners[0].share, 0.6);
+        assert_eq!(owners[1].line_count, 40);
+
+        let db_file = File::load(project.id, file.id, conn).unwrap();
+        assert_eq!(
+            db_file.current_owners,
+            vec!["Krakaw".to_string(), "Other".to_string()]
+        );
+
+        // A new blame replaces the previous one
+        let owners = FileCurrentOwner::replace_for_file(
+            file.id,
+            "beefdead",
+            vec![NewFileCurrentOwner {
+                owner_id: other.id,
+                line_count: 10,
+            }],
+            conn,
+        )
+        .unwrap();
+        assert_eq!(owners.len(), 1);
+        assert_eq!(owners[0].share, 1.0);
+        assert_eq!(owners[0].sha, "beefdead".to_string());
+    }
+}
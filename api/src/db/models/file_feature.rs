@@ -7,6 +7,7 @@ use crate::db::models::feature::Feature;
 use crate::db::Connection;
 use crate::errors::FownerError;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct FileFeature {
     pub file_id: u32,
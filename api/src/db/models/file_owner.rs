@@ -11,6 +11,8 @@ pub struct FileOwner {
     pub owner_id: u32,
     pub action_date: NaiveDateTime,
     pub sha: String,
+    /// The owner was credited through a Co-authored-by trailer rather than as the author
+    pub co_author: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -20,6 +22,7 @@ pub struct NewFileOwner {
     pub owner_id: u32,
     pub action_date: NaiveDateTime,
     pub sha: String,
+    pub co_author: bool,
 }
 
 impl FileOwner {
@@ -29,7 +32,7 @@ impl FileOwner {
         action_date: Option<NaiveDateTime>,
         conn: &Connection,
     ) -> Result<Vec<FileOwner>, FownerError> {
-        let mut stmt = conn.prepare("SELECT file_id, owner_id, action_date, sha, created_at, updated_at FROM file_owners WHERE file_id = ?1 AND (?2 IS NULL OR owner_id = ?2) AND (?3 IS NULL or action_date = ?3) ORDER BY action_date DESC")?;
+        let mut stmt = conn.prepare("SELECT file_id, owner_id, action_date, sha, co_author, created_at, updated_at FROM file_owners WHERE file_id = ?1 AND (?2 IS NULL OR owner_id = ?2) AND (?3 IS NULL or action_date = ?3) ORDER BY action_date DESC")?;
         extract_all!(
             params![file_id, owner_id, action_date.map(|d| d.timestamp())],
             stmt
@@ -44,20 +47,22 @@ impl<'stmt> From<&Row<'stmt>> for FileOwner {
             owner_id: row.get(1).unwrap(),
             action_date: NaiveDateTime::from_timestamp(row.get(2).unwrap(), 0),
             sha: row.get(3).unwrap(),
-            created_at: NaiveDateTime::from_timestamp(row.get(4).unwrap(), 0),
-            updated_at: NaiveDateTime::from_timestamp(row.get(5).unwrap(), 0),
+            co_author: row.get(4).unwrap(),
+            created_at: NaiveDateTime::from_timestamp(row.get(5).unwrap(), 0),
+            updated_at: NaiveDateTime::from_timestamp(row.get(6).unwrap(), 0),
         }
     }
 }
 
 impl NewFileOwner {
     pub fn save(&self, conn: &Connection) -> Result<FileOwner, FownerError> {
-        let mut stmt = conn.prepare("INSERT INTO file_owners (file_id, owner_id, action_date, sha, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, strftime('%s','now'), strftime('%s','now'))")?;
+        let mut stmt = conn.prepare("INSERT INTO file_owners (file_id, owner_id, action_date, sha, co_author, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, strftime('%s','now'), strftime('%s','now'))")?;
         let _res = stmt.execute(params![
             self.file_id,
             self.owner_id,
             self.action_date.timestamp(),
-            self.sha
+            self.sha,
+            self.co_author
         ])?;
         let file_owner = FileOwner::load(
             self.file_id,
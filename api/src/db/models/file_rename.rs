@@ -0,0 +1,57 @@
+use chrono::NaiveDateTime;
+use r2d2_sqlite::rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::extract_all;
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileRename {
+    pub file_id: u32,
+    pub previous_path: String,
+    pub path: String,
+    pub sha: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+pub struct NewFileRename {
+    pub file_id: u32,
+    pub previous_path: String,
+    pub path: String,
+    pub sha: String,
+}
+
+impl FileRename {
+    pub fn load_by_file(file_id: u32, conn: &Connection) -> Result<Vec<FileRename>, FownerError> {
+        let mut stmt = conn.prepare("SELECT file_id, previous_path, path, sha, created_at, updated_at FROM file_renames WHERE file_id = ?1 ORDER BY rowid ASC")?;
+        extract_all!(params![file_id], stmt)
+    }
+}
+
+impl NewFileRename {
+    pub fn save(&self, conn: &Connection) -> Result<usize, FownerError> {
+        let mut stmt = conn.prepare("INSERT INTO file_renames (file_id, previous_path, path, sha, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, strftime('%s','now'), strftime('%s','now'))")?;
+        let result = stmt.execute(params![
+            self.file_id,
+            self.previous_path,
+            self.path,
+            self.sha
+        ])?;
+        Ok(result)
+    }
+}
+
+impl<'stmt> From<&Row<'stmt>> for FileRename {
+    fn from(row: &Row) -> Self {
+        Self {
+            file_id: row.get(0).unwrap(),
+            previous_path: row.get(1).unwrap(),
+            path: row.get(2).unwrap(),
+            sha: row.get(3).unwrap(),
+            created_at: NaiveDateTime::from_timestamp(row.get(4).unwrap(), 0),
+            updated_at: NaiveDateTime::from_timestamp(row.get(5).unwrap(), 0),
+        }
+    }
+}
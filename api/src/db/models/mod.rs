@@ -1,13 +1,20 @@
-pub use crate::errors::FownerError;
-
+pub mod bot_pattern;
 pub mod commit;
+pub mod commit_co_author;
+pub mod commit_feature;
+pub mod commit_ref;
 pub mod feature;
 pub mod file;
 pub mod file_commit;
+pub mod file_current_owner;
 pub mod file_feature;
 pub mod file_owner;
+pub mod file_rename;
 pub mod owner;
+pub mod owner_merge;
 pub mod project;
+pub mod project_ref;
+pub mod tag;
 
 macro_rules! extract_first {
     ($params:expr,$stmt:expr) => {
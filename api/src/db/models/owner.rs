@@ -1,19 +1,27 @@
 use chrono::NaiveDateTime;
 use r2d2_sqlite::rusqlite::{params, Row};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::db::models::bot_pattern::BotPattern;
+use crate::db::models::owner_merge::{NewOwnerMerge, OwnerMergeSource};
 use crate::db::models::{extract_all, extract_first};
 use crate::db::Connection;
 use crate::errors::FownerError;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Owner {
     pub id: u32,
     pub handle: String,
+    pub email: Option<String>,
     pub name: Option<String>,
     pub primary_owner_id: Option<u32>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// Bots are left out of ownership and contribution stats by default
+    pub is_bot: bool,
+    /// Set when `is_bot` was chosen through the API rather than by the bot patterns
+    pub is_bot_manual: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -21,18 +29,22 @@ pub struct NewOwner {
     pub handle: String,
     pub name: Option<String>,
     pub primary_owner_id: Option<u32>,
+    pub email: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct UpdateOwner {
     pub name: Option<String>,
     pub primary_owner_id: Option<u32>,
+    /// Overrides the bot patterns for this owner, left unchanged when `None`
+    #[serde(default)]
+    pub is_bot: Option<bool>,
 }
 
 impl Owner {
     pub fn sql(where_sql: &str) -> String {
         format!(
-            "SELECT id, handle, name, primary_owner_id, created_at, updated_at FROM owners WHERE {}",
+            "SELECT id, handle, email, name, primary_owner_id, created_at, updated_at, is_bot, is_bot_manual FROM owners WHERE {}",
             where_sql
         )
     }
@@ -57,34 +69,168 @@ impl Owner {
         extract_first!(params![handle], stmt)
     }
 
+    pub fn load_by_email(email: String, conn: &Connection) -> Result<Self, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql("LOWER(email) = LOWER(?1)"))?;
+        extract_first!(params![email], stmt)
+    }
+
+    /// Owners who authored, co-authored or currently own lines in the project
+    pub fn load_by_project(project_id: u32, conn: &Connection) -> Result<Vec<Self>, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql(
+            r#"id IN (SELECT owner_id FROM commits WHERE project_id = ?1
+                      UNION
+                      SELECT cca.owner_id
+                      FROM commit_co_authors cca
+                               INNER JOIN commits c ON c.id = cca.commit_id
+                      WHERE c.project_id = ?1
+                      UNION
+                      SELECT fco.owner_id
+                      FROM file_current_owners fco
+                               INNER JOIN files f ON f.id = fco.file_id
+                      WHERE f.project_id = ?1)
+            ORDER BY id"#,
+        ))?;
+        extract_all!(params![project_id], stmt)
+    }
+
+    /// Owners who authored or co-authored the commits stored after `commit_id`
+    pub fn load_by_commits_after(
+        project_id: u32,
+        commit_id: i64,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql(
+            r#"id IN (SELECT owner_id FROM commits WHERE project_id = ?1 AND id > ?2
+                      UNION
+                      SELECT cca.owner_id
+                      FROM commit_co_authors cca
+                               INNER JOIN commits c ON c.id = cca.commit_id
+                      WHERE c.project_id = ?1 AND c.id > ?2)
+            ORDER BY handle"#,
+        ))?;
+        extract_all!(params![project_id, commit_id], stmt)
+    }
+
+    pub fn update_name(self, name: String, conn: &Connection) -> Result<Self, FownerError> {
+        let mut stmt = conn.prepare(
+            "UPDATE owners SET name = ?1, updated_at = strftime('%s','now') WHERE id = ?2",
+        )?;
+        let _res = stmt.execute(params![name, self.id])?;
+        Self::load(self.id, conn)
+    }
+
+    pub fn update_email(self, email: String, conn: &Connection) -> Result<Self, FownerError> {
+        let mut stmt = conn.prepare(
+            "UPDATE owners SET email = ?1, updated_at = strftime('%s','now') WHERE id = ?2",
+        )?;
+        let _res = stmt.execute(params![email, self.id])?;
+        Self::load(self.id, conn)
+    }
+
     pub fn update(
         self,
         update_details: UpdateOwner,
         conn: &Connection,
     ) -> Result<Self, FownerError> {
-        let mut stmt =
-            conn.prepare("UPDATE owners SET name = ?1, primary_owner_id = ?2, updated_at = strftime('%s','now') WHERE id = ?3")?;
+        let mut stmt = conn.prepare(
+            r#"
+            UPDATE owners
+            SET name = ?1,
+                primary_owner_id = ?2,
+                is_bot = coalesce(?3, is_bot),
+                is_bot_manual = (?3 IS NOT NULL OR is_bot_manual),
+                updated_at = strftime('%s','now')
+            WHERE id = ?4"#,
+        )?;
         let _res = stmt.execute(params![
             update_details.name,
             update_details.primary_owner_id,
+            update_details.is_bot,
             self.id
         ])?;
+        if update_details.primary_owner_id != self.primary_owner_id {
+            NewOwnerMerge {
+                owner_id: self.id,
+                primary_owner_id: update_details.primary_owner_id,
+                previous_primary_owner_id: self.primary_owner_id,
+                source: OwnerMergeSource::Manual,
+                project_id: None,
+            }
+            .save(conn)?;
+        }
         Self::load(self.id, conn)
     }
+
+    /// Whether the handle or email matches any of the bot patterns
+    pub fn matches_bot_patterns(&self, patterns: &[Regex]) -> bool {
+        patterns.iter().any(|re| {
+            re.is_match(&self.handle)
+                || self
+                    .email
+                    .as_ref()
+                    .map(|email| re.is_match(email))
+                    .unwrap_or_default()
+        })
+    }
+
+    /// Re-evaluates the bot patterns for every owner not flagged by hand,
+    /// returning how many owners are bots
+    pub fn flag_bots(conn: &Connection) -> Result<usize, FownerError> {
+        let patterns = BotPattern::regexes(conn)?;
+        let mut stmt =
+            conn.prepare("UPDATE owners SET is_bot = ?1 WHERE id = ?2 AND is_bot != ?1")?;
+        let mut bots = 0;
+        for owner in Self::all(conn)? {
+            let is_bot = if owner.is_bot_manual {
+                owner.is_bot
+            } else {
+                let is_bot = owner.matches_bot_patterns(&patterns);
+                stmt.execute(params![is_bot, owner.id])?;
+                is_bot
+            };
+            bots += is_bot as usize;
+        }
+        Ok(bots)
+    }
 }
 
 impl NewOwner {
+    /// Owners are matched on their email, falling back to the handle when there is no email
     pub fn save_or_load(&self, conn: &Connection) -> Result<Owner, FownerError> {
-        if let Ok(owner) = Owner::load_by_handle(self.handle.clone(), conn) {
+        if let Some(email) = &self.email {
+            if let Ok(owner) = Owner::load_by_email(email.clone(), conn) {
+                return Ok(owner);
+            }
+            // Owners stored before emails were captured only have a handle, claim them for this email
+            let mut stmt =
+                conn.prepare(&Owner::sql("LOWER(handle) = LOWER(?1) AND email IS NULL"))?;
+            let legacy_owner: Result<Owner, FownerError> = stmt
+                .query_row(params![self.handle], |r| Ok(Owner::from(r)))
+                .map_err(FownerError::from);
+            if let Ok(owner) = legacy_owner {
+                return owner.update_email(email.clone(), conn);
+            }
+        } else if let Ok(owner) = Owner::load_by_handle(self.handle.clone(), conn) {
             return Ok(owner);
         };
         self.save(conn)
     }
 
     pub fn save(&self, conn: &Connection) -> Result<Owner, FownerError> {
-        let mut stmt = conn.prepare("INSERT INTO owners (handle, name, primary_owner_id, created_at, updated_at) VALUES (?1, ?2, ?3, strftime('%s','now'), strftime('%s','now'))")?;
-        let _res = stmt.execute(params![self.handle, self.name, self.primary_owner_id])?;
-        Owner::load_by_handle(self.handle.clone(), conn)
+        let mut stmt = conn.prepare("INSERT INTO owners (handle, email, name, primary_owner_id, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, strftime('%s','now'), strftime('%s','now'))")?;
+        let _res = stmt.execute(params![
+            self.handle,
+            self.email,
+            self.name,
+            self.primary_owner_id
+        ])?;
+        let owner = Owner::load(conn.last_insert_rowid() as u32, conn)?;
+        if owner.matches_bot_patterns(&BotPattern::regexes(conn)?) {
+            let mut stmt = conn.prepare("UPDATE owners SET is_bot = 1 WHERE id = ?1")?;
+            stmt.execute(params![owner.id])?;
+            return Owner::load(owner.id, conn);
+        }
+        Ok(owner)
     }
 }
 
@@ -93,16 +239,20 @@ impl<'stmt> From<&Row<'stmt>> for Owner {
         Self {
             id: row.get(0).unwrap(),
             handle: row.get(1).unwrap(),
-            name: row.get(2).unwrap(),
-            primary_owner_id: row.get(3).unwrap(),
-            created_at: NaiveDateTime::from_timestamp(row.get(4).unwrap(), 0),
-            updated_at: NaiveDateTime::from_timestamp(row.get(5).unwrap(), 0),
+            email: row.get(2).unwrap(),
+            name: row.get(3).unwrap(),
+            primary_owner_id: row.get(4).unwrap(),
+            created_at: NaiveDateTime::from_timestamp(row.get(5).unwrap(), 0),
+            updated_at: NaiveDateTime::from_timestamp(row.get(6).unwrap(), 0),
+            is_bot: row.get(7).unwrap(),
+            is_bot_manual: row.get(8).unwrap(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::db::models::bot_pattern::NewBotPattern;
     use crate::db::models::owner::{NewOwner, Owner, UpdateOwner};
     use crate::test::tests::TestHandler;
     use crate::Connection;
@@ -116,6 +266,7 @@ mod tests {
             handle: "Krakaw".to_string(),
             name: Some("Kra-kaw!".to_string()),
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -133,6 +284,7 @@ mod tests {
             handle: "Krakaw".to_string(),
             name: Some("Kra-kaw!".to_string()),
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -153,6 +305,7 @@ mod tests {
             handle: "Krakaw".to_string(),
             name: Some("Kra-kaw!".to_string()),
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -170,6 +323,7 @@ mod tests {
             handle: "Krakaw".to_string(),
             name: Some("Kra-kaw!".to_string()),
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -177,6 +331,7 @@ mod tests {
             handle: "Krakaw2".to_string(),
             name: Some("Kra-kaw! 2".to_string()),
             primary_owner_id: Some(1),
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -194,6 +349,7 @@ mod tests {
             handle: "Krakaw".to_string(),
             name: Some("Kra-kaw!".to_string()),
             primary_owner_id: None,
+            email: None,
         }
         .save(&conn)
         .unwrap();
@@ -206,6 +362,7 @@ mod tests {
                 UpdateOwner {
                     name: Some("krakaw".to_string()),
                     primary_owner_id: None,
+                    is_bot: None,
                 },
                 &conn,
             )
@@ -213,4 +370,123 @@ mod tests {
         assert_eq!(new_owner.id, db_owner.id);
         assert_eq!(new_owner.name, Some("krakaw".to_string()));
     }
+
+    #[test]
+    fn save_or_load_by_email() {
+        let handler = TestHandler::init();
+        let db = &handler.db;
+        let conn = Connection::try_from(db).unwrap();
+        let john = NewOwner {
+            handle: "John Smith".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: Some("john@example.com".to_string()),
+        }
+        .save_or_load(&conn)
+        .unwrap();
+
+        // Same name, different person
+        let other_john = NewOwner {
+            handle: "John Smith".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: Some("john.smith@other.com".to_string()),
+        }
+        .save_or_load(&conn)
+        .unwrap();
+        assert_ne!(john.id, other_john.id);
+
+        // Same person, different name
+        let johnny = NewOwner {
+            handle: "Johnny".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: Some("John@Example.com".to_string()),
+        }
+        .save_or_load(&conn)
+        .unwrap();
+        assert_eq!(john, johnny);
+    }
+
+    #[test]
+    fn save_or_load_claims_legacy_owner() {
+        let handler = TestHandler::init();
+        let db = &handler.db;
+        let conn = Connection::try_from(db).unwrap();
+        let legacy = NewOwner {
+            handle: "Krakaw".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: None,
+        }
+        .save(&conn)
+        .unwrap();
+        assert_eq!(legacy.email, None);
+
+        let owner = NewOwner {
+            handle: "krakaw".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: Some("krakaw@example.com".to_string()),
+        }
+        .save_or_load(&conn)
+        .unwrap();
+        assert_eq!(owner.id, legacy.id);
+        assert_eq!(owner.email, Some("krakaw@example.com".to_string()));
+    }
+
+    #[test]
+    fn bots() {
+        let handler = TestHandler::init();
+        let db = &handler.db;
+        let conn = Connection::try_from(db).unwrap();
+        let renovate = NewOwner {
+            handle: "Renovate Bot".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: Some("bot@renovateapp.com".to_string()),
+        }
+        .save(&conn)
+        .unwrap();
+        assert!(renovate.is_bot);
+        let release = NewOwner {
+            handle: "Release Robot".to_string(),
+            name: None,
+            primary_owner_id: None,
+            email: Some("releases@example.com".to_string()),
+        }
+        .save(&conn)
+        .unwrap();
+        assert!(!release.is_bot);
+
+        let pattern = NewBotPattern {
+            pattern: "^releases@".to_string(),
+        }
+        .save(&conn)
+        .unwrap();
+        assert!(Owner::load(release.id, &conn).unwrap().is_bot);
+        assert!(NewBotPattern {
+            pattern: "[unclosed".to_string()
+        }
+        .save(&conn)
+        .is_err());
+
+        // Marking an owner by hand overrides the patterns
+        let renovate = renovate
+            .update(
+                UpdateOwner {
+                    name: None,
+                    primary_owner_id: None,
+                    is_bot: Some(false),
+                },
+                &conn,
+            )
+            .unwrap();
+        assert!(!renovate.is_bot);
+        assert!(renovate.is_bot_manual);
+
+        pattern.destroy(&conn).unwrap();
+        assert!(!Owner::load(release.id, &conn).unwrap().is_bot);
+        assert!(!Owner::load(renovate.id, &conn).unwrap().is_bot);
+    }
 }
@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use r2d2_sqlite::rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::extract_all;
+use crate::db::models::owner::Owner;
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+/// What set an owner's `primary_owner_id`
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnerMergeSource {
+    /// Set through the owners API
+    Manual,
+    /// The `.mailmap` committed to the repository
+    Mailmap,
+    /// The mailmap uploaded for the project
+    ProjectMailmap,
+    /// An accepted duplicate owner suggestion
+    Suggestion,
+}
+
+impl OwnerMergeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OwnerMergeSource::Manual => "manual",
+            OwnerMergeSource::Mailmap => "mailmap",
+            OwnerMergeSource::ProjectMailmap => "project_mailmap",
+            OwnerMergeSource::Suggestion => "suggestion",
+        }
+    }
+
+    /// Mailmaps may replace the merges they made, but never a merge made by hand
+    pub fn is_mailmap(&self) -> bool {
+        matches!(
+            self,
+            OwnerMergeSource::Mailmap | OwnerMergeSource::ProjectMailmap
+        )
+    }
+}
+
+impl FromStr for OwnerMergeSource {
+    type Err = FownerError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "manual" => Ok(OwnerMergeSource::Manual),
+            "mailmap" => Ok(OwnerMergeSource::Mailmap),
+            "project_mailmap" => Ok(OwnerMergeSource::ProjectMailmap),
+            "suggestion" => Ok(OwnerMergeSource::Suggestion),
+            _ => Err(FownerError::Internal(format!(
+                "Unknown owner merge source {}",
+                source
+            ))),
+        }
+    }
+}
+
+/// An audit record of an owner's `primary_owner_id` changing
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct OwnerMerge {
+    pub id: u32,
+    pub owner_id: u32,
+    pub primary_owner_id: Option<u32>,
+    pub previous_primary_owner_id: Option<u32>,
+    pub source: OwnerMergeSource,
+    /// The project whose mailmap made the change
+    pub project_id: Option<u32>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewOwnerMerge {
+    pub owner_id: u32,
+    pub primary_owner_id: Option<u32>,
+    pub previous_primary_owner_id: Option<u32>,
+    pub source: OwnerMergeSource,
+    pub project_id: Option<u32>,
+}
+
+impl OwnerMerge {
+    fn sql(where_clause: &str) -> String {
+        format!(
+            "SELECT id, owner_id, primary_owner_id, previous_primary_owner_id, source, project_id, created_at FROM owner_merges WHERE {}",
+            where_clause
+        )
+    }
+
+    /// The changes to the owner's primary owner, newest first
+    pub fn load_by_owner(owner_id: u32, conn: &Connection) -> Result<Vec<Self>, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql("owner_id = ?1 ORDER BY id DESC"))?;
+        extract_all!(params![owner_id], stmt)
+    }
+
+    /// Points each of `owner_ids`, and the owners already pointing at them, at the primary
+    /// owner of `primary_owner_id`, returning the owners that changed
+    pub fn merge_into(
+        primary_owner_id: u32,
+        owner_ids: &[u32],
+        source: OwnerMergeSource,
+        conn: &Connection,
+    ) -> Result<Vec<Owner>, FownerError> {
+        let primary = Owner::load(primary_owner_id, conn)?;
+        let primary_owner_id = primary.primary_owner_id.unwrap_or(primary.id);
+        let mut merged = vec![];
+        for owner_id in owner_ids {
+            let owner = Owner::load(*owner_id, conn)?;
+            let mut stmt = conn.prepare(&Owner::sql("primary_owner_id = ?1"))?;
+            let aliases = stmt
+                .query_map(params![owner.id], |r| Ok(Owner::from(r)))?
+                .collect::<Result<Vec<Owner>, _>>()?;
+            for owner in std::iter::once(owner).chain(aliases) {
+                if owner.id == primary_owner_id || owner.primary_owner_id == Some(primary_owner_id)
+                {
+                    continue;
+                }
+                NewOwnerMerge {
+                    owner_id: owner.id,
+                    primary_owner_id: Some(primary_owner_id),
+                    previous_primary_owner_id: owner.primary_owner_id,
+                    source,
+                    project_id: None,
+                }
+                .save(conn)?;
+                merged.push(Owner::load(owner.id, conn)?);
+            }
+        }
+        Ok(merged)
+    }
+
+    /// The source of the owner's current primary owner, `None` when it was never merged
+    pub fn latest_source(
+        owner_id: u32,
+        conn: &Connection,
+    ) -> Result<Option<OwnerMergeSource>, FownerError> {
+        Ok(Self::load_by_owner(owner_id, conn)?
+            .first()
+            .map(|merge| merge.source))
+    }
+}
+
+impl NewOwnerMerge {
+    /// Points the owner at its new primary owner and records the change
+    pub fn save(&self, conn: &Connection) -> Result<(), FownerError> {
+        let mut stmt = conn.prepare(
+            "UPDATE owners SET primary_owner_id = ?1, updated_at = strftime('%s','now') WHERE id = ?2",
+        )?;
+        stmt.execute(params![self.primary_owner_id, self.owner_id])?;
+        let mut stmt = conn.prepare(
+            r#"
+        INSERT INTO owner_merges (owner_id, primary_owner_id, previous_primary_owner_id, source, project_id, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, strftime('%s','now'))
+        "#,
+        )?;
+        stmt.execute(params![
+            self.owner_id,
+            self.primary_owner_id,
+            self.previous_primary_owner_id,
+            self.source.as_str(),
+            self.project_id
+        ])?;
+        Ok(())
+    }
+}
+
+impl<'stmt> From<&Row<'stmt>> for OwnerMerge {
+    fn from(row: &Row) -> Self {
+        let source: String = row.get(4).unwrap();
+        Self {
+            id: row.get(0).unwrap(),
+            owner_id: row.get(1).unwrap(),
+            primary_owner_id: row.get(2).unwrap(),
+            previous_primary_owner_id: row.get(3).unwrap(),
+            source: OwnerMergeSource::from_str(&source).unwrap_or(OwnerMergeSource::Manual),
+            project_id: row.get(5).unwrap(),
+            created_at: NaiveDateTime::from_timestamp(row.get(6).unwrap(), 0),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use chrono::NaiveDateTime;
 use r2d2_sqlite::rusqlite::{params, Row};
@@ -8,7 +9,14 @@ use crate::db::models::feature::Feature;
 use crate::db::models::{extract_all, extract_first};
 use crate::db::Connection;
 use crate::errors::FownerError;
+use crate::git::credentials::{GitCredentials, SealedCredentials};
+use crate::git::features::FeatureExtractor;
+use crate::git::history::MergePolicy;
+use crate::git::mailmap::Mailmap;
 use crate::git::manager::GitManager;
+use crate::git::path_filter::PathFilter;
+use crate::git::remote::{validate_remotes, CloneMode, GitRemote, RemoteConfig, DEFAULT_REMOTE};
+use crate::git::subpaths::Subpaths;
 use crate::File;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -19,6 +27,33 @@ pub struct Project {
     pub github_api_token: Option<String>,
     pub github_labels_only: bool,
     pub path: String,
+    /// Regexes used to extract features from commit messages, `None` uses the default `[Feature]` pattern
+    pub commit_feature_patterns: Option<Vec<String>>,
+    /// Regexes used to extract features from forge labels, `None` uses the default `f: Feature` pattern
+    pub label_feature_patterns: Option<Vec<String>>,
+    /// Conventional Commits scopes become features and the commit type is recorded
+    pub conventional_commits: bool,
+    /// Branches to ingest and track, `None` follows the checked out branch
+    pub branches: Option<Vec<String>>,
+    /// How merge commits are ingested, `None` records merges without crediting any files
+    pub merge_policy: Option<MergePolicy>,
+    /// Directories inside the repository the project covers, `None` covers the whole repository
+    /// Set on creation, projects with different subpaths can share a clone
+    pub subpaths: Option<Vec<String>>,
+    /// Globs a path must match one of to be ingested, `None` includes every path
+    pub include_globs: Option<Vec<String>>,
+    /// Globs of paths that are never ingested, such as lockfiles and vendored code
+    pub exclude_globs: Option<Vec<String>>,
+    /// A mailmap uploaded for the project, its entries override the repository's `.mailmap`
+    pub mailmap: Option<String>,
+    /// Used for the repository's clones and fetches, the secrets are never serialized
+    pub credentials: Option<SealedCredentials>,
+    /// The name of the remote `repo_url` is cloned as, set on creation
+    pub remote_name: String,
+    /// How the repository is cloned and kept up to date, set on creation
+    pub clone_mode: CloneMode,
+    /// Extra remotes, such as forks, fetched alongside `repo_url`
+    pub remotes: Option<Vec<GitRemote>>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -30,6 +65,43 @@ pub struct NewProject {
     pub github_api_token: Option<String>,
     pub github_labels_only: bool,
     pub path: PathBuf,
+    pub commit_feature_patterns: Option<Vec<String>>,
+    pub label_feature_patterns: Option<Vec<String>>,
+    #[serde(default)]
+    pub conventional_commits: bool,
+    pub branches: Option<Vec<String>>,
+    pub merge_policy: Option<MergePolicy>,
+    pub subpaths: Option<Vec<String>>,
+    pub include_globs: Option<Vec<String>>,
+    pub exclude_globs: Option<Vec<String>>,
+    pub credentials: Option<GitCredentials>,
+    /// Defaults to `origin`
+    pub remote_name: Option<String>,
+    #[serde(default)]
+    pub clone_mode: CloneMode,
+    pub remotes: Option<Vec<GitRemote>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct UpdateProject {
+    pub name: Option<String>,
+    pub github_labels_only: Option<bool>,
+    /// The lists are kept when missing and reset by an explicit `null` or an empty list
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub commit_feature_patterns: Option<Option<Vec<String>>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub label_feature_patterns: Option<Option<Vec<String>>>,
+    pub conventional_commits: Option<bool>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub branches: Option<Option<Vec<String>>>,
+    pub merge_policy: Option<MergePolicy>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub include_globs: Option<Option<Vec<String>>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    pub exclude_globs: Option<Option<Vec<String>>>,
+    pub mailmap: Option<String>,
+    pub credentials: Option<GitCredentials>,
+    pub remotes: Option<Vec<GitRemote>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,16 +113,29 @@ pub struct DisplayProject {
 
 impl NewProject {
     pub fn save_or_load(&self, conn: &Connection) -> Result<Project, FownerError> {
-        if let Ok(project) = Project::load_by_path(&self.path, conn) {
+        let subpaths = Subpaths::normalize(self.subpaths.clone())?;
+        if let Ok(project) = Project::load_by_path_and_subpaths(&self.path, &subpaths, conn) {
             return Ok(project);
         }
         self.save(conn)
     }
     pub fn save(&self, conn: &Connection) -> Result<Project, FownerError> {
+        validate_feature_patterns(&self.commit_feature_patterns, &self.label_feature_patterns)?;
+        validate_branches(&self.branches)?;
+        PathFilter::new(&self.include_globs, &self.exclude_globs)?;
+        let subpaths = Subpaths::normalize(self.subpaths.clone())?;
+        if let Some(credentials) = &self.credentials {
+            credentials.validate()?;
+        }
+        let remote_name = self
+            .remote_name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_REMOTE.to_string());
+        validate_remotes(&remote_name, &self.remotes)?;
         let mut stmt = conn.prepare(
             r#"
-        INSERT INTO projects (name, repo_url, github_api_token, github_labels_only, path, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, strftime('%s', 'now'), strftime('%s', 'now'))
+        INSERT INTO projects (name, repo_url, github_api_token, github_labels_only, path, commit_feature_patterns, label_feature_patterns, conventional_commits, branches, merge_policy, subpaths, include_globs, exclude_globs, credentials, remote_name, clone_mode, remotes, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, strftime('%s', 'now'), strftime('%s', 'now'))
         "#,
         )?;
         let _res = stmt.execute(params![
@@ -58,7 +143,25 @@ impl NewProject {
             self.repo_url,
             self.github_api_token,
             self.github_labels_only,
-            self.path.to_string_lossy()
+            self.path.to_string_lossy(),
+            list_to_json(&self.commit_feature_patterns)?,
+            list_to_json(&self.label_feature_patterns)?,
+            self.conventional_commits,
+            list_to_json(&self.branches)?,
+            self.merge_policy.map(|p| p.as_str()),
+            list_to_json(&subpaths)?,
+            list_to_json(&self.include_globs)?,
+            list_to_json(&self.exclude_globs)?,
+            credentials_to_json(
+                &self
+                    .credentials
+                    .as_ref()
+                    .map(SealedCredentials::seal)
+                    .transpose()?
+            )?,
+            remote_name,
+            self.clone_mode.as_str(),
+            remotes_to_json(&self.remotes)?
         ])?;
         let id = conn.last_insert_rowid();
         Project::load(id as u32, conn)
@@ -70,7 +173,9 @@ impl Project {
         format!(
             r#"
             SELECT
-                id, name, repo_url, github_api_token, github_labels_only, path, created_at, updated_at
+                id, name, repo_url, github_api_token, github_labels_only, path, created_at, updated_at,
+                commit_feature_patterns, label_feature_patterns, conventional_commits, branches, merge_policy, subpaths, include_globs, exclude_globs, mailmap, credentials,
+                remote_name, clone_mode, remotes
                 FROM projects
                 {}
                 {}
@@ -104,7 +209,7 @@ impl Project {
     pub fn get_github_api_url(&self) -> Result<String, FownerError> {
         if let Some(repo_url) = &self.repo_url {
             if !repo_url.contains("github.com") {
-                return Err(FownerError::GitError(format!(
+                return Err(FownerError::InvalidRemote(format!(
                     "Github API url cannot be generated from {}",
                     repo_url
                 )));
@@ -119,7 +224,7 @@ impl Project {
                 let repo_owner: Vec<&str> = path
                     .first()
                     .ok_or_else(|| {
-                        FownerError::GitError("Invalid SSH Github url in repo_url".to_string())
+                        FownerError::InvalidRemote("Invalid SSH Github url in repo_url".to_string())
                     })?
                     .rsplit('/')
                     .collect();
@@ -129,11 +234,11 @@ impl Project {
                 .first()
                 .map(|r| r.replace(".git", ""))
                 .ok_or_else(|| {
-                    FownerError::GitError("Missing repository in repo_url".to_string())
+                    FownerError::InvalidRemote("Missing repository in repo_url".to_string())
                 })?;
-            let owner = repo_owner
-                .get(1)
-                .ok_or_else(|| FownerError::GitError("Missing owner in repo_url".to_string()))?;
+            let owner = repo_owner.get(1).ok_or_else(|| {
+                FownerError::InvalidRemote("Missing owner in repo_url".to_string())
+            })?;
 
             let github_api_url = format!("https://api.github.com/repos/{}/{}", owner, repo);
             Ok(github_api_url)
@@ -160,6 +265,28 @@ impl Project {
         Ok(rows)
     }
 
+    /// Loads every project of the clone at exactly `path`, one for each set of subpaths
+    pub fn load_all_by_path(path: &Path, conn: &Connection) -> Result<Vec<Self>, FownerError> {
+        let mut stmt = conn.prepare(&Project::sql(Some("WHERE path = ?1".to_string()), None))?;
+        extract_all!(params![path.to_string_lossy()], stmt)
+    }
+
+    /// Loads the project covering exactly `subpaths` of the clone at `path`
+    pub fn load_by_path_and_subpaths(
+        path: &Path,
+        subpaths: &Option<Vec<String>>,
+        conn: &Connection,
+    ) -> Result<Self, FownerError> {
+        let mut stmt = conn.prepare(&Project::sql(
+            Some("WHERE path = ?1 AND coalesce(subpaths, '') = coalesce(?2, '')".to_string()),
+            Some("LIMIT 1".to_string()),
+        ))?;
+        extract_first!(
+            params![path.to_string_lossy(), list_to_json(subpaths)?],
+            stmt
+        )
+    }
+
     pub fn for_display(&self, conn: &Connection) -> Result<DisplayProject, FownerError> {
         let features = Feature::load_by_project(self.id, conn)?;
         let files = File::all(self.id, conn)?;
@@ -170,6 +297,155 @@ impl Project {
         })
     }
 
+    /// Only the fields present in `update_details` are changed, lists set to `null` or
+    /// left empty go back to their defaults
+    pub fn update(
+        self,
+        update_details: UpdateProject,
+        conn: &Connection,
+    ) -> Result<Self, FownerError> {
+        let commit_feature_patterns = updated_list(
+            update_details.commit_feature_patterns,
+            self.commit_feature_patterns,
+        );
+        let label_feature_patterns = updated_list(
+            update_details.label_feature_patterns,
+            self.label_feature_patterns,
+        );
+        validate_feature_patterns(&commit_feature_patterns, &label_feature_patterns)?;
+        let branches = updated_list(update_details.branches, self.branches);
+        validate_branches(&branches)?;
+        let include_globs = updated_list(update_details.include_globs, self.include_globs);
+        let exclude_globs = updated_list(update_details.exclude_globs, self.exclude_globs);
+        let path_filter = PathFilter::new(&include_globs, &exclude_globs)?;
+        let mailmap = update_details.mailmap.or(self.mailmap);
+        if let Some(mailmap) = &mailmap {
+            Mailmap::parse(mailmap)?;
+        }
+        if let Some(credentials) = &update_details.credentials {
+            credentials.validate()?;
+        }
+        let credentials = match &update_details.credentials {
+            Some(credentials) => Some(SealedCredentials::seal(credentials)?),
+            None => self.credentials,
+        };
+        let remotes = update_details.remotes.or(self.remotes);
+        validate_remotes(&self.remote_name, &remotes)?;
+        let mut stmt = conn.prepare(
+            r#"
+        UPDATE projects
+        SET name                    = ?1,
+            github_labels_only      = ?2,
+            commit_feature_patterns = ?3,
+            label_feature_patterns  = ?4,
+            conventional_commits    = ?5,
+            branches                = ?6,
+            merge_policy            = ?7,
+            include_globs           = ?8,
+            exclude_globs           = ?9,
+            mailmap                 = ?10,
+            credentials             = ?11,
+            remotes                 = ?12,
+            updated_at              = strftime('%s', 'now')
+        WHERE id = ?13
+        "#,
+        )?;
+        let _res = stmt.execute(params![
+            update_details.name.or(self.name),
+            update_details
+                .github_labels_only
+                .unwrap_or(self.github_labels_only),
+            list_to_json(&commit_feature_patterns)?,
+            list_to_json(&label_feature_patterns)?,
+            update_details
+                .conventional_commits
+                .unwrap_or(self.conventional_commits),
+            list_to_json(&branches)?,
+            update_details
+                .merge_policy
+                .or(self.merge_policy)
+                .map(|p| p.as_str()),
+            list_to_json(&include_globs)?,
+            list_to_json(&exclude_globs)?,
+            mailmap,
+            credentials_to_json(&credentials)?,
+            remotes_to_json(&remotes)?,
+            self.id
+        ])?;
+        // Files ingested before the globs changed may now be excluded
+        File::flag_excluded(self.id, &path_filter, conn)?;
+        Self::load(self.id, conn)
+    }
+
+    /// Removes the project's credentials, later clones and fetches use the server's own
+    pub fn clear_credentials(self, conn: &Connection) -> Result<Self, FownerError> {
+        let mut stmt = conn.prepare(
+            "UPDATE projects SET credentials = NULL, updated_at = strftime('%s', 'now') WHERE id = ?1",
+        )?;
+        stmt.execute(params![self.id])?;
+        Self::load(self.id, conn)
+    }
+
+    /// Seals credentials stored in plain text before they were encrypted, returns how many were
+    pub fn seal_plaintext_credentials(conn: &Connection) -> Result<usize, FownerError> {
+        let mut stmt =
+            conn.prepare("SELECT id, credentials FROM projects WHERE credentials IS NOT NULL")?;
+        let stored = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<(u32, String)>, _>>()?;
+        let mut stmt = conn.prepare("UPDATE projects SET credentials = ?1 WHERE id = ?2")?;
+        let mut sealed = 0;
+        for (id, json) in stored {
+            if SealedCredentials::from_stored_json(&json).is_ok() {
+                continue;
+            }
+            let credentials =
+                SealedCredentials::seal(&GitCredentials::from_plaintext_json(&json)?)?;
+            stmt.execute(params![credentials.to_stored_json()?, id])?;
+            sealed += 1;
+        }
+        Ok(sealed)
+    }
+
+    /// The remotes the project's clone fetches and how it is cloned
+    pub fn remote_config(&self) -> RemoteConfig {
+        RemoteConfig {
+            name: self.remote_name.clone(),
+            clone_mode: self.clone_mode,
+            remotes: self.remotes.clone().unwrap_or_default(),
+        }
+    }
+
+    pub fn subpaths(&self) -> Subpaths {
+        Subpaths::new(&self.subpaths)
+    }
+
+    pub fn path_filter(&self) -> Result<PathFilter, FownerError> {
+        PathFilter::new(&self.include_globs, &self.exclude_globs)
+    }
+
+    /// The uploaded mailmap, `None` when there is none
+    pub fn mailmap(&self) -> Result<Option<Mailmap>, FownerError> {
+        self.mailmap.as_deref().map(Mailmap::parse).transpose()
+    }
+
+    pub fn commit_feature_extractor(&self) -> Result<FeatureExtractor, FownerError> {
+        let feature_extractor = match &self.commit_feature_patterns {
+            Some(patterns) => FeatureExtractor::new(patterns)?,
+            None => FeatureExtractor::for_commits(),
+        };
+        Ok(feature_extractor.with_conventional_commits(self.conventional_commits))
+    }
+
+    pub fn label_feature_extractor(&self) -> Result<FeatureExtractor, FownerError> {
+        match &self.label_feature_patterns {
+            Some(patterns) => FeatureExtractor::new(patterns),
+            None => Ok(FeatureExtractor::for_labels()),
+        }
+    }
+
     pub fn destroy(self, conn: &Connection) -> Result<usize, FownerError> {
         let mut stmt = conn.prepare("DELETE FROM projects WHERE id = ?")?;
         let result = stmt.execute(params![self.id])?;
@@ -177,6 +453,61 @@ impl Project {
     }
 }
 
+fn validate_feature_patterns(
+    commit_feature_patterns: &Option<Vec<String>>,
+    label_feature_patterns: &Option<Vec<String>>,
+) -> Result<(), FownerError> {
+    if let Some(patterns) = commit_feature_patterns {
+        FeatureExtractor::new(patterns)?;
+    }
+    if let Some(patterns) = label_feature_patterns {
+        FeatureExtractor::new(patterns)?;
+    }
+    Ok(())
+}
+
+fn validate_branches(branches: &Option<Vec<String>>) -> Result<(), FownerError> {
+    for branch in branches.iter().flatten() {
+        if branch.trim().is_empty() || branch.contains(char::is_whitespace) {
+            return Err(FownerError::InvalidBranch(branch.to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn list_to_json(list: &Option<Vec<String>>) -> Result<Option<String>, FownerError> {
+    list.as_ref()
+        .map(|p| serde_json::to_string(p).map_err(FownerError::from))
+        .transpose()
+}
+
+fn remotes_to_json(remotes: &Option<Vec<GitRemote>>) -> Result<Option<String>, FownerError> {
+    remotes
+        .as_ref()
+        .map(|r| serde_json::to_string(r).map_err(FownerError::from))
+        .transpose()
+}
+
+/// `None` keeps the current list, an explicit `null` or an empty list resets it
+fn updated_list(
+    update: Option<Option<Vec<String>>>,
+    current: Option<Vec<String>>,
+) -> Option<Vec<String>> {
+    match update {
+        Some(list) => list.filter(|list| !list.is_empty()),
+        None => current,
+    }
+}
+
+fn credentials_to_json(
+    credentials: &Option<SealedCredentials>,
+) -> Result<Option<String>, FownerError> {
+    credentials
+        .as_ref()
+        .map(SealedCredentials::to_stored_json)
+        .transpose()
+}
+
 impl<'stmt> From<&Row<'stmt>> for Project {
     fn from(row: &Row) -> Self {
         Self {
@@ -188,6 +519,51 @@ impl<'stmt> From<&Row<'stmt>> for Project {
             path: row.get(5).unwrap(),
             created_at: NaiveDateTime::from_timestamp(row.get(6).unwrap(), 0),
             updated_at: NaiveDateTime::from_timestamp(row.get(7).unwrap(), 0),
+            commit_feature_patterns: row
+                .get(8)
+                .map(|p: Option<String>| p.and_then(|p| serde_json::from_str(&p).ok()))
+                .unwrap_or_default(),
+            label_feature_patterns: row
+                .get(9)
+                .map(|p: Option<String>| p.and_then(|p| serde_json::from_str(&p).ok()))
+                .unwrap_or_default(),
+            conventional_commits: row.get(10).unwrap(),
+            branches: row
+                .get(11)
+                .map(|b: Option<String>| b.and_then(|b| serde_json::from_str(&b).ok()))
+                .unwrap_or_default(),
+            merge_policy: row
+                .get(12)
+                .map(|p: Option<String>| p.and_then(|p| MergePolicy::from_str(&p).ok()))
+                .unwrap_or_default(),
+            subpaths: row
+                .get(13)
+                .map(|s: Option<String>| s.and_then(|s| serde_json::from_str(&s).ok()))
+                .unwrap_or_default(),
+            include_globs: row
+                .get(14)
+                .map(|g: Option<String>| g.and_then(|g| serde_json::from_str(&g).ok()))
+                .unwrap_or_default(),
+            exclude_globs: row
+                .get(15)
+                .map(|g: Option<String>| g.and_then(|g| serde_json::from_str(&g).ok()))
+                .unwrap_or_default(),
+            mailmap: row.get(16).unwrap(),
+            credentials: row
+                .get(17)
+                .map(|c: Option<String>| {
+                    c.and_then(|c| SealedCredentials::from_stored_json(&c).ok())
+                })
+                .unwrap_or_default(),
+            remote_name: row.get(18).unwrap(),
+            clone_mode: row
+                .get(19)
+                .map(|m: String| CloneMode::from_str(&m).unwrap_or_default())
+                .unwrap_or_default(),
+            remotes: row
+                .get(20)
+                .map(|r: Option<String>| r.and_then(|r| serde_json::from_str(&r).ok()))
+                .unwrap_or_default(),
         }
     }
 }
@@ -200,6 +576,18 @@ impl From<&GitManager> for NewProject {
             path: repo.path.clone(),
             github_api_token: None,
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: Some(repo.remotes.name.clone()),
+            clone_mode: repo.remotes.clone_mode,
+            remotes: Some(repo.remotes.remotes.clone()).filter(|r| !r.is_empty()),
         }
     }
 }
@@ -209,9 +597,14 @@ mod tests {
     use std::env;
     use std::path::{Path, PathBuf};
 
-    use crate::db::models::project::NewProject;
+    use r2d2_sqlite::rusqlite::params;
+
+    use crate::db::models::project::{NewProject, UpdateProject};
+    use crate::git::backend::GitBackendKind;
+    use crate::git::credentials::GitCredentials;
+    use crate::git::remote::{CloneMode, GitRemote, RemoteConfig};
     use crate::test::tests::TestHandler;
-    use crate::{Connection, Project};
+    use crate::{Connection, FownerError, Project};
 
     fn add_project(conn: &Connection, tmp_dir: &Path, name: String) -> Project {
         let path = tmp_dir.join(&name);
@@ -221,6 +614,18 @@ mod tests {
             path,
             github_api_token: None,
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save_or_load(conn)
         .unwrap()
@@ -255,6 +660,18 @@ mod tests {
             path,
             github_api_token: None,
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(conn);
         eprintln!("err_result = {:?}", err_result);
@@ -263,6 +680,50 @@ mod tests {
         assert_eq!(project1, db_projects);
     }
 
+    #[test]
+    fn projects_share_a_clone() {
+        let handler = TestHandler::init();
+        let conn = &Connection::try_from(&handler.db).unwrap();
+        let path = handler.tmp_dir.join("platform");
+        let new_project = |subpaths: Option<Vec<String>>| NewProject {
+            name: None,
+            repo_url: None,
+            path: path.clone(),
+            github_api_token: None,
+            github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
+        };
+        let whole = new_project(None).save(conn).unwrap();
+        let billing = new_project(Some(vec!["./products/billing/".to_string()]))
+            .save(conn)
+            .unwrap();
+        assert_ne!(whole.id, billing.id);
+        assert_eq!(billing.subpaths, Some(vec!["products/billing".to_string()]));
+        assert!(new_project(Some(vec!["products/billing".to_string()]))
+            .save(conn)
+            .is_err());
+        assert_eq!(
+            new_project(Some(vec!["products/billing".to_string()]))
+                .save_or_load(conn)
+                .unwrap(),
+            billing
+        );
+        assert!(new_project(Some(vec!["../billing".to_string()]))
+            .save(conn)
+            .is_err());
+    }
+
     #[test]
     fn all() {
         let handler = TestHandler::init();
@@ -319,6 +780,18 @@ mod tests {
             path: tmp_dir.to_path_buf(),
             github_api_token: Some("abc".to_string()),
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(conn)
         .unwrap();
@@ -326,6 +799,211 @@ mod tests {
         assert_eq!(db_project.github_api_token, Some("abc".to_string()));
     }
 
+    #[test]
+    fn update_feature_patterns() {
+        let handler = TestHandler::init();
+        let db = &handler.db;
+        let conn = &Connection::try_from(db).unwrap();
+        let project = add_project(conn, &handler.tmp_dir, "Project_1".to_string());
+        assert_eq!(project.commit_feature_patterns, None);
+        assert_eq!(
+            project
+                .commit_feature_extractor()
+                .unwrap()
+                .extract_from_message("Add [Core]"),
+            vec!["Core"]
+        );
+
+        let project = project
+            .update(
+                UpdateProject {
+                    commit_feature_patterns: Some(Some(vec![r"#feature/([\w-]+)".to_string()])),
+                    ..UpdateProject::default()
+                },
+                conn,
+            )
+            .unwrap();
+        assert_eq!(project.name, Some("Project_1".to_string()));
+        assert_eq!(
+            project
+                .commit_feature_extractor()
+                .unwrap()
+                .extract_from_message("Add [Core] #feature/search"),
+            vec!["search"]
+        );
+        assert_eq!(project.label_feature_patterns, None);
+
+        let invalid = project.clone().update(
+            UpdateProject {
+                label_feature_patterns: Some(Some(vec!["(unclosed".to_string()])),
+                ..UpdateProject::default()
+            },
+            conn,
+        );
+        assert!(invalid.is_err());
+        assert_eq!(Project::load(project.id, conn).unwrap(), project);
+
+        let invalid = project.clone().update(
+            UpdateProject {
+                branches: Some(Some(vec!["main branch".to_string()])),
+                ..UpdateProject::default()
+            },
+            conn,
+        );
+        assert!(matches!(invalid, Err(FownerError::InvalidBranch(_))));
+        assert_eq!(Project::load(project.id, conn).unwrap(), project);
+
+        // A missing list is kept, `null` or an empty list resets it
+        let project = project
+            .update(
+                UpdateProject {
+                    include_globs: Some(Some(vec!["src/**".to_string()])),
+                    branches: Some(Some(vec!["main".to_string()])),
+                    ..UpdateProject::default()
+                },
+                conn,
+            )
+            .unwrap();
+        let reset: UpdateProject = serde_json::from_str(
+            r#"{"commit_feature_patterns": null, "include_globs": [], "name": "Renamed"}"#,
+        )
+        .unwrap();
+        let project = project.update(reset, conn).unwrap();
+        assert_eq!(project.name, Some("Renamed".to_string()));
+        assert_eq!(project.commit_feature_patterns, None);
+        assert_eq!(project.include_globs, None);
+        assert_eq!(project.branches, Some(vec!["main".to_string()]));
+        assert_eq!(
+            project
+                .commit_feature_extractor()
+                .unwrap()
+                .extract_from_message("Add [Core]"),
+            vec!["Core"]
+        );
+    }
+
+    #[test]
+    fn credentials() {
+        let handler = TestHandler::init();
+        let conn = &Connection::try_from(&handler.db).unwrap();
+        let project = add_project(conn, &handler.tmp_dir, "Project_1".to_string());
+        assert_eq!(project.credentials, None);
+
+        let credentials = GitCredentials::HttpsToken {
+            token: "ghp_secret".to_string(),
+            username: None,
+        };
+        let project = project
+            .update(
+                UpdateProject {
+                    credentials: Some(credentials.clone()),
+                    ..UpdateProject::default()
+                },
+                conn,
+            )
+            .unwrap();
+        let stored = Project::load(project.id, conn)
+            .unwrap()
+            .credentials
+            .unwrap();
+        assert_eq!(stored.open().unwrap(), credentials);
+        assert_eq!(
+            stored.credentials,
+            GitCredentials::HttpsToken {
+                token: String::new(),
+                username: None,
+            }
+        );
+        let column: String = conn
+            .prepare("SELECT credentials FROM projects WHERE id = ?1")
+            .unwrap()
+            .query_row(params![project.id], |row| row.get(0))
+            .unwrap();
+        assert!(!column.contains("ghp_secret"));
+
+        // Credentials stored before they were sealed are sealed once a key is installed
+        conn.prepare("UPDATE projects SET credentials = ?1 WHERE id = ?2")
+            .unwrap()
+            .execute(params![
+                r#"{"kind":"https_token","token":"ghp_secret"}"#,
+                project.id
+            ])
+            .unwrap();
+        assert_eq!(Project::seal_plaintext_credentials(conn).unwrap(), 1);
+        assert_eq!(Project::seal_plaintext_credentials(conn).unwrap(), 0);
+        let stored = Project::load(project.id, conn)
+            .unwrap()
+            .credentials
+            .unwrap();
+        assert_eq!(stored.open().unwrap(), credentials);
+        assert!(!serde_json::to_string(&project)
+            .unwrap()
+            .contains("ghp_secret"));
+
+        let invalid = project.clone().update(
+            UpdateProject {
+                credentials: Some(GitCredentials::SshKey {
+                    private_key: String::new(),
+                    passphrase: None,
+                }),
+                ..UpdateProject::default()
+            },
+            conn,
+        );
+        assert!(matches!(invalid, Err(FownerError::GitAuthentication(_))));
+
+        let project = project.clear_credentials(conn).unwrap();
+        assert_eq!(project.credentials, None);
+    }
+
+    #[test]
+    fn remotes() {
+        let handler = TestHandler::init();
+        let conn = &Connection::try_from(&handler.db).unwrap();
+        let project = add_project(conn, &handler.tmp_dir, "Project_1".to_string());
+        assert_eq!(project.remote_config(), RemoteConfig::default());
+
+        let fork = GitRemote {
+            name: "fork".to_string(),
+            url: "https://example.com/fork.git".to_string(),
+        };
+        let project = project
+            .update(
+                UpdateProject {
+                    remotes: Some(vec![fork.clone()]),
+                    ..UpdateProject::default()
+                },
+                conn,
+            )
+            .unwrap();
+        assert_eq!(project.remote_config().remotes, vec![fork]);
+
+        let clashing = project.clone().update(
+            UpdateProject {
+                remotes: Some(vec![GitRemote {
+                    name: "origin".to_string(),
+                    url: "https://example.com/other.git".to_string(),
+                }]),
+                ..UpdateProject::default()
+            },
+            conn,
+        );
+        assert!(clashing.is_err());
+
+        let mirror = NewProject {
+            remote_name: Some("upstream".to_string()),
+            clone_mode: CloneMode::Mirror,
+            ..NewProject::from(&TestHandler::git_manager(
+                &handler.tmp_dir.join("mirror"),
+                GitBackendKind::Cli,
+            ))
+        }
+        .save(conn)
+        .unwrap();
+        assert_eq!(mirror.remote_name, "upstream");
+        assert_eq!(mirror.clone_mode, CloneMode::Mirror);
+    }
+
     #[test]
     fn get_github_api_url() {
         let handler = TestHandler::init();
@@ -339,6 +1017,18 @@ mod tests {
             path: tmp_dir.join("p1"),
             github_api_token: Some("abc".to_string()),
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(conn)
         .unwrap();
@@ -351,6 +1041,18 @@ mod tests {
             path: tmp_dir.join("p2"),
             github_api_token: Some("abc".to_string()),
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(conn)
         .unwrap();
@@ -363,6 +1065,18 @@ mod tests {
             path: tmp_dir.join("p3"),
             github_api_token: Some("abc".to_string()),
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(conn)
         .unwrap();
@@ -387,6 +1101,18 @@ mod tests {
             path: PathBuf::from("data/fowner".to_string()),
             github_api_token: Some("abc".to_string()),
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(&conn)
         .unwrap();
@@ -414,6 +1140,18 @@ mod tests {
             path: handler.tmp_dir.clone(),
             github_api_token: Some("abc".to_string()),
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(&conn)
         .unwrap();
@@ -0,0 +1,70 @@
+use chrono::NaiveDateTime;
+use r2d2_sqlite::rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::{extract_all, extract_first};
+use crate::db::Connection;
+use crate::errors::FownerError;
+
+/// The last tip of a tracked branch that was fully ingested
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProjectRef {
+    pub project_id: u32,
+    pub ref_name: String,
+    pub sha: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+pub struct NewProjectRef {
+    pub project_id: u32,
+    pub ref_name: String,
+    pub sha: String,
+}
+
+impl ProjectRef {
+    fn sql(where_clause: &str) -> String {
+        format!(
+            "SELECT project_id, ref_name, sha, created_at, updated_at FROM project_refs WHERE {}",
+            where_clause
+        )
+    }
+
+    pub fn load(project_id: u32, ref_name: &str, conn: &Connection) -> Result<Self, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql("project_id = ?1 AND ref_name = ?2"))?;
+        extract_first!(params![project_id, ref_name], stmt)
+    }
+
+    pub fn load_by_project(project_id: u32, conn: &Connection) -> Result<Vec<Self>, FownerError> {
+        let mut stmt = conn.prepare(&Self::sql("project_id = ?1 ORDER BY ref_name"))?;
+        extract_all!(params![project_id], stmt)
+    }
+}
+
+impl NewProjectRef {
+    pub fn save(&self, conn: &Connection) -> Result<ProjectRef, FownerError> {
+        let mut stmt = conn.prepare(
+            r#"
+        INSERT INTO project_refs (project_id, ref_name, sha, created_at, updated_at)
+        VALUES (?1, ?2, ?3, strftime('%s', 'now'), strftime('%s', 'now'))
+        ON CONFLICT
+            DO UPDATE SET sha        = EXCLUDED.sha,
+                          updated_at = strftime('%s', 'now');
+        "#,
+        )?;
+        let _res = stmt.execute(params![self.project_id, self.ref_name, self.sha])?;
+        ProjectRef::load(self.project_id, &self.ref_name, conn)
+    }
+}
+
+impl<'stmt> From<&Row<'stmt>> for ProjectRef {
+    fn from(row: &Row) -> Self {
+        Self {
+            project_id: row.get(0).unwrap(),
+            ref_name: row.get(1).unwrap(),
+            sha: row.get(2).unwrap(),
+            created_at: NaiveDateTime::from_timestamp(row.get(3).unwrap(), 0),
+            updated_at: NaiveDateTime::from_timestamp(row.get(4).unwrap(), 0),
+        }
+    }
+}
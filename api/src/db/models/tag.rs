@@ -0,0 +1,208 @@
+use chrono::NaiveDateTime;
+use r2d2_sqlite::rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::feature::Feature;
+use crate::db::models::owner::Owner;
+use crate::db::models::{extract_all, extract_first};
+use crate::db::Connection;
+use crate::errors::FownerError;
+use crate::File;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Tag {
+    pub id: u32,
+    pub project_id: u32,
+    pub name: String,
+    pub sha: String,
+    pub tagged_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+pub struct NewTag {
+    pub project_id: u32,
+    pub name: String,
+    pub sha: String,
+    pub tagged_at: NaiveDateTime,
+}
+
+/// Everything that changed after the `from` tag up to and including the `to` tag
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagChanges {
+    pub from: Option<Tag>,
+    pub to: Tag,
+    pub features: Vec<Feature>,
+    pub files: Vec<File>,
+    pub owners: Vec<Owner>,
+}
+
+impl NewTag {
+    /// Tags that are moved keep their id and point to the new sha
+    pub fn save(&self, conn: &Connection) -> Result<Tag, FownerError> {
+        let mut stmt = conn.prepare(
+            r#"
+        INSERT INTO tags (project_id, name, sha, tagged_at, created_at, updated_at)
+        VALUES (?1, ?2, ?3, ?4, strftime('%s', 'now'), strftime('%s', 'now'))
+        ON CONFLICT
+            DO UPDATE SET sha        = EXCLUDED.sha,
+                          tagged_at  = EXCLUDED.tagged_at,
+                          updated_at = strftime('%s', 'now');
+        "#,
+        )?;
+        let _res = stmt.execute(params![
+            self.project_id,
+            self.name,
+            self.sha,
+            self.tagged_at.timestamp()
+        ])?;
+        Tag::load_by_name(self.project_id, &self.name, conn)
+    }
+}
+
+impl Tag {
+    fn sql(where_clause: &str) -> String {
+        format!(
+            r#"
+            SELECT t.id, t.project_id, t.name, t.sha, t.tagged_at, t.created_at, t.updated_at
+            FROM tags t
+            WHERE {}
+            "#,
+            where_clause
+        )
+    }
+
+    pub fn load_by_name(
+        project_id: u32,
+        name: &str,
+        conn: &Connection,
+    ) -> Result<Tag, FownerError> {
+        let mut stmt = conn.prepare(&Tag::sql("t.project_id = ?1 AND t.name = ?2"))?;
+        extract_first!(params![project_id, name], stmt)
+    }
+
+    pub fn load_by_project(project_id: u32, conn: &Connection) -> Result<Vec<Tag>, FownerError> {
+        let mut stmt = conn.prepare(&Tag::sql("t.project_id = ?1 ORDER BY t.tagged_at DESC"))?;
+        extract_all!(params![project_id], stmt)
+    }
+
+    /// The tag pointing to the most recent commit before the one this tag points to
+    pub fn previous(&self, conn: &Connection) -> Result<Option<Tag>, FownerError> {
+        let commit_time = self.commit_time(conn)?;
+        let mut stmt = conn.prepare(&Tag::sql(
+            r#"t.project_id = ?1
+              AND (SELECT c.commit_time FROM commits c WHERE c.project_id = t.project_id AND c.sha = t.sha) < ?2
+            ORDER BY (SELECT c.commit_time FROM commits c WHERE c.project_id = t.project_id AND c.sha = t.sha) DESC,
+                     t.tagged_at DESC
+            LIMIT 1"#,
+        ))?;
+        let tags: Result<Vec<Tag>, FownerError> =
+            extract_all!(params![self.project_id, commit_time], stmt);
+        Ok(tags?.into_iter().next())
+    }
+
+    /// Returns the time of the commit the tag points to, which must have been ingested
+    fn commit_time(&self, conn: &Connection) -> Result<i64, FownerError> {
+        let mut stmt =
+            conn.prepare("SELECT commit_time FROM commits WHERE project_id = ?1 AND sha = ?2")?;
+        stmt.query_row(params![self.project_id, self.sha], |r| r.get(0))
+            .map_err(|_| {
+                FownerError::NotFound(format!(
+                    "Commit {} for tag {} has not been ingested",
+                    self.sha, self.name
+                ))
+            })
+    }
+
+    /// Lists the features, files and owners of commits made after `from` up to this tag,
+    /// falling back to the previous tag when `from` is not given
+    pub fn changes_since(
+        self,
+        from: Option<Tag>,
+        conn: &Connection,
+    ) -> Result<TagChanges, FownerError> {
+        let from = match from {
+            Some(from) => Some(from),
+            None => self.previous(conn)?,
+        };
+        let to_time = self.commit_time(conn)?;
+        let from_time = from.as_ref().map(|f| f.commit_time(conn)).transpose()?;
+        let commits_sql = r#"
+            SELECT c.id
+            FROM commits c
+            WHERE c.project_id = ?1
+              AND (?2 IS NULL OR c.commit_time > ?2)
+              AND c.commit_time <= ?3
+        "#;
+        let params = params![self.project_id, from_time, to_time];
+
+        let mut stmt = conn.prepare(&format!(
+            r#"
+            SELECT f.id, f.project_id, f.name, f.description, f.created_at, f.updated_at
+            FROM features f
+            WHERE f.id IN (SELECT ff.feature_id
+                           FROM file_commits fc
+                                    INNER JOIN file_features ff ON ff.file_id = fc.file_id
+                           WHERE fc.commit_id IN ({}))
+            ORDER BY f.name
+            "#,
+            commits_sql
+        ))?;
+        let rows = stmt.query_map(params, |r| Ok(Feature::from(r)))?;
+        let mut features = vec![];
+        for row in rows {
+            features.push(row?);
+        }
+
+        let mut stmt = conn.prepare(&File::sql(
+            Some(format!(
+                "AND f.id IN (SELECT fc.file_id FROM file_commits fc WHERE fc.commit_id IN ({}))",
+                commits_sql
+            )),
+            None,
+        ))?;
+        let rows = stmt.query_map(params, |r| Ok(File::from(r)))?;
+        let mut files = vec![];
+        for row in rows {
+            files.push(row?);
+        }
+
+        // Authors and co-authors, resolved to their primary owner
+        let mut stmt = conn.prepare(&Owner::sql(&format!(
+            r#"id IN (SELECT COALESCE(o.primary_owner_id, o.id)
+                      FROM (SELECT owner_id FROM commits WHERE id IN ({commits})
+                            UNION
+                            SELECT owner_id FROM commit_co_authors WHERE commit_id IN ({commits})) co
+                               INNER JOIN owners o ON o.id = co.owner_id)
+            ORDER BY handle"#,
+            commits = commits_sql
+        )))?;
+        let rows = stmt.query_map(params, |r| Ok(Owner::from(r)))?;
+        let mut owners = vec![];
+        for row in rows {
+            owners.push(row?);
+        }
+
+        Ok(TagChanges {
+            from,
+            to: self,
+            features,
+            files,
+            owners,
+        })
+    }
+}
+
+impl<'stmt> From<&Row<'stmt>> for Tag {
+    fn from(row: &Row) -> Self {
+        Self {
+            id: row.get(0).unwrap(),
+            project_id: row.get(1).unwrap(),
+            name: row.get(2).unwrap(),
+            sha: row.get(3).unwrap(),
+            tagged_at: NaiveDateTime::from_timestamp(row.get(4).unwrap(), 0),
+            created_at: NaiveDateTime::from_timestamp(row.get(5).unwrap(), 0),
+            updated_at: NaiveDateTime::from_timestamp(row.get(6).unwrap(), 0),
+        }
+    }
+}
@@ -1,18 +1,39 @@
+use std::collections::HashSet;
+
 use chrono::NaiveDateTime;
 use log::{debug, error, trace};
+use serde::Serialize;
 
-use crate::db::models::commit::{Commit, NewCommit};
+use crate::db::models::commit::{Commit, NewCommit, UnreachableLinks};
+use crate::db::models::commit_co_author::CommitCoAuthor;
+use crate::db::models::commit_feature::CommitFeature;
+use crate::db::models::commit_ref::CommitRef;
 use crate::db::models::feature::NewFeature;
-use crate::db::models::file::NewFile;
+use crate::db::models::file::{File, NewFile};
 use crate::db::models::file_commit::FileCommit;
+use crate::db::models::file_current_owner::{FileCurrentOwner, NewFileCurrentOwner};
 use crate::db::models::file_owner::NewFileOwner;
-use crate::db::models::owner::NewOwner;
+use crate::db::models::owner::{NewOwner, Owner};
+use crate::db::models::owner_merge::{NewOwnerMerge, OwnerMerge, OwnerMergeSource};
 use crate::db::models::project::{NewProject, Project};
+use crate::db::models::project_ref::{NewProjectRef, ProjectRef};
+use crate::db::models::tag::NewTag;
 use crate::db::Connection;
 use crate::errors::FownerError;
 use crate::git::github::Github;
+use crate::git::mailmap::Mailmap;
 use crate::git::manager::GitManager;
 
+/// The commits a fetch found to be unreachable and what happened to them
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    /// The commits and their links were deleted rather than flagged
+    pub pruned: bool,
+    pub commits: Vec<String>,
+    #[serde(flatten)]
+    pub links: UnreachableLinks,
+}
+
 pub struct Processor<'a> {
     pub conn: &'a Connection<'a>,
     pub git_manager: GitManager,
@@ -20,8 +41,17 @@ pub struct Processor<'a> {
 }
 
 impl<'a> Processor<'a> {
-    pub fn new(git_manager: GitManager, conn: &'a Connection) -> Result<Self, FownerError> {
-        let project = NewProject::from(&git_manager).save_or_load(conn)?;
+    /// Loads or creates the project covering `subpaths` of the repository
+    pub fn new(
+        git_manager: GitManager,
+        subpaths: Option<Vec<String>>,
+        conn: &'a Connection,
+    ) -> Result<Self, FownerError> {
+        let project = NewProject {
+            subpaths,
+            ..NewProject::from(&git_manager)
+        }
+        .save_or_load(conn)?;
         Ok(Processor {
             conn,
             git_manager,
@@ -45,24 +75,62 @@ impl<'a> Processor<'a> {
         stop_at_sha: Option<String>,
         skip_github_labels: bool,
     ) -> Result<(usize, usize), FownerError> {
-        let latest_commit = self.get_most_recent_commit();
-        let history = self.git_manager.parse_history(latest_commit)?;
         let project = self.project.clone();
         let project_id = project.id;
-        let number_of_commits = history.len();
+        let feature_extractor = self.project.commit_feature_extractor()?;
+        let subpaths = self.project.subpaths();
+        let path_filter = self.project.path_filter()?;
+        let tracked_refs = self.tracked_refs()?;
+        let refs: Vec<String> = tracked_refs.iter().map(|(_, r)| r.clone()).collect();
+        // Everything reachable from a previously ingested tip is already stored
+        let mut processed_tips = vec![];
+        for project_ref in ProjectRef::load_by_project(project_id, self.conn)? {
+            if self.git_manager.commit_exists(&project_ref.sha)? {
+                processed_tips.push(project_ref.sha);
+            }
+        }
+        let number_of_commits = self.git_manager.count_history(
+            &refs,
+            &processed_tips,
+            &subpaths,
+            project.merge_policy,
+        )?;
+        let history = self.git_manager.history_stream(
+            &refs,
+            &processed_tips,
+            &subpaths,
+            project.merge_policy,
+            &feature_extractor,
+        )?;
         let github = Github::try_from(&project).ok();
         let stop_at_sha = stop_at_sha.unwrap_or_default();
         debug!("{} new commits to process", number_of_commits);
 
         let mut commit_counter = 1;
+        let mut stopped_early = false;
+        let mut touched_paths = HashSet::new();
         for git_history in history {
+            let mut git_history = git_history?;
+            // Commits can already be stored when a previous fetch stopped early
+            if Commit::exists(project_id, &git_history.sha, self.conn)? {
+                continue;
+            }
+            // Commits that only changed excluded paths are left out entirely
+            let changed_files = !git_history.files.is_empty();
+            path_filter.apply(&mut git_history);
+            if changed_files && git_history.files.is_empty() && git_history.sha != stop_at_sha {
+                continue;
+            }
+            let is_merge = git_history.is_merge();
             // For each GitHistory
-            // 1. We need to create an Owner from the handle
+            // 1. We need to create an Owner from the handle and email
 
             let owner = NewOwner {
                 handle: git_history.handle,
                 name: None,
                 primary_owner_id: None,
+                // Authors without an email are told apart by their handle
+                email: Some(git_history.email).filter(|e| !e.is_empty()),
             }
             .save_or_load(self.conn)?;
 
@@ -70,6 +138,7 @@ impl<'a> Processor<'a> {
             let commit_date = NaiveDateTime::from_timestamp(git_history.timestamp as i64, 0);
             let sha = git_history.sha.clone();
             if sha == stop_at_sha {
+                stopped_early = true;
                 break;
             }
             let new_commit = NewCommit {
@@ -78,10 +147,41 @@ impl<'a> Processor<'a> {
                 sha: sha.clone(),
                 parent_sha: git_history.parent_sha.clone(),
                 description: git_history.summary.clone(),
+                body: Some(git_history.body.clone()).filter(|b| !b.is_empty()),
+                commit_type: git_history.commit_type.clone(),
                 commit_time: commit_date,
+                committer_handle: Some(git_history.committer_handle.clone()),
+                committer_email: Some(git_history.committer_email.clone())
+                    .filter(|e| !e.is_empty()),
+                committer_time: Some(NaiveDateTime::from_timestamp(
+                    git_history.committer_timestamp as i64,
+                    0,
+                )),
+                is_merge,
             };
             trace!("new_commit = {:?}", new_commit);
             let commit = new_commit.save(self.conn)?;
+            // 2a. Credit everyone named in a Co-authored-by trailer
+            let mut co_authors = vec![];
+            for co_author in git_history.co_authors {
+                let co_owner = NewOwner {
+                    handle: co_author.handle,
+                    name: None,
+                    primary_owner_id: None,
+                    email: Some(co_author.email).filter(|e| !e.is_empty()),
+                }
+                .save_or_load(self.conn)?;
+                if co_owner.id == owner.id || co_authors.iter().any(|o: &Owner| o.id == co_owner.id)
+                {
+                    continue;
+                }
+                CommitCoAuthor {
+                    commit_id: commit.id,
+                    owner_id: co_owner.id,
+                }
+                .save(self.conn)?;
+                co_authors.push(co_owner);
+            }
             // 3. Create the features
             let mut features = vec![];
             let mut source_feature_names = vec![];
@@ -105,17 +205,34 @@ impl<'a> Processor<'a> {
                 source_feature_names.append(&mut git_history.features.clone());
             }
             for feature in source_feature_names {
-                features.push(
-                    NewFeature {
-                        project_id,
-                        name: feature,
-                        description: None,
-                    }
-                    .save(self.conn)?,
-                );
+                let feature = NewFeature {
+                    project_id,
+                    name: feature,
+                    description: None,
+                }
+                .save(self.conn)?;
+                CommitFeature {
+                    commit_id: commit.id,
+                    feature_id: feature.id,
+                }
+                .save(self.conn)?;
+                features.push(feature);
+            }
+            // 4a. Move renamed files to their new path so their features, owners and commits follow them
+            for rename in git_history.renames {
+                if let Ok(file) = File::load_by_path(project_id, rename.from, self.conn) {
+                    file.rename(rename.to, sha.clone(), self.conn)?;
+                }
             }
-            // 4a. We need to extract all of the files and create a new File entry for each that is linked to the project
+            // 4b. We need to extract all of the files and create a new File entry for each that is linked to the project
             for file_path in git_history.files {
+                touched_paths.insert(file_path.clone());
+                let line_change = git_history
+                    .line_changes
+                    .iter()
+                    .find(|l| l.path == file_path)
+                    .cloned()
+                    .unwrap_or_default();
                 let file = NewFile {
                     project_id: project.id,
                     path: file_path,
@@ -123,23 +240,36 @@ impl<'a> Processor<'a> {
                 }
                 .save(self.conn)?;
 
-                // 4b. We need to create a FileOwner for each file
+                // 4c. We need to create a FileOwner for each file, for the author and every co-author
                 NewFileOwner {
                     sha: sha.clone(),
                     file_id: file.id,
                     owner_id: owner.id,
                     action_date: commit_date,
+                    co_author: false,
                 }
                 .save(self.conn)?;
+                for co_author in &co_authors {
+                    NewFileOwner {
+                        sha: sha.clone(),
+                        file_id: file.id,
+                        owner_id: co_author.id,
+                        action_date: commit_date,
+                        co_author: true,
+                    }
+                    .save(self.conn)?;
+                }
 
-                // 4c. We create a FileCommit link for every file
+                // 4d. We create a FileCommit link for every file with the lines it added and removed
                 FileCommit {
                     file_id: file.id,
                     commit_id: commit.id,
+                    additions: line_change.additions,
+                    deletions: line_change.deletions,
                 }
                 .save(self.conn)?;
 
-                // 4d. Attach the features to the files
+                // 4e. Attach the features to the files
                 for feature in &features {
                     let _f = file.add_feature(feature.id, self.conn);
                 }
@@ -148,20 +278,1023 @@ impl<'a> Processor<'a> {
                 "Processed {} of {} - {}",
                 commit_counter,
                 number_of_commits,
-                sha.chars().into_iter().take(7).collect::<String>()
+                sha.chars().take(7).collect::<String>()
             );
             commit_counter += 1;
         }
+        // 5. Record which of the tracked branches contain each commit and, when every
+        // commit was processed, the tips the next fetch can start from
+        for (branch, git_ref) in tracked_refs {
+            let shas = self.git_manager.rev_list(&git_ref)?;
+            CommitRef::save_for_shas(project_id, &branch, &shas, self.conn)?;
+            if !stopped_early {
+                NewProjectRef {
+                    project_id,
+                    ref_name: branch,
+                    sha: self.git_manager.rev_parse(&git_ref)?,
+                }
+                .save(self.conn)?;
+            }
+        }
+        // 6. Store the tags so releases can be compared
+        for git_tag in self.git_manager.tags()? {
+            NewTag {
+                project_id,
+                name: git_tag.name,
+                sha: git_tag.sha,
+                tagged_at: NaiveDateTime::from_timestamp(git_tag.timestamp, 0),
+            }
+            .save(self.conn)?;
+        }
+        // 7. Blame the changed files to find who owns their surviving lines
+        self.update_current_owners(touched_paths)?;
+        // 8. Point aliases at the canonical identities in the mailmaps
+        let merged = self.resolve_mailmap()?;
+        debug!("Merged {} owners through the mailmap", merged);
         debug!("Finished processing project id: {}", project_id);
         Ok((commit_counter - 1, number_of_commits))
     }
 
-    fn get_most_recent_commit(&self) -> Option<NaiveDateTime> {
-        Commit::fetch_latest_for_project(self.project.id, self.conn)
-            .map(|c| c.commit_time)
-            .ok()
+    /// Finds stored commits that no tracked branch reaches any more, which happens when
+    /// upstream history is rewritten, and either deletes them with their file links or
+    /// flags them as unreachable
+    pub fn reconcile_unreachable(&self, prune: bool) -> Result<ReconcileReport, FownerError> {
+        let project_id = self.project.id;
+        let mut reachable = HashSet::new();
+        for (branch, git_ref) in self.tracked_refs()? {
+            let shas = self.git_manager.rev_list(&git_ref)?;
+            // Rewritten branches no longer contain some of the commits linked to them
+            CommitRef::remove_for_ref(project_id, &branch, self.conn)?;
+            CommitRef::save_for_shas(project_id, &branch, &shas, self.conn)?;
+            reachable.extend(shas);
+        }
+        let commits = Commit::flag_unreachable(project_id, &reachable, self.conn)?;
+        if commits.is_empty() {
+            return Ok(ReconcileReport {
+                pruned: prune,
+                ..ReconcileReport::default()
+            });
+        }
+        debug!("{} commits are no longer reachable", commits.len());
+        let paths = Commit::unreachable_paths(project_id, self.conn)?;
+        let links = if prune {
+            Commit::prune_unreachable(project_id, self.conn)?
+        } else {
+            Commit::unreachable_links(project_id, self.conn)?
+        };
+        // The surviving lines of the files may have changed owners
+        self.update_current_owners(paths)?;
+        Ok(ReconcileReport {
+            pruned: prune,
+            commits,
+            links,
+        })
+    }
+
+    /// Stores the share of surviving lines each owner has in the files at HEAD
+    pub fn update_current_owners(&self, paths: HashSet<String>) -> Result<(), FownerError> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let subpaths = self.project.subpaths();
+        let head_sha = self.git_manager.head_sha()?;
+        let head_files: HashSet<String> = self
+            .git_manager
+            .files_at_head()?
+            .iter()
+            .filter_map(|path| subpaths.to_project(path))
+            .collect();
+        debug!("Blaming {} files at {}", paths.len(), head_sha);
+        for path in paths {
+            let file = match File::load_by_path(self.project.id, path.clone(), self.conn) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            // Files that no longer exist at HEAD have no current owners
+            let blame = if head_files.contains(&path) {
+                match self.git_manager.blame(&subpaths.to_repo(&path)) {
+                    Ok(blame) => blame,
+                    // One file git cannot blame must not fail the whole ingest,
+                    // it keeps the current owners it had
+                    Err(e) => {
+                        error!("Could not blame {}: {}", path, e);
+                        continue;
+                    }
+                }
+            } else {
+                vec![]
+            };
+            let mut current_owners = vec![];
+            for git_blame in blame {
+                let owner = NewOwner {
+                    handle: git_blame.handle,
+                    name: None,
+                    primary_owner_id: None,
+                    email: git_blame.email,
+                }
+                .save_or_load(self.conn)?;
+                current_owners.push(NewFileCurrentOwner {
+                    owner_id: owner.id,
+                    line_count: git_blame.line_count,
+                });
+            }
+            FileCurrentOwner::replace_for_file(file.id, &head_sha, current_owners, self.conn)?;
+        }
+        Ok(())
+    }
+
+    /// Points the project's owners at the canonical identities of the project's uploaded
+    /// mailmap and the repository's `.mailmap`, returning how many owners were merged.
+    /// Merges made by hand are left alone.
+    pub fn resolve_mailmap(&self) -> Result<usize, FownerError> {
+        let mut mailmaps = vec![];
+        if let Some(mailmap) = self.project.mailmap()? {
+            mailmaps.push((mailmap, OwnerMergeSource::ProjectMailmap));
+        }
+        if let Some(contents) = self.git_manager.read_at_head(".mailmap")? {
+            match Mailmap::parse(&contents) {
+                Ok(mailmap) => mailmaps.push((mailmap, OwnerMergeSource::Mailmap)),
+                Err(e) => error!("Ignoring the repository's .mailmap: {}", e),
+            }
+        }
+        mailmaps.retain(|(mailmap, _)| !mailmap.is_empty());
+        if mailmaps.is_empty() {
+            return Ok(0);
+        }
+        let mut merged = 0;
+        for owner in Owner::load_by_project(self.project.id, self.conn)? {
+            let email = match &owner.email {
+                Some(email) => email.clone(),
+                None => continue,
+            };
+            let resolved = mailmaps.iter().find_map(|(mailmap, source)| {
+                mailmap
+                    .resolve(&owner.handle, &email)
+                    .map(|identity| (identity, *source))
+            });
+            let (identity, source) = match resolved {
+                Some(resolved) => resolved,
+                None => continue,
+            };
+            // Only the name was corrected, the owner already is the canonical identity
+            if identity.email.eq_ignore_ascii_case(&email) {
+                if let (Some(name), None) = (identity.name, &owner.name) {
+                    owner.update_name(name, self.conn)?;
+                }
+                continue;
+            }
+            let canonical = NewOwner {
+                handle: identity
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| owner.handle.clone()),
+                name: None,
+                primary_owner_id: None,
+                email: Some(identity.email),
+            }
+            .save_or_load(self.conn)?;
+            let primary_owner_id = canonical.primary_owner_id.unwrap_or(canonical.id);
+            if let (Some(name), None) = (identity.name, &canonical.name) {
+                canonical.update_name(name, self.conn)?;
+            }
+            if primary_owner_id == owner.id || owner.primary_owner_id == Some(primary_owner_id) {
+                continue;
+            }
+            if owner.primary_owner_id.is_some()
+                && !OwnerMerge::latest_source(owner.id, self.conn)?
+                    .map(|source| source.is_mailmap())
+                    .unwrap_or_default()
+            {
+                continue;
+            }
+            NewOwnerMerge {
+                owner_id: owner.id,
+                primary_owner_id: Some(primary_owner_id),
+                previous_primary_owner_id: owner.primary_owner_id,
+                source,
+                project_id: Some(self.project.id),
+            }
+            .save(self.conn)?;
+            merged += 1;
+        }
+        Ok(merged)
+    }
+
+    /// Returns the branches to ingest paired with the ref to log for each of them
+    pub fn tracked_refs(&self) -> Result<Vec<(String, String)>, FownerError> {
+        let branches = match &self.project.branches {
+            Some(branches) if !branches.is_empty() => branches.clone(),
+            _ => vec![self.git_manager.current_branch()?],
+        };
+        branches
+            .into_iter()
+            .map(|branch| {
+                let git_ref = self.git_manager.resolve_ref(&branch)?;
+                Ok((branch, git_ref))
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use crate::db::models::commit::Commit;
+    use crate::db::models::file_commit::FileCommit;
+    use crate::db::models::file_current_owner::FileCurrentOwner;
+    use crate::db::models::file_owner::FileOwner;
+    use crate::db::models::owner::{Owner, UpdateOwner};
+    use crate::db::models::owner_merge::{OwnerMerge, OwnerMergeSource};
+    use crate::db::models::project::UpdateProject;
+    use crate::db::models::project_ref::ProjectRef;
+    use crate::db::models::tag::Tag;
+    use crate::db::processor::Processor;
+    use crate::db::stats::contributions_per_owner::contributions_per_owner;
+    use crate::git::backend::GitBackendKind;
+    use crate::git::history::MergePolicy;
+    use crate::test::builders::repo_builder::RepoBuilder;
+    use crate::test::tests::TestHandler;
+    use crate::{Connection, File};
+
+    #[actix_web::test]
+    async fn current_owners() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "one\ntwo\nthree\nfour\n")
+            .write("README.md", "readme\n");
+        repo.commit("Initial commit");
+        repo.write("src/main.rs", "one\n2\n3\nfour\n");
+        repo.commit_as("Keith Simon", "keith@example.com", "Rewrite the middle");
+        repo.git(&["rm", "-q", "README.md"]);
+        repo.commit("Remove the readme");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let file =
+            File::load_by_path(processor.project.id, "src/main.rs".to_string(), &conn).unwrap();
+        let current_owners = FileCurrentOwner::load_by_file(file.id, false, &conn).unwrap();
+        assert_eq!(current_owners.len(), 2);
+        assert_eq!(current_owners[0].line_count, 2);
+        assert_eq!(current_owners[0].share, 0.5);
+        // Both authors touched the file historically
+        assert_eq!(file.owners.len(), 2);
+
+        let readme =
+            File::load_by_path(processor.project.id, "README.md".to_string(), &conn).unwrap();
+        assert!(readme.current_owners.is_empty());
+        assert_eq!(readme.owners, vec!["Krakaw".to_string()]);
+    }
+
+    #[actix_web::test]
+    async fn co_authors() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        let sha = repo.commit(
+            "Pair on main\n\nCo-authored-by: Keith Simon <keith@example.com>\nCo-authored-by: Krakaw <krakaw@example.com>",
+        );
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        // The author listing themselves as a co-author is only credited once
+        let commit = Commit::load_by_sha(sha, &conn).unwrap();
+        assert_eq!(commit.owner_handle, "Krakaw");
+        assert_eq!(commit.co_author_handles, vec!["Keith Simon".to_string()]);
+
+        let file =
+            File::load_by_path(processor.project.id, "src/main.rs".to_string(), &conn).unwrap();
+        let file_owners = FileOwner::load(file.id, None, None, &conn).unwrap();
+        assert_eq!(file_owners.len(), 2);
+        assert_eq!(file_owners.iter().filter(|o| o.co_author).count(), 1);
+    }
+
+    #[actix_web::test]
+    async fn authors_without_emails() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        let first = repo.commit_as("Alice", "", "Add main");
+        repo.write("src/lib.rs", "fn lib() {}\n");
+        let second = repo.commit_as("Bob", "", "Add lib");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        // An empty email must not merge different authors into one owner
+        let first = Commit::load_by_sha(first, &conn).unwrap();
+        let second = Commit::load_by_sha(second, &conn).unwrap();
+        assert_eq!(first.owner_handle, "Alice");
+        assert_eq!(second.owner_handle, "Bob");
+        assert_ne!(first.owner_id, second.owner_id);
+    }
+
+    #[actix_web::test]
+    async fn features_from_body() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/billing.rs", "fn bill() {}\n");
+        let sha = repo.commit("Add billing (#12)\n\n---\nFeatures: [Billing, Invoices]");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let commit = Commit::load_by_sha(sha, &conn).unwrap();
+        assert_eq!(commit.description, "Add billing (#12)");
+        assert_eq!(
+            commit.body,
+            Some("---\nFeatures: [Billing, Invoices]".to_string())
+        );
+        let file =
+            File::load_by_path(processor.project.id, "src/billing.rs".to_string(), &conn).unwrap();
+        assert_eq!(file.feature_names.len(), 2);
+        assert!(file.feature_names.contains(&"Invoices".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn conventional_commits() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/billing.rs", "fn bill() {}\n");
+        let feat = repo.commit("feat(billing): Add invoices [Payments]");
+        repo.write("src/billing.rs", "fn bill() { todo!() }\n");
+        repo.commit("fix(billing,auth)!: Require a session to bill");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let mut processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor.project = processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    conventional_commits: Some(true),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let commit = Commit::load_by_sha(feat, &conn).unwrap();
+        assert_eq!(commit.commit_type, Some("feat".to_string()));
+        let file =
+            File::load_by_path(processor.project.id, "src/billing.rs".to_string(), &conn).unwrap();
+        assert_eq!(file.feature_names.len(), 3);
+        for feature in ["Payments", "billing", "auth"] {
+            assert!(file.feature_names.contains(&feature.to_string()));
+        }
+
+        let (total, fixes) = Commit::search(
+            processor.project.id,
+            None,
+            Some("FIX".to_string()),
+            None,
+            10,
+            0,
+            None,
+            None,
+            &conn,
+        )
+        .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(
+            fixes[0].description,
+            "fix(billing,auth)!: Require a session to bill"
+        );
+    }
+
+    #[actix_web::test]
+    async fn tracked_branches() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        let shared = repo.commit("Initial commit");
+        repo.git(&["checkout", "-q", "-b", "release"]);
+        repo.write("src/hotfix.rs", "fn fix() {}\n");
+        let hotfix = repo.commit("Hotfix on the release");
+        repo.git(&["checkout", "-q", "main"]);
+        repo.write("src/feature.rs", "fn feature() {}\n");
+        let feature = repo.commit("Feature on main");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let mut processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor.project = processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    branches: Some(Some(vec!["main".to_string(), "release".to_string()])),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let shared = Commit::load_by_sha(shared, &conn).unwrap();
+        assert_eq!(shared.refs.len(), 2);
+        let hotfix = Commit::load_by_sha(hotfix, &conn).unwrap();
+        assert_eq!(hotfix.refs, vec!["release".to_string()]);
+        let feature = Commit::load_by_sha(feature, &conn).unwrap();
+        assert_eq!(feature.refs, vec!["main".to_string()]);
+
+        let project_id = processor.project.id;
+        let (total, _) = Commit::search(
+            project_id,
+            None,
+            None,
+            Some("release".to_string()),
+            10,
+            0,
+            None,
+            None,
+            &conn,
+        )
+        .unwrap();
+        assert_eq!(total, 2);
+
+        let release_files = File::search(
+            project_id,
+            "".to_string(),
+            Some("release".to_string()),
+            false,
+            10,
+            0,
+            &conn,
+        )
+        .unwrap();
+        let mut paths: Vec<String> = release_files.into_iter().map(|f| f.path).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["src/hotfix.rs", "src/main.rs"]);
+    }
+
+    #[actix_web::test]
+    async fn tag_changes() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.commit_at(1655391971, "Initial commit [Core]");
+        repo.git(&["tag", "v1.0"]);
+        repo.write("src/billing.rs", "fn bill() {}\n");
+        repo.commit_at(1655391981, "Add billing [Billing]");
+        repo.write("src/auth.rs", "fn login() {}\n");
+        repo.commit_at(1655391991, "Add auth [Auth]");
+        repo.git(&["tag", "-a", "v1.1", "-m", "Release 1.1"]);
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let project_id = processor.project.id;
+        let tags = Tag::load_by_project(project_id, &conn).unwrap();
+        assert_eq!(tags.len(), 2);
+
+        // The previous tag is used when no starting tag is given
+        let release = Tag::load_by_name(project_id, "v1.1", &conn).unwrap();
+        let changes = release.changes_since(None, &conn).unwrap();
+        assert_eq!(changes.from.unwrap().name, "v1.0");
+        let features: Vec<String> = changes.features.into_iter().map(|f| f.name).collect();
+        assert_eq!(features, vec!["Auth", "Billing"]);
+        let mut paths: Vec<String> = changes.files.into_iter().map(|f| f.path).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["src/auth.rs", "src/billing.rs"]);
+        assert_eq!(changes.owners.len(), 1);
+
+        // The first tag includes everything up to it
+        let first = Tag::load_by_name(project_id, "v1.0", &conn).unwrap();
+        let changes = first.changes_since(None, &conn).unwrap();
+        assert!(changes.from.is_none());
+        assert_eq!(changes.files.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn incremental_ingest() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.commit_at(1655391971, "Initial commit");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let processor = Processor::new(git_manager, None, &conn).unwrap();
+        let (commits, _) = processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+        assert_eq!(commits, 1);
+        let refs = ProjectRef::load_by_project(processor.project.id, &conn).unwrap();
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].ref_name, "main");
+
+        // Nothing new to ingest
+        let (commits, _) = processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+        assert_eq!(commits, 0);
+
+        // A rebased commit authored before the last ingested one is still picked up
+        repo.write("src/lib.rs", "fn lib() {}\n");
+        let sha = repo.commit_at(1555391971, "Backdated commit");
+        let (commits, _) = processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+        assert_eq!(commits, 1);
+        assert!(Commit::load_by_sha(sha.clone(), &conn).is_ok());
+        let refs = ProjectRef::load_by_project(processor.project.id, &conn).unwrap();
+        assert_eq!(refs[0].sha, sha);
+    }
+
+    #[actix_web::test]
+    async fn rewritten_history() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        let initial = repo.commit("Initial commit [Core]");
+        repo.write("src/main.rs", "fn main() { bill() }\n");
+        repo.write("src/billing.rs", "fn bill() {}\n");
+        let rewritten = repo.commit("Add billing [Billing]");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Library);
+        let processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+        let project_id = processor.project.id;
+
+        // Force the branch back past the billing commit
+        repo.git(&["reset", "-q", "--hard", &initial]);
+        repo.write("src/main.rs", "fn main() { login() }\n");
+        repo.commit("Add auth [Auth]");
+
+        let report = processor.reconcile_unreachable(false).unwrap();
+        assert!(!report.pruned);
+        assert_eq!(report.commits, vec![rewritten.clone()]);
+        assert_eq!(report.links.file_commits, 2);
+        assert_eq!(report.links.file_owners, 2);
+        assert_eq!(report.links.file_features, 2);
+        assert!(
+            Commit::load_by_sha(rewritten.clone(), &conn)
+                .unwrap()
+                .unreachable
+        );
+
+        let report = processor.reconcile_unreachable(true).unwrap();
+        assert!(report.pruned);
+        assert_eq!(report.commits, vec![rewritten.clone()]);
+        assert_eq!(report.links.file_features, 2);
+        assert!(Commit::load_by_sha(rewritten, &conn).is_err());
+        let main = File::load_by_path(project_id, "src/main.rs".to_string(), &conn).unwrap();
+        assert_eq!(main.feature_names, vec!["Core"]);
+        assert_eq!(main.commit_shas.len(), 1);
+        let billing = File::load_by_path(project_id, "src/billing.rs".to_string(), &conn).unwrap();
+        assert!(billing.feature_names.is_empty());
+        assert!(billing.owners.is_empty());
+
+        // The new history is ingested on top of what survived
+        let (commits, _) = processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+        assert_eq!(commits, 1);
+        let main = File::load_by_path(project_id, "src/main.rs".to_string(), &conn).unwrap();
+        assert_eq!(main.commit_shas.len(), 2);
+        assert!(processor
+            .reconcile_unreachable(true)
+            .unwrap()
+            .commits
+            .is_empty());
+    }
+
+    #[actix_web::test]
+    async fn merge_policy() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.commit("Initial commit");
+        repo.git(&["checkout", "-q", "-b", "side"]);
+        repo.write("src/side.rs", "fn side() {}\n");
+        let side = repo.commit_as("Keith Simon", "keith@example.com", "Side work");
+        repo.git(&["checkout", "-q", "main"]);
+        repo.git(&["merge", "-q", "--no-ff", "-m", "Merge side", "side"]);
+        let merge = repo.git(&["rev-parse", "HEAD"]);
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let mut processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor.project = processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    name: Some("Demo".to_string()),
+                    merge_policy: Some(MergePolicy::FirstParent),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        assert_eq!(
+            processor.project.merge_policy,
+            Some(MergePolicy::FirstParent)
+        );
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        // The merge is credited with the side branch it brought in
+        let project_id = processor.project.id;
+        assert!(!Commit::exists(project_id, &side, &conn).unwrap());
+        let merge = Commit::load_by_sha(merge, &conn).unwrap();
+        assert!(merge.is_merge);
+        let file = File::load_by_path(project_id, "src/side.rs".to_string(), &conn).unwrap();
+        let file_commits = FileCommit::load_by_file(file.id, None, &conn).unwrap();
+        assert_eq!(file_commits.len(), 1);
+        assert_eq!(file_commits[0].commit_id, merge.id);
+
+        let total_contributions = |include_merges: bool| {
+            let contributions = contributions_per_owner(
+                None,
+                Some(project_id),
+                None,
+                None,
+                None,
+                None,
+                false,
+                include_merges,
+                false,
+                &conn,
+            )
+            .unwrap();
+            serde_json::to_value(&contributions[&project_id]).unwrap()["contributions"]
+                .as_object()
+                .unwrap()
+                .values()
+                .map(|c| c["total_contributions"].as_u64().unwrap())
+                .sum::<u64>()
+        };
+        assert_eq!(total_contributions(true), 2);
+        assert_eq!(total_contributions(false), 1);
+    }
+
+    #[actix_web::test]
+    async fn subpaths() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("README.md", "# Platform\n");
+        repo.write("products/billing/src/main.rs", "fn bill() {}\n");
+        repo.write("products/search/src/main.rs", "fn find() {}\n");
+        repo.commit("Initial commit");
+        repo.write("products/billing/src/main.rs", "fn bill() { todo!() }\n");
+        let billing_only = repo.commit_as("Keith Simon", "keith@example.com", "Bill [Billing]");
+        repo.write("README.md", "# Platform\n\nProducts\n");
+        let readme = repo.commit("Describe the products");
+
+        for (backend, subpath) in [
+            (GitBackendKind::Cli, "products/billing"),
+            (GitBackendKind::Library, "products/search"),
+        ] {
+            let git_manager = TestHandler::git_manager(&repo.path, backend);
+            let processor =
+                Processor::new(git_manager, Some(vec![subpath.to_string()]), &conn).unwrap();
+            let (processed, _) = processor
+                .fetch_commits_and_update_db(None, true)
+                .await
+                .unwrap();
+            let project_id = processor.project.id;
+            let files = File::all(project_id, &conn).unwrap();
+            assert_eq!(
+                files
+                    .iter()
+                    .map(|f| f.path.clone())
+                    .collect::<Vec<String>>(),
+                vec!["src/main.rs".to_string()]
+            );
+            assert!(!Commit::exists(project_id, &readme, &conn).unwrap());
+            let current_owners = FileCurrentOwner::load_by_file(files[0].id, false, &conn).unwrap();
+            assert_eq!(current_owners.len(), 1);
+            if subpath == "products/billing" {
+                assert_eq!(processed, 2);
+                assert!(Commit::exists(project_id, &billing_only, &conn).unwrap());
+            } else {
+                assert_eq!(processed, 1);
+                assert!(!Commit::exists(project_id, &billing_only, &conn).unwrap());
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn path_globs() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.write("vendor/lib.rs", "fn lib() {}\n");
+        repo.write("Cargo.lock", "# lock\n");
+        repo.commit("Initial commit");
+        repo.write("Cargo.lock", "# lock\n# bumped\n");
+        let bump = repo.commit("Bump the lockfile");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let mut processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor.project = processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    name: Some("Demo".to_string()),
+                    exclude_globs: Some(Some(vec!["*.lock".to_string()])),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let project_id = processor.project.id;
+        assert!(!Commit::exists(project_id, &bump, &conn).unwrap());
+        let paths = |conn: &Connection| {
+            File::search(project_id, "".to_string(), None, false, 10, 0, conn)
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(paths(&conn), vec!["src/main.rs", "vendor/lib.rs"]);
+
+        // Excluding a directory later hides the files it already ingested
+        processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    exclude_globs: Some(Some(vec!["*.lock".to_string(), "vendor/**".to_string()])),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        assert_eq!(paths(&conn), vec!["src/main.rs"]);
+        let vendor = File::load_by_path(project_id, "vendor/lib.rs".to_string(), &conn).unwrap();
+        assert!(vendor.excluded);
+        let contributions = contributions_per_owner(
+            None,
+            Some(project_id),
+            None,
+            None,
+            None,
+            None,
+            false,
+            true,
+            false,
+            &conn,
+        )
+        .unwrap();
+        let contributions = serde_json::to_value(&contributions[&project_id]).unwrap();
+        let owner = contributions["contributions"]
+            .as_object()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        assert_eq!(owner["total_additions"], 1);
+
+        assert_eq!(File::purge_excluded(project_id, &conn).unwrap(), 1);
+        assert!(File::load_by_path(project_id, "vendor/lib.rs".to_string(), &conn).is_err());
+    }
+
+    #[actix_web::test]
+    async fn bot_owners() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("Cargo.toml", "[package]\n");
+        repo.commit("Initial commit");
+        repo.write("Cargo.toml", "[package]\n[dependencies]\nregex = \"1\"\n");
+        repo.commit_as(
+            "dependabot[bot]",
+            "49699333+dependabot[bot]@users.noreply.github.com",
+            "Bump regex",
+        );
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let mut processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor.project = processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    name: Some("Demo".to_string()),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let project_id = processor.project.id;
+        let bot = Owner::load_by_handle("dependabot[bot]".to_string(), &conn).unwrap();
+        assert!(bot.is_bot);
+        let file = |include_bots: bool| {
+            File::search(project_id, "".to_string(), None, include_bots, 10, 0, &conn)
+                .unwrap()
+                .remove(0)
+        };
+        assert_eq!(file(false).owners, vec!["Krakaw"]);
+        assert_eq!(file(false).current_owners, vec!["Krakaw"]);
+        assert_eq!(file(true).owners.len(), 2);
+        let current_owners = FileCurrentOwner::load_by_file(file(false).id, false, &conn).unwrap();
+        assert_eq!(current_owners.len(), 1);
+
+        let owners = |include_bots: bool| {
+            let contributions = contributions_per_owner(
+                None,
+                Some(project_id),
+                None,
+                None,
+                None,
+                None,
+                false,
+                true,
+                include_bots,
+                &conn,
+            )
+            .unwrap();
+            serde_json::to_value(&contributions[&project_id]).unwrap()["contributions"]
+                .as_object()
+                .unwrap()
+                .len()
+        };
+        assert_eq!(owners(false), 1);
+        assert_eq!(owners(true), 2);
+
+        // Owners marked by hand keep their flag when the patterns change
+        bot.update(
+            UpdateOwner {
+                name: None,
+                primary_owner_id: None,
+                is_bot: Some(false),
+            },
+            &conn,
+        )
+        .unwrap();
+        Owner::flag_bots(&conn).unwrap();
+        assert_eq!(owners(false), 2);
+    }
+
+    #[actix_web::test]
+    async fn mailmap() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.commit("Initial commit");
+        repo.write("src/lib.rs", "fn lib() {}\n");
+        repo.commit_as("kra", "krakaw@old-company.com", "Add lib");
+        repo.write("src/billing.rs", "fn bill() {}\n");
+        repo.commit_as("Jane", "jane@laptop.local", "Add billing");
+        repo.write(
+            ".mailmap",
+            "Keith Krakaw <krakaw@example.com> <krakaw@old-company.com>\n",
+        );
+        repo.commit("Add a mailmap");
+
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let mut processor = Processor::new(git_manager, None, &conn).unwrap();
+        processor.project = processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    name: Some("Demo".to_string()),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        processor
+            .fetch_commits_and_update_db(None, true)
+            .await
+            .unwrap();
+
+        let krakaw = Owner::load_by_email("krakaw@example.com".to_string(), &conn).unwrap();
+        assert_eq!(krakaw.name, Some("Keith Krakaw".to_string()));
+        let alias = Owner::load_by_email("krakaw@old-company.com".to_string(), &conn).unwrap();
+        assert_eq!(alias.primary_owner_id, Some(krakaw.id));
+        let merges = OwnerMerge::load_by_owner(alias.id, &conn).unwrap();
+        assert_eq!(merges.len(), 1);
+        assert_eq!(merges[0].source, OwnerMergeSource::Mailmap);
+        assert_eq!(merges[0].project_id, Some(processor.project.id));
+
+        // Uploaded mailmaps are applied alongside the repository's
+        processor.project = processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    mailmap: Some("Jane Doe <jane@example.com> <jane@laptop.local>".to_string()),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .unwrap();
+        assert_eq!(processor.resolve_mailmap().unwrap(), 1);
+        let jane = Owner::load_by_email("jane@laptop.local".to_string(), &conn).unwrap();
+        let merges = OwnerMerge::load_by_owner(jane.id, &conn).unwrap();
+        assert_eq!(merges[0].source, OwnerMergeSource::ProjectMailmap);
+
+        let project_id = processor.project.id;
+        let contributions = contributions_per_owner(
+            None,
+            Some(project_id),
+            None,
+            None,
+            None,
+            None,
+            false,
+            true,
+            false,
+            &conn,
+        )
+        .unwrap();
+        let contributions = serde_json::to_value(&contributions[&project_id]).unwrap();
+        assert_eq!(contributions["contributions"].as_object().unwrap().len(), 2);
+
+        // Merges made by hand are never replaced by a mailmap
+        let other = Owner::load_by_handle("Krakaw".to_string(), &conn).unwrap();
+        let jane = jane
+            .update(
+                UpdateOwner {
+                    name: None,
+                    primary_owner_id: Some(other.id),
+                    is_bot: None,
+                },
+                &conn,
+            )
+            .unwrap();
+        assert_eq!(processor.resolve_mailmap().unwrap(), 0);
+        assert_eq!(
+            Owner::load(jane.id, &conn).unwrap().primary_owner_id,
+            Some(other.id)
+        );
+        let merges = OwnerMerge::load_by_owner(jane.id, &conn).unwrap();
+        assert_eq!(merges[0].source, OwnerMergeSource::Manual);
+        assert_eq!(merges.len(), 2);
+
+        assert!(processor
+            .project
+            .clone()
+            .update(
+                UpdateProject {
+                    mailmap: Some("<jane@example.com".to_string()),
+                    ..UpdateProject::default()
+                },
+                &conn,
+            )
+            .is_err());
+    }
+}
@@ -24,18 +24,27 @@ pub struct Contributions {
     owner_id: u32,
     owner_handle: String,
     total_contributions: usize,
+    total_authored: usize,
+    total_co_authored: usize,
+    total_additions: u64,
+    total_deletions: u64,
     contribution_counts: Vec<ContributionCount>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ContributionCount {
     commit_count: u64,
+    authored_count: u64,
+    co_authored_count: u64,
+    additions: u64,
+    deletions: u64,
     commit_time: ContributionDateTime,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default)]
 pub enum TimeBreakdown {
     #[serde(rename = "daily")]
+    #[default]
     Daily,
     #[serde(rename = "monthly")]
     Monthly,
@@ -43,12 +52,6 @@ pub enum TimeBreakdown {
     Yearly,
 }
 
-impl Default for TimeBreakdown {
-    fn default() -> Self {
-        Self::Daily
-    }
-}
-
 impl From<String> for TimeBreakdown {
     fn from(t: String) -> Self {
         let t = t.to_lowercase();
@@ -72,13 +75,17 @@ impl From<TimeBreakdown> for String {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn contributions_per_owner(
     owner_id: Option<u32>,
     project_id: Option<u32>,
     start: Option<NaiveDate>,
     end: Option<NaiveDate>,
     time_breakdown: Option<TimeBreakdown>,
+    commit_type: Option<String>,
     merge_projects: bool,
+    include_merges: bool,
+    include_bots: bool,
     conn: &Connection,
 ) -> Result<HashMap<u32, ContributionResponse>, FownerError> {
     let time_breakdown = time_breakdown.unwrap_or_default();
@@ -89,15 +96,37 @@ pub fn contributions_per_owner(
            coalesce(o.primary_owner_id, o.id)                       as owner_id,
            coalesce(po.handle, o.handle)                            as handle,
            strftime('{date_format}', datetime(commit_time, 'unixepoch')) as commit_time_string,
-           COUNT(c.id)                                              AS commit_count
-    FROM commits c
+           COUNT(c.id)                                              AS commit_count,
+           SUM(ca.co_authored = 0)                                  AS authored_count,
+           SUM(ca.co_authored)                                      AS co_authored_count,
+           COALESCE(SUM(fc.additions), 0)                           AS additions,
+           COALESCE(SUM(fc.deletions), 0)                           AS deletions
+    -- Authors and co-authors are both credited with the commit
+    FROM (SELECT id AS commit_id, owner_id, 0 AS co_authored FROM commits
+          UNION ALL
+          SELECT commit_id, owner_id, 1 AS co_authored FROM commit_co_authors) ca
+             JOIN commits c on ca.commit_id = c.id
              JOIN projects p on c.project_id = p.id
-             JOIN owners o on c.owner_id = o.id
+             JOIN owners o on ca.owner_id = o.id
              LEFT JOIN owners po ON o.primary_owner_id = po.id
+             LEFT JOIN (SELECT commit_id, SUM(additions) AS additions, SUM(deletions) AS deletions
+                        FROM file_commits
+                            INNER JOIN files ON files.id = file_commits.file_id
+                        WHERE files.excluded = 0
+                        GROUP BY commit_id) fc ON fc.commit_id = c.id
     WHERE (?1 IS NULL OR (o.id = ?1 OR o.primary_owner_id = ?1))
     AND (?2 IS NULL OR c.project_id = ?2)
     AND (?3 IS NULL OR commit_time >= ?3)
     AND (?4 IS NULL OR commit_time <= ?4)
+    AND (?5 IS NULL OR c.commit_type = LOWER(?5))
+    AND (?6 OR c.is_merge = 0)
+    AND (?7 OR (o.is_bot = 0 AND coalesce(po.is_bot, 0) = 0))
+    -- Commits that only touched excluded files are left out
+    AND (NOT EXISTS (SELECT 1 FROM file_commits efc WHERE efc.commit_id = c.id)
+         OR EXISTS (SELECT 1
+                    FROM file_commits efc
+                        INNER JOIN files ef ON ef.id = efc.file_id
+                    WHERE efc.commit_id = c.id AND ef.excluded = 0))
 
     GROUP BY {group_projects} coalesce(o.primary_owner_id, o.id), strftime('{date_format}', datetime(commit_time, 'unixepoch'))
     ORDER BY commit_time;
@@ -115,7 +144,10 @@ pub fn contributions_per_owner(
         owner_id,
         project_id,
         start.map(|s| s.and_hms(0, 0, 0).timestamp()),
-        end.map(|e| e.and_hms(23, 59, 59).timestamp())
+        end.map(|e| e.and_hms(23, 59, 59).timestamp()),
+        commit_type,
+        include_merges,
+        include_bots
     ])?;
     let mut result = HashMap::new();
     while let Some(row) = rows.next()? {
@@ -134,7 +166,11 @@ pub fn contributions_per_owner(
 
         let commit_date = NaiveDate::from_str(&date_string)?;
         let commit_count = row.get_unwrap(5);
-        let mut contribution_response = result.entry(project_id).or_insert(ContributionResponse {
+        let authored_count: u64 = row.get_unwrap(6);
+        let co_authored_count: u64 = row.get_unwrap(7);
+        let additions: u64 = row.get_unwrap(8);
+        let deletions: u64 = row.get_unwrap(9);
+        let contribution_response = result.entry(project_id).or_insert(ContributionResponse {
             project_id,
             project_name,
             start: commit_date,
@@ -147,20 +183,32 @@ pub fn contributions_per_owner(
         }
         let contribution_count = ContributionCount {
             commit_count,
+            authored_count,
+            co_authored_count,
+            additions,
+            deletions,
             commit_time: commit_time_string,
         };
-        let mut contributions = contribution_response
+        let contributions = contribution_response
             .contributions
             .entry(owner_id)
             .or_insert(Contributions {
                 owner_id,
                 owner_handle,
                 total_contributions: 0,
+                total_authored: 0,
+                total_co_authored: 0,
+                total_additions: 0,
+                total_deletions: 0,
                 contribution_counts: vec![],
             });
 
         contributions.contribution_counts.push(contribution_count);
         contributions.total_contributions += commit_count as usize;
+        contributions.total_authored += authored_count as usize;
+        contributions.total_co_authored += co_authored_count as usize;
+        contributions.total_additions += additions;
+        contributions.total_deletions += deletions;
     }
     Ok(result)
 }
@@ -0,0 +1,510 @@
+use std::collections::{HashMap, HashSet};
+
+use r2d2_sqlite::rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::owner::Owner;
+use crate::{Connection, FownerError};
+
+/// Pairs scoring below this are not suggested unless a lower `min_score` is requested
+pub const DEFAULT_MIN_SCORE: f64 = 0.5;
+
+/// Local parts shared by unrelated people, they never count as a match
+const GENERIC_LOCAL_PARTS: &[&str] = &[
+    "admin",
+    "bot",
+    "build",
+    "ci",
+    "contact",
+    "dev",
+    "developer",
+    "git",
+    "github",
+    "hello",
+    "info",
+    "mail",
+    "me",
+    "noreply",
+    "root",
+    "user",
+];
+
+/// Why two owners look like the same person
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DuplicateReason {
+    /// The names are the same once case and punctuation are ignored, `john.smith` and `John Smith`
+    SameName { name: String, other_name: String },
+    /// One name is the initial and surname of the other, `jsmith` and `John Smith`
+    Initials { short: String, full: String },
+    /// The names differ by a typo or two, `Jon Smith` and `John Smith`
+    SimilarName {
+        name: String,
+        other_name: String,
+        similarity: f64,
+    },
+    /// Both emails have the same local part, `jsmith@work.com` and `jsmith@home.com`
+    SameEmailLocalPart { local_part: String },
+    /// One email's local part is the other's name, `john.smith@work.com` and `John Smith`
+    EmailMatchesName { local_part: String, name: String },
+    /// The share of the files either owner touched that both touched
+    SharedFiles { shared: usize, jaccard: f64 },
+    /// Days both owners committed on
+    OverlappingCommitDays { shared_days: usize },
+}
+
+impl DuplicateReason {
+    fn score(&self) -> f64 {
+        match self {
+            DuplicateReason::SameName { .. } => 0.6,
+            DuplicateReason::Initials { .. } => 0.4,
+            DuplicateReason::SimilarName { similarity, .. } => 0.4 * similarity,
+            DuplicateReason::SameEmailLocalPart { .. } => 0.5,
+            DuplicateReason::EmailMatchesName { .. } => 0.4,
+            DuplicateReason::SharedFiles { jaccard, .. } => 0.2 * jaccard,
+            DuplicateReason::OverlappingCommitDays { .. } => 0.1,
+        }
+    }
+
+    /// Shared files and commit days only strengthen a match on names or emails
+    fn is_identity(&self) -> bool {
+        !matches!(
+            self,
+            DuplicateReason::SharedFiles { .. } | DuplicateReason::OverlappingCommitDays { .. }
+        )
+    }
+}
+
+/// Two owners that are likely the same person and the reasons they matched
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DuplicateOwnerMatch {
+    pub owner_id: u32,
+    pub other_owner_id: u32,
+    pub score: f64,
+    pub reasons: Vec<DuplicateReason>,
+}
+
+/// Owners that are likely the same person, accepting the suggestion points the others at
+/// `primary_owner_id`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DuplicateOwnerGroup {
+    /// The owner with the most commits
+    pub primary_owner_id: u32,
+    pub owners: Vec<Owner>,
+    /// The best score of the matches in the group
+    pub score: f64,
+    pub matches: Vec<DuplicateOwnerMatch>,
+}
+
+/// What an owner is called and when and where they committed
+struct OwnerProfile {
+    owner: Owner,
+    names: Vec<Vec<String>>,
+    local_part: Option<String>,
+    files: HashSet<u32>,
+    days: HashSet<i64>,
+    commits: usize,
+}
+
+/// Suggests groups of owners that are likely the same person, from their names, email local
+/// parts, and the files and days they committed on.
+///
+/// Only owners without a primary owner are compared, activity of their aliases counts as
+/// theirs. `project_id` limits the owners to those of the project.
+pub fn duplicate_owners(
+    project_id: Option<u32>,
+    min_score: f64,
+    conn: &Connection,
+) -> Result<Vec<DuplicateOwnerGroup>, FownerError> {
+    let owners = match project_id {
+        Some(project_id) => Owner::load_by_project(project_id, conn)?,
+        None => Owner::all(conn)?,
+    };
+    let mut profiles: Vec<OwnerProfile> = owners
+        .into_iter()
+        .filter(|owner| owner.primary_owner_id.is_none())
+        .map(|owner| OwnerProfile {
+            names: [Some(owner.handle.clone()), owner.name.clone()]
+                .into_iter()
+                .flatten()
+                .map(|name| tokens(&name))
+                .filter(|tokens| !tokens.is_empty())
+                .collect(),
+            local_part: owner.email.as_deref().and_then(local_part),
+            files: HashSet::new(),
+            days: HashSet::new(),
+            commits: 0,
+            owner,
+        })
+        .collect();
+    let index: HashMap<u32, usize> = profiles
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.owner.id, i))
+        .collect();
+
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT DISTINCT coalesce(o.primary_owner_id, o.id), fo.file_id
+        FROM file_owners fo
+                 INNER JOIN owners o ON o.id = fo.owner_id
+                 INNER JOIN files f ON f.id = fo.file_id
+        WHERE ?1 IS NULL OR f.project_id = ?1
+        "#,
+    )?;
+    let mut rows = stmt.query(params![project_id])?;
+    while let Some(row) = rows.next()? {
+        if let Some(i) = index.get(&row.get::<_, u32>(0)?) {
+            profiles[*i].files.insert(row.get(1)?);
+        }
+    }
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT coalesce(o.primary_owner_id, o.id), c.commit_time / 86400
+        FROM commits c
+                 INNER JOIN owners o ON o.id = c.owner_id
+        WHERE ?1 IS NULL OR c.project_id = ?1
+        "#,
+    )?;
+    let mut rows = stmt.query(params![project_id])?;
+    while let Some(row) = rows.next()? {
+        if let Some(i) = index.get(&row.get::<_, u32>(0)?) {
+            profiles[*i].days.insert(row.get(1)?);
+            profiles[*i].commits += 1;
+        }
+    }
+
+    let mut matches = vec![];
+    for (i, profile) in profiles.iter().enumerate() {
+        for other in profiles.iter().skip(i + 1) {
+            if let Some(duplicate) = compare(profile, other) {
+                if duplicate.score >= min_score {
+                    matches.push(duplicate);
+                }
+            }
+        }
+    }
+    Ok(group(matches, &profiles, &index))
+}
+
+/// Scores a pair of owners, `None` when nothing about their names or emails matches
+fn compare(profile: &OwnerProfile, other: &OwnerProfile) -> Option<DuplicateOwnerMatch> {
+    let mut reasons = vec![];
+    if let Some(reason) = name_reason(&profile.names, &other.names) {
+        reasons.push(reason);
+    }
+    match (&profile.local_part, &other.local_part) {
+        (Some(local_part), Some(other_local_part)) if local_part == other_local_part => {
+            reasons.push(DuplicateReason::SameEmailLocalPart {
+                local_part: local_part.clone(),
+            });
+        }
+        _ => {
+            let email_matches_name = |local_part: &Option<String>, names: &[Vec<String>]| {
+                let local_part = local_part.as_ref()?;
+                names
+                    .iter()
+                    .find(|name| name.len() > 1 && &name.concat() == local_part)
+                    .map(|name| DuplicateReason::EmailMatchesName {
+                        local_part: local_part.clone(),
+                        name: name.join(" "),
+                    })
+            };
+            if let Some(reason) = email_matches_name(&profile.local_part, &other.names)
+                .or_else(|| email_matches_name(&other.local_part, &profile.names))
+            {
+                reasons.push(reason);
+            }
+        }
+    }
+    if !reasons.iter().any(DuplicateReason::is_identity) {
+        return None;
+    }
+    let shared = profile.files.intersection(&other.files).count();
+    if shared > 0 {
+        let jaccard = shared as f64 / profile.files.union(&other.files).count() as f64;
+        reasons.push(DuplicateReason::SharedFiles {
+            shared,
+            jaccard: round(jaccard),
+        });
+    }
+    let shared_days = profile.days.intersection(&other.days).count();
+    if shared_days > 0 {
+        reasons.push(DuplicateReason::OverlappingCommitDays { shared_days });
+    }
+    let score = reasons.iter().map(DuplicateReason::score).sum::<f64>();
+    Some(DuplicateOwnerMatch {
+        owner_id: profile.owner.id,
+        other_owner_id: other.owner.id,
+        score: round(score.min(1.0)),
+        reasons,
+    })
+}
+
+/// The strongest match between any of the names of two owners
+fn name_reason(names: &[Vec<String>], other_names: &[Vec<String>]) -> Option<DuplicateReason> {
+    let mut best: Option<DuplicateReason> = None;
+    for name in names {
+        for other_name in other_names {
+            let (key, other_key) = (name.concat(), other_name.concat());
+            let reason = if key.len() >= 3 && key == other_key {
+                Some(DuplicateReason::SameName {
+                    name: name.join(" "),
+                    other_name: other_name.join(" "),
+                })
+            } else if let Some((short, full)) = initials(name, other_name) {
+                Some(DuplicateReason::Initials { short, full })
+            } else {
+                let similarity = similarity(&key, &other_key);
+                (key.len() >= 5 && similarity >= 0.85).then(|| DuplicateReason::SimilarName {
+                    name: name.join(" "),
+                    other_name: other_name.join(" "),
+                    similarity: round(similarity),
+                })
+            };
+            if let Some(reason) = reason {
+                if best
+                    .as_ref()
+                    .map(|b| reason.score() > b.score())
+                    .unwrap_or(true)
+                {
+                    best = Some(reason);
+                }
+            }
+        }
+    }
+    best
+}
+
+/// `jsmith` or `johns` against `John Smith`
+fn initials(name: &[String], other_name: &[String]) -> Option<(String, String)> {
+    let (short, full) = match (name, other_name) {
+        ([short], full) | (full, [short]) if full.len() > 1 => (short, full),
+        _ => return None,
+    };
+    let first = full.first()?;
+    let last = full.last()?;
+    let candidates = [
+        format!("{}{}", first.chars().next()?, last),
+        format!("{}{}", first, last.chars().next()?),
+    ];
+    (short.len() >= 3 && candidates.contains(short)).then(|| (short.clone(), full.join(" ")))
+}
+
+/// Merges overlapping pairs into groups led by the owner with the most commits
+fn group(
+    matches: Vec<DuplicateOwnerMatch>,
+    profiles: &[OwnerProfile],
+    index: &HashMap<u32, usize>,
+) -> Vec<DuplicateOwnerGroup> {
+    let mut parents: Vec<usize> = (0..profiles.len()).collect();
+    fn find(parents: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        parents[i] = root;
+        root
+    }
+    for duplicate in &matches {
+        let a = find(&mut parents, index[&duplicate.owner_id]);
+        let b = find(&mut parents, index[&duplicate.other_owner_id]);
+        parents[a] = b;
+    }
+    let mut groups: HashMap<usize, Vec<DuplicateOwnerMatch>> = HashMap::new();
+    for duplicate in matches {
+        let root = find(&mut parents, index[&duplicate.owner_id]);
+        groups.entry(root).or_default().push(duplicate);
+    }
+    let mut groups: Vec<DuplicateOwnerGroup> = groups
+        .into_values()
+        .map(|matches| {
+            let mut members: Vec<&OwnerProfile> = matches
+                .iter()
+                .flat_map(|m| [m.owner_id, m.other_owner_id])
+                .collect::<HashSet<u32>>()
+                .into_iter()
+                .map(|id| &profiles[index[&id]])
+                .collect();
+            members.sort_by(|a, b| b.commits.cmp(&a.commits).then(a.owner.id.cmp(&b.owner.id)));
+            DuplicateOwnerGroup {
+                primary_owner_id: members[0].owner.id,
+                owners: members.iter().map(|p| p.owner.clone()).collect(),
+                score: matches.iter().map(|m| m.score).fold(0.0, f64::max),
+                matches,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.primary_owner_id.cmp(&b.primary_owner_id))
+    });
+    groups
+}
+
+/// Lowercase words of a name, splitting on punctuation and camel case
+fn tokens(name: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut previous_lowercase = false;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            previous_lowercase = false;
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if c.is_uppercase() && previous_lowercase && !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        previous_lowercase = c.is_lowercase();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// The local part of an email without `+tags`, using the login of GitHub noreply addresses
+fn local_part(email: &str) -> Option<String> {
+    let local_part = email.split('@').next()?;
+    let local_part = match local_part.split_once('+') {
+        Some((id, login)) if id.chars().all(|c| c.is_ascii_digit()) => login,
+        Some((local_part, _)) => local_part,
+        None => local_part,
+    };
+    let key = tokens(local_part).concat();
+    (key.len() >= 3 && !GENERIC_LOCAL_PARTS.contains(&key.as_str())).then_some(key)
+}
+
+/// One minus the edit distance relative to the longer string
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::models::owner::NewOwner;
+    use crate::db::models::owner_merge::{OwnerMerge, OwnerMergeSource};
+    use crate::db::stats::duplicate_owners::{
+        duplicate_owners, local_part, similarity, tokens, DuplicateReason, DEFAULT_MIN_SCORE,
+    };
+    use crate::test::tests::TestHandler;
+    use crate::Connection;
+
+    #[test]
+    fn names_and_emails() {
+        assert_eq!(tokens("John Smith"), vec!["john", "smith"]);
+        assert_eq!(tokens("john.smith"), vec!["john", "smith"]);
+        assert_eq!(tokens("JohnSmith"), vec!["john", "smith"]);
+        assert_eq!(tokens("jsmith"), vec!["jsmith"]);
+        assert_eq!(
+            local_part("12345+jsmith@users.noreply.github.com"),
+            Some("jsmith".to_string())
+        );
+        assert_eq!(
+            local_part("john.smith+fowner@example.com"),
+            Some("johnsmith".to_string())
+        );
+        assert_eq!(local_part("root@localhost"), None);
+        assert!(similarity("jonsmith", "johnsmith") > 0.85);
+        assert!(similarity("jane", "john") < 0.5);
+    }
+
+    #[test]
+    fn suggestions() {
+        let handler = TestHandler::init();
+        let conn = Connection::try_from(&handler.db).unwrap();
+        let owner = |handle: &str, email: &str| {
+            NewOwner {
+                handle: handle.to_string(),
+                name: None,
+                primary_owner_id: None,
+                email: Some(email.to_string()),
+            }
+            .save(&conn)
+            .unwrap()
+        };
+        let john = owner("John Smith", "john@example.com");
+        let jsmith = owner("jsmith", "jsmith@home.net");
+        let dotted = owner("john.smith", "jsmith@old-company.com");
+        let jane = owner("Jane Doe", "jane@example.com");
+
+        let groups = duplicate_owners(None, DEFAULT_MIN_SCORE, &conn).unwrap();
+        assert_eq!(groups.len(), 1);
+        let mut ids: Vec<u32> = groups[0].owners.iter().map(|o| o.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![john.id, jsmith.id, dotted.id]);
+        assert!(!ids.contains(&jane.id));
+        let reasons = |a: u32, b: u32| {
+            groups[0]
+                .matches
+                .iter()
+                .find(|m| [m.owner_id, m.other_owner_id] == [a, b])
+                .unwrap()
+                .reasons
+                .clone()
+        };
+        assert!(matches!(
+            reasons(john.id, dotted.id)[0],
+            DuplicateReason::SameName { .. }
+        ));
+        assert!(
+            reasons(jsmith.id, dotted.id).contains(&DuplicateReason::SameEmailLocalPart {
+                local_part: "jsmith".to_string()
+            })
+        );
+
+        // Accepting the suggestion merges the group and its aliases into the primary owner
+        let alias = NewOwner {
+            handle: "J. Smith".to_string(),
+            name: None,
+            primary_owner_id: Some(jsmith.id),
+            email: None,
+        }
+        .save(&conn)
+        .unwrap();
+        let merged = OwnerMerge::merge_into(
+            john.id,
+            &[jsmith.id, dotted.id],
+            OwnerMergeSource::Suggestion,
+            &conn,
+        )
+        .unwrap();
+        assert_eq!(merged.len(), 3);
+        assert!(merged.iter().all(|o| o.primary_owner_id == Some(john.id)));
+        let merges = OwnerMerge::load_by_owner(alias.id, &conn).unwrap();
+        assert_eq!(merges[0].previous_primary_owner_id, Some(jsmith.id));
+        assert_eq!(merges[0].source, OwnerMergeSource::Suggestion);
+        assert!(duplicate_owners(None, DEFAULT_MIN_SCORE, &conn)
+            .unwrap()
+            .is_empty());
+    }
+}
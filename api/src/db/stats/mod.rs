@@ -1 +1,2 @@
 pub mod contributions_per_owner;
+pub mod duplicate_owners;
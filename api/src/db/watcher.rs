@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use actix_web::rt::time::sleep;
+use log::{debug, info, warn};
+
+use crate::db::models::commit::Commit;
+use crate::db::models::feature::Feature;
+use crate::db::models::owner::Owner;
+use crate::db::processor::Processor;
+use crate::db::{Connection, Db};
+use crate::errors::FownerError;
+use crate::git::manager::GitManager;
+
+/// What an ingest triggered by a change to the watched refs stored
+#[derive(Debug, Default)]
+pub struct WatchSummary {
+    pub commits: usize,
+    /// Commits dropped from the tracked branches since the previous ingest
+    pub unreachable: usize,
+    pub features: Vec<String>,
+    pub owners: Vec<String>,
+}
+
+/// Polls a local repository's tracked refs and ingests the new commits whenever they move
+pub struct Watcher {
+    db: Db,
+    git_manager: GitManager,
+    subpaths: Option<Vec<String>>,
+    interval: Duration,
+    fetch_github_labels: bool,
+    prune_unreachable: bool,
+    /// The tracked refs and the shas they pointed to at the last ingest
+    tips: Option<Vec<(String, String)>>,
+}
+
+impl Watcher {
+    pub fn new(
+        db: Db,
+        git_manager: GitManager,
+        subpaths: Option<Vec<String>>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            db,
+            git_manager,
+            subpaths,
+            interval,
+            fetch_github_labels: false,
+            prune_unreachable: false,
+            tips: None,
+        }
+    }
+
+    pub fn with_github_labels(mut self, fetch_github_labels: bool) -> Self {
+        self.fetch_github_labels = fetch_github_labels;
+        self
+    }
+
+    pub fn with_prune_unreachable(mut self, prune_unreachable: bool) -> Self {
+        self.prune_unreachable = prune_unreachable;
+        self
+    }
+
+    /// Polls until an error that is not a git one, a failed poll is retried on the next tick
+    /// since the repository can be mid-commit or mid-rebase
+    pub async fn run(&mut self) -> Result<(), FownerError> {
+        info!(
+            "Watching {} every {:?}",
+            self.git_manager.path.display(),
+            self.interval
+        );
+        loop {
+            match self.poll().await {
+                Ok(Some(summary)) => {
+                    if summary.unreachable > 0 {
+                        info!("{} commits are no longer reachable", summary.unreachable);
+                    }
+                    info!(
+                        "Ingested {} new commits, features: [{}], owners: [{}]",
+                        summary.commits,
+                        summary.features.join(", "),
+                        summary.owners.join(", ")
+                    )
+                }
+                Ok(None) => {}
+                Err(
+                    e @ (FownerError::Execution(_)
+                    | FownerError::Git2(_)
+                    | FownerError::GitError(_)
+                    | FownerError::GitTimeout(_, _)),
+                ) => {
+                    warn!("Could not read the repository: {}", e)
+                }
+                Err(e) => return Err(e),
+            }
+            sleep(self.interval).await;
+        }
+    }
+
+    /// Ingests the new commits when the tracked refs moved since the last poll,
+    /// the first poll always ingests to catch up with the repository
+    pub async fn poll(&mut self) -> Result<Option<WatchSummary>, FownerError> {
+        let tips = self.current_tips()?;
+        if self.tips.as_ref() == Some(&tips) {
+            return Ok(None);
+        }
+        debug!("Tracked refs moved to {:?}", tips);
+        let summary = self.ingest().await?;
+        // Recorded before the ingest, commits landing meanwhile trigger the next poll
+        self.tips = Some(tips);
+        Ok(Some(summary))
+    }
+
+    fn processor<'a>(&self, conn: &'a Connection) -> Result<Processor<'a>, FownerError> {
+        // `GitManager::clone` clones the repository, this copies the manager
+        let git_manager = Clone::clone(&self.git_manager);
+        Processor::new(git_manager, self.subpaths.clone(), conn)
+    }
+
+    fn current_tips(&self) -> Result<Vec<(String, String)>, FownerError> {
+        let conn = Connection::try_from(&self.db)?;
+        let processor = self.processor(&conn)?;
+        processor
+            .tracked_refs()?
+            .into_iter()
+            .map(|(branch, git_ref)| {
+                let sha = self.git_manager.rev_parse(&git_ref)?;
+                Ok((branch, sha))
+            })
+            .collect()
+    }
+
+    async fn ingest(&self) -> Result<WatchSummary, FownerError> {
+        let mut db = self.db.pool.get()?;
+        let tx = db.transaction()?;
+        let conn = Connection::from(tx);
+        let processor = self.processor(&conn)?;
+        let project_id = processor.project.id;
+        let latest_id = Commit::latest_id(project_id, &conn)?;
+        let reconciled = processor.reconcile_unreachable(self.prune_unreachable)?;
+        let (commits, _) = processor
+            .fetch_commits_and_update_db(None, !self.fetch_github_labels)
+            .await?;
+        let summary = WatchSummary {
+            commits,
+            unreachable: reconciled.commits.len(),
+            features: Feature::load_by_commits_after(project_id, latest_id, &conn)?
+                .into_iter()
+                .map(|f| f.name)
+                .collect(),
+            owners: Owner::load_by_commits_after(project_id, latest_id, &conn)?
+                .into_iter()
+                .map(|o| o.handle)
+                .collect(),
+        };
+        drop(processor);
+        conn.transaction()?.commit()?;
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::db::watcher::Watcher;
+    use crate::git::backend::GitBackendKind;
+    use crate::test::builders::repo_builder::RepoBuilder;
+    use crate::test::tests::TestHandler;
+
+    #[actix_web::test]
+    async fn ingests_new_commits() {
+        let handler = TestHandler::init();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.commit("Initial commit [Core]");
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let mut watcher = Watcher::new(
+            handler.db.clone(),
+            git_manager,
+            None,
+            Duration::from_millis(10),
+        );
+
+        let summary = watcher.poll().await.unwrap().unwrap();
+        assert_eq!(summary.commits, 1);
+        assert_eq!(summary.features, vec!["Core".to_string()]);
+        assert!(watcher.poll().await.unwrap().is_none());
+
+        repo.write("src/billing.rs", "fn bill() {}\n");
+        repo.commit_as("Pair", "pair@example.com", "Add billing [Billing]");
+        let summary = watcher.poll().await.unwrap().unwrap();
+        assert_eq!(summary.commits, 1);
+        assert_eq!(summary.unreachable, 0);
+        assert_eq!(summary.features, vec!["Billing".to_string()]);
+        assert_eq!(summary.owners, vec!["Pair".to_string()]);
+        assert!(watcher.poll().await.unwrap().is_none());
+    }
+}
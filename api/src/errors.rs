@@ -1,7 +1,9 @@
 use std::num::ParseIntError;
 use std::string::FromUtf8Error;
+use std::time::Duration;
 
 use actix_web::http::uri::InvalidUri;
+use actix_web::http::StatusCode;
 use thiserror::Error;
 
 #[allow(dead_code)]
@@ -28,23 +30,137 @@ pub enum FownerError {
     #[error("JSON Parse Error: {0}")]
     JsonParse(#[from] serde_json::Error),
     #[error("HTTP Error: {0}")]
-    ActixError(#[from] actix_web::Error),
+    ActixError(String),
     #[error("Git Error: {0}")]
     GitError(String),
+    #[error("Git Authentication Error: {0}")]
+    GitAuthentication(String),
+    #[error("Git command timed out after {1:?}: {0}")]
+    GitTimeout(String, Duration),
+    #[error("Libgit2 Error: {0}")]
+    Git2(#[from] git2::Error),
     #[error("Github Error: {0}")]
     GithubError(String),
     #[error("Invalid URI: {0}")]
     InvalidUri(#[from] InvalidUri),
     #[error("AWC Send Request Error: {0}")]
-    AwcSendRequest(#[from] awc::error::SendRequestError),
+    AwcSendRequest(String),
     #[error("AWC JSON Payload Error: {0}")]
-    AwcPayload(#[from] awc::error::JsonPayloadError),
+    AwcPayload(String),
     #[error("File Cannot Have Features: {0}")]
     FileCannotHaveFeatures(String),
     #[error("Invalid Date String: {0}")]
     ParseDate(#[from] chrono::ParseError),
+    #[error("Invalid Feature Pattern: {0}")]
+    InvalidFeaturePattern(String),
+    #[error("Invalid Path Glob: {0}")]
+    InvalidPathGlob(String),
+    #[error("Invalid Bot Pattern: {0}")]
+    InvalidBotPattern(String),
+    #[error("Invalid Subpath, it must be a relative path inside the repository: {0}")]
+    InvalidSubpath(String),
+    #[error("Invalid Mailmap: {0}")]
+    InvalidMailmap(String),
+    #[error("Invalid Branch Name: {0}")]
+    InvalidBranch(String),
+    #[error("Invalid Remote: {0}")]
+    InvalidRemote(String),
+    #[error("Hook Already Exists, pass --force to replace it: {0}")]
+    HookExists(String),
+    #[error("Secret Key Error: {0}")]
+    SecretKey(String),
     #[error("Internal Error: {0}")]
     Internal(String),
 }
 
-impl actix_web::error::ResponseError for FownerError {}
+// The HTTP client errors are kept as messages so errors can be sent between threads,
+// git work runs off the actix workers
+impl From<actix_web::Error> for FownerError {
+    fn from(e: actix_web::Error) -> Self {
+        FownerError::ActixError(e.to_string())
+    }
+}
+
+impl From<awc::error::SendRequestError> for FownerError {
+    fn from(e: awc::error::SendRequestError) -> Self {
+        FownerError::AwcSendRequest(e.to_string())
+    }
+}
+
+impl From<awc::error::JsonPayloadError> for FownerError {
+    fn from(e: awc::error::JsonPayloadError) -> Self {
+        FownerError::AwcPayload(e.to_string())
+    }
+}
+
+impl actix_web::error::ResponseError for FownerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            // The project's credentials were rejected by the remote or cannot be used
+            FownerError::GitAuthentication(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            FownerError::GitTimeout(_, _) => StatusCode::GATEWAY_TIMEOUT,
+            FownerError::InvalidFeaturePattern(_)
+            | FownerError::InvalidSubpath(_)
+            | FownerError::InvalidPathGlob(_)
+            | FownerError::InvalidBotPattern(_)
+            | FownerError::InvalidMailmap(_)
+            | FownerError::InvalidBranch(_)
+            | FownerError::InvalidRemote(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+
+    use crate::errors::FownerError;
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(
+            FownerError::GitAuthentication("denied".to_string()).status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            FownerError::GitTimeout("git fetch".to_string(), Duration::from_secs(1)).status_code(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+        assert_eq!(
+            FownerError::InvalidFeaturePattern("(unclosed".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            FownerError::InvalidSubpath("../other".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            FownerError::InvalidPathGlob("src/[".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            FownerError::InvalidBotPattern("(unclosed".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            FownerError::InvalidMailmap("line 1: <broken".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            FownerError::InvalidBranch("main branch".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            FownerError::InvalidRemote("a b".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            FownerError::Internal("oops".to_string()).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -0,0 +1,635 @@
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, trace};
+
+use crate::git::backend::{GitBackend, GitHistoryIter, DEFAULT_GIT_TIMEOUT};
+use crate::git::blame::GitBlame;
+use crate::git::credentials::{authentication_error, CredentialFiles, GitCredentials};
+use crate::git::features::FeatureExtractor;
+use crate::git::history::{GitDiffStats, GitHistory, GitHistoryParser, MergePolicy};
+use crate::git::remote::{CloneMode, RemoteConfig, MANAGED_REMOTE_KEY};
+use crate::git::tag::GitTag;
+use crate::FownerError;
+
+const GIT_HISTORY_LOG_FORMAT: &str =
+    "---%n%an%n%ae%n%cn%n%ce%n%H%n%P%n%ad%n%cd%n%(trailers:key=Co-authored-by,valueonly,separator=%x1F)%n%s%n%x02%b%x03";
+
+/// The longest wait between checks on a running command
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lines of `git log` read ahead of the commit being processed
+const HISTORY_LINE_BUFFER: usize = 1024;
+
+/// Runs the `git` binary in the repository and parses its output
+/// Commands are killed once they run longer than the timeout, the streamed `git log` once it
+/// goes longer than the timeout without producing the next commit
+pub struct CliBackend {
+    path: PathBuf,
+    credentials: Option<GitCredentials>,
+    timeout: Duration,
+}
+
+impl CliBackend {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            credentials: None,
+            timeout: DEFAULT_GIT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Authenticates clones and fetches with the project's credentials
+    pub fn with_credentials(mut self, credentials: Option<GitCredentials>) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    fn git(&self) -> Command {
+        let mut command = Command::new("git");
+        // Nobody is there to answer a prompt, fail instead of waiting for the timeout
+        // Paths are written as they are, `unquote` handles the few git still quotes
+        command
+            .current_dir(&self.path)
+            .args(["-c", "core.quotepath=off"])
+            .env("GIT_TERMINAL_PROMPT", "0");
+        // ssh prompts for host keys and passphrases itself, unless an ssh command was configured
+        if std::env::var_os("GIT_SSH_COMMAND").is_none() && std::env::var_os("GIT_SSH").is_none() {
+            command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+        }
+        command
+    }
+
+    /// A `git` command that talks to the remote, authenticated with the project's credentials
+    /// when it has them, the files must be kept until the command finishes
+    fn remote_git(&self) -> Result<(Command, Option<CredentialFiles>), FownerError> {
+        let mut command = self.git();
+        let files = match &self.credentials {
+            Some(credentials) => Some(credentials.configure(&mut command)?),
+            None => None,
+        };
+        Ok((command, files))
+    }
+
+    /// Like `stdout`, but reports rejected or missing credentials as an authentication error
+    fn remote_stdout(output: Output) -> Result<String, FownerError> {
+        Self::stdout(output).map_err(|e| match e {
+            FownerError::Execution(stderr) => {
+                authentication_error(&stderr).unwrap_or(FownerError::Execution(stderr))
+            }
+            e => e,
+        })
+    }
+
+    /// Returns stdout, or the stderr as an error when git failed
+    fn stdout(output: Output) -> Result<String, FownerError> {
+        if !output.status.success() {
+            return Err(FownerError::Execution(String::from_utf8(output.stderr)?));
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    fn config(&self, key: &str, value: &str) -> Result<(), FownerError> {
+        Self::stdout(
+            self.git()
+                .args(["config", key, value])
+                .output_within(self.timeout)?,
+        )?;
+        Ok(())
+    }
+
+    /// Adds the project's extra remotes, updates their urls and removes the extra remotes
+    /// no longer configured, remotes fowner did not add are left alone
+    fn sync_remotes(&self, remotes: &RemoteConfig) -> Result<(), FownerError> {
+        let existing = Self::stdout(self.git().arg("remote").output_within(self.timeout)?)?;
+        let existing: Vec<&str> = existing.lines().collect();
+        for remote in &remotes.remotes {
+            if existing.contains(&remote.name.as_str()) {
+                Self::stdout(
+                    self.git()
+                        .args(["remote", "set-url", &remote.name, &remote.url])
+                        .output_within(self.timeout)?,
+                )?;
+                continue;
+            }
+            Self::stdout(
+                self.git()
+                    .args(["remote", "add", &remote.name, &remote.url])
+                    .output_within(self.timeout)?,
+            )?;
+            self.config(
+                &format!("remote.{}.fetch", remote.name),
+                &remote.fetch_refspec(),
+            )?;
+            self.config(
+                &format!("remote.{}.{}", remote.name, MANAGED_REMOTE_KEY),
+                "true",
+            )?;
+        }
+        let names = remotes.names();
+        for name in existing {
+            if names.iter().any(|n| n == name) {
+                continue;
+            }
+            let managed = self
+                .git()
+                .args(["config", "--bool"])
+                .arg(format!("remote.{}.{}", name, MANAGED_REMOTE_KEY))
+                .output_within(self.timeout)?;
+            if Self::stdout(managed).unwrap_or_default().trim() == "true" {
+                Self::stdout(
+                    self.git()
+                        .args(["remote", "remove", name])
+                        .output_within(self.timeout)?,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The revision walking arguments shared by `log` and `rev-list`
+    fn revisions(
+        refs: &[String],
+        exclude: &[String],
+        merge_policy: Option<MergePolicy>,
+    ) -> Vec<String> {
+        let mut args = vec![];
+        match merge_policy {
+            Some(MergePolicy::FirstParent) => args.push("--first-parent".to_string()),
+            Some(MergePolicy::Ignore) => args.push("--no-merges".to_string()),
+            Some(MergePolicy::DiffFirstParent) | None => {}
+        }
+        if refs.is_empty() {
+            args.push("HEAD".to_string());
+        }
+        args.extend(refs.iter().cloned());
+        args.extend(exclude.iter().map(|sha| format!("^{}", sha)));
+        args
+    }
+
+    /// The pathspecs that limit `log` and `rev-list`, the whole repository when empty
+    fn pathspecs(paths: &[String]) -> Vec<String> {
+        let mut args = vec!["--".to_string()];
+        if paths.is_empty() {
+            args.push(".".to_string());
+        }
+        args.extend(paths.iter().cloned());
+        args
+    }
+}
+
+impl GitBackend for CliBackend {
+    fn is_valid_repo(&self) -> bool {
+        // `status` needs a working tree, bare and mirror clones only have a git dir
+        match self
+            .git()
+            .args(["rev-parse", "--git-dir"])
+            .output_within(self.timeout)
+        {
+            Ok(output) => output.status.success(),
+            Err(e) => {
+                debug!("Checking if repo is valid failed {:?}", e);
+                false
+            }
+        }
+    }
+
+    fn clone_repo(&self, url: &str, remotes: &RemoteConfig) -> Result<(), FownerError> {
+        let (mut command, _files) = self.remote_git()?;
+        command.args(["clone", "--origin", &remotes.name]);
+        match remotes.clone_mode {
+            CloneMode::Checkout => {}
+            CloneMode::Bare => {
+                command.arg("--bare");
+            }
+            CloneMode::Mirror => {
+                command.arg("--mirror");
+            }
+        }
+        let result = command.arg(url).arg(".").output_within(self.timeout)?;
+        // Bound first, `debug!` skips its arguments when debug logging is off
+        let output = Self::remote_stdout(result)?;
+        debug!("{}", output);
+        if remotes.clone_mode == CloneMode::Bare {
+            // Bare clones have no fetch refspec, fetch branches straight into the local ones
+            self.config(
+                &format!("remote.{}.fetch", remotes.name),
+                remotes.clone_mode.fetch_refspec().unwrap_or_default(),
+            )?;
+        }
+        if !remotes.remotes.is_empty() {
+            self.fetch(remotes)?;
+        }
+        Ok(())
+    }
+
+    fn fetch(&self, remotes: &RemoteConfig) -> Result<(), FownerError> {
+        self.sync_remotes(remotes)?;
+        let (mut command, _files) = self.remote_git()?;
+        let result = command
+            .args(["fetch", "--prune", "--multiple"])
+            .args(remotes.names())
+            .output_within(self.timeout)?;
+        Self::remote_stdout(result)?;
+        if remotes.clone_mode != CloneMode::Checkout {
+            // The fetch already moved the branches, there is no working tree to update
+            return Ok(());
+        }
+        // A pull cannot fast-forward over rewritten history, so reset to the upstream instead
+        let result = self
+            .git()
+            .args(["reset", "--hard", "@{upstream}"])
+            .output_within(self.timeout)?;
+        let output = Self::stdout(result)?;
+        debug!("{}", output);
+        Ok(())
+    }
+
+    fn cleanup(&self, remotes: &RemoteConfig) -> Result<String, FownerError> {
+        let (mut command, _files) = self.remote_git()?;
+        Self::remote_stdout(
+            command
+                .args(["remote", "prune"])
+                .args(remotes.names())
+                .output_within(self.timeout)?,
+        )
+    }
+
+    fn history<'a>(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        paths: &[String],
+        merge_policy: Option<MergePolicy>,
+        feature_extractor: &'a FeatureExtractor,
+    ) -> Result<GitHistoryIter<'a>, FownerError> {
+        let mut args = vec![
+            "--no-pager".to_string(),
+            "log".to_string(),
+            "--reverse".to_string(),
+            "--raw".to_string(),
+            "--numstat".to_string(),
+            "--find-renames".to_string(),
+            format!("--pretty=format:{}", GIT_HISTORY_LOG_FORMAT),
+            "--date=unix".to_string(),
+        ];
+        if merge_policy.is_some() {
+            args.push("--diff-merges=first-parent".to_string());
+        }
+        args.extend(Self::revisions(refs, exclude, merge_policy));
+        let mut command = self.git();
+        command.args(args).args(Self::pathspecs(paths));
+        let description = describe(&command);
+        trace!("{}", description);
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| FownerError::GitError("git log has no stdout".to_string()))?;
+        let stderr = read_in_background(child.stderr.take());
+        Ok(Box::new(GitHistoryStream {
+            child,
+            lines: read_lines_in_background(stdout),
+            stderr: Some(stderr),
+            parser: GitHistoryParser::new(feature_extractor),
+            description,
+            timeout: self.timeout,
+            finished: false,
+            reaped: false,
+        }))
+    }
+
+    fn count_history(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        paths: &[String],
+        merge_policy: Option<MergePolicy>,
+    ) -> Result<usize, FownerError> {
+        let result = self
+            .git()
+            .args(["rev-list", "--count"])
+            .args(Self::revisions(refs, exclude, merge_policy))
+            .args(Self::pathspecs(paths))
+            .output_within(self.timeout)?;
+        Ok(Self::stdout(result)?.trim().parse()?)
+    }
+
+    fn diff_stats(&self, sha: &str) -> Result<GitDiffStats, FownerError> {
+        let result = self
+            .git()
+            .args([
+                "--no-pager",
+                "show",
+                "--raw",
+                "--numstat",
+                "--find-renames",
+                "--format=",
+                sha,
+            ])
+            .output_within(self.timeout)?;
+        let mut stats = GitDiffStats::default();
+        for line in Self::stdout(result)?.lines() {
+            let line = line.trim();
+            if !line.is_empty() {
+                stats.parse_line(line);
+            }
+        }
+        Ok(stats)
+    }
+
+    fn blame(&self, path: &str) -> Result<Vec<GitBlame>, FownerError> {
+        let result = self
+            .git()
+            .args(["blame", "--line-porcelain", "HEAD", "--", path])
+            .output_within(self.timeout)?;
+        if !result.status.success() {
+            return Err(FownerError::Execution(String::from_utf8(result.stderr)?));
+        }
+        Ok(GitBlame::parse(&String::from_utf8_lossy(&result.stdout)))
+    }
+
+    fn tags(&self) -> Result<Vec<GitTag>, FownerError> {
+        let result = self
+            .git()
+            .args([
+                "for-each-ref",
+                "refs/tags",
+                "--format=%(refname:short)%09%(*objectname)%09%(objectname)%09%(creatordate:unix)",
+            ])
+            .output_within(self.timeout)?;
+        Ok(GitTag::parse(&Self::stdout(result)?))
+    }
+
+    fn current_branch(&self) -> Result<String, FownerError> {
+        let result = self
+            .git()
+            .args(["rev-parse", "--abbrev-ref", "HEAD"])
+            .output_within(self.timeout)?;
+        Ok(Self::stdout(result)?.trim().to_string())
+    }
+
+    fn verify_ref(&self, git_ref: &str) -> Option<String> {
+        let result = self
+            .git()
+            .args(["rev-parse", "--verify", "--quiet"])
+            .arg(format!("{}^{{commit}}", git_ref))
+            .output_within(self.timeout)
+            .ok()?;
+        result.status.success().then(|| git_ref.to_string())
+    }
+
+    fn rev_list(&self, git_ref: &str) -> Result<Vec<String>, FownerError> {
+        let result = self
+            .git()
+            .args(["rev-list", git_ref, "--"])
+            .output_within(self.timeout)?;
+        Ok(Self::stdout(result)?.lines().map(String::from).collect())
+    }
+
+    fn rev_parse(&self, git_ref: &str) -> Result<String, FownerError> {
+        let result = self
+            .git()
+            .args(["rev-parse", "--verify"])
+            .arg(format!("{}^{{commit}}", git_ref))
+            .output_within(self.timeout)?;
+        Ok(Self::stdout(result)?.trim().to_string())
+    }
+
+    fn commit_exists(&self, sha: &str) -> Result<bool, FownerError> {
+        let result = self
+            .git()
+            .args(["cat-file", "-e"])
+            .arg(format!("{}^{{commit}}", sha))
+            .output_within(self.timeout)?;
+        Ok(result.status.success())
+    }
+
+    fn files_at_head(&self) -> Result<Vec<String>, FownerError> {
+        let result = self
+            .git()
+            .args(["ls-tree", "-r", "-z", "--name-only", "HEAD"])
+            .output_within(self.timeout)?;
+        // `-z` keeps paths with newlines or non-ASCII characters unquoted
+        Ok(Self::stdout(result)?
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn read_at_head(&self, path: &str) -> Result<Option<String>, FownerError> {
+        let object = format!("HEAD:{}", path);
+        let exists = self
+            .git()
+            .args(["cat-file", "-e"])
+            .arg(&object)
+            .output_within(self.timeout)?;
+        if !exists.status.success() {
+            return Ok(None);
+        }
+        let result = self
+            .git()
+            .args(["cat-file", "blob"])
+            .arg(&object)
+            .output_within(self.timeout)?;
+        Ok(Some(Self::stdout(result)?))
+    }
+
+    fn staged_files(&self) -> Result<Vec<String>, FownerError> {
+        let result = self
+            .git()
+            .args(["diff", "--cached", "--name-only", "--no-renames", "-z"])
+            .output_within(self.timeout)?;
+        Ok(Self::stdout(result)?
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn hooks_dir(&self) -> Result<PathBuf, FownerError> {
+        let result = self
+            .git()
+            .args(["rev-parse", "--git-path", "hooks"])
+            .output_within(self.timeout)?;
+        // Relative to the repository unless the hooks path is configured as an absolute one
+        Ok(self.path.join(Self::stdout(result)?.trim()))
+    }
+}
+
+/// Runs a command to completion like `Command::output`, killing it once it outlives `timeout`
+trait OutputWithin {
+    fn output_within(&mut self, timeout: Duration) -> Result<Output, FownerError>;
+}
+
+impl OutputWithin for Command {
+    fn output_within(&mut self, timeout: Duration) -> Result<Output, FownerError> {
+        let description = describe(self);
+        trace!("{}", description);
+        let mut child = self
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| FownerError::GitError(format!("Failed to run {}: {}", description, e)))?;
+        // Read both pipes while waiting so a chatty command cannot fill one and stall
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        let deadline = Instant::now() + timeout;
+        let mut interval = Duration::from_millis(1);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                // The readers are left behind, helpers git started may still hold the pipes
+                return Err(FownerError::GitTimeout(description, timeout));
+            }
+            thread::sleep(interval);
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        };
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Sends each line of `pipe` as it is read, at most `HISTORY_LINE_BUFFER` ahead of the receiver
+fn read_lines_in_background<R: Read + Send + 'static>(
+    pipe: R,
+) -> Receiver<std::io::Result<String>> {
+    let (sender, receiver) = sync_channel(HISTORY_LINE_BUFFER);
+    thread::spawn(move || {
+        for line in BufReader::new(pipe).lines() {
+            // The stream was dropped
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// The command line of a git command, for logs and timeout errors
+fn describe(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Yields each commit of a running `git log` as it is read from stdout
+pub struct GitHistoryStream<'a> {
+    child: Child,
+    lines: Receiver<std::io::Result<String>>,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    parser: GitHistoryParser<'a>,
+    description: String,
+    /// How long `next` waits for git to produce the next commit
+    timeout: Duration,
+    finished: bool,
+    /// The child was waited on, nothing is left to stop
+    reaped: bool,
+}
+
+impl<'a> GitHistoryStream<'a> {
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.reaped = true;
+    }
+}
+
+impl<'a> Iterator for GitHistoryStream<'a> {
+    type Item = Result<GitHistory, FownerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let line = match self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.finished = true;
+                    self.kill();
+                    return Some(Err(FownerError::GitTimeout(
+                        self.description.clone(),
+                        self.timeout,
+                    )));
+                }
+            };
+            let parsed = line
+                .map_err(FownerError::from)
+                .and_then(|line| self.parser.parse_line(&line));
+            match parsed {
+                Ok(None) => continue,
+                Ok(Some(history)) => return Some(Ok(history)),
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.finished = true;
+        let status = match self.child.wait() {
+            Ok(status) => status,
+            Err(e) => return Some(Err(e.into())),
+        };
+        self.reaped = true;
+        if !status.success() {
+            let stderr = self
+                .stderr
+                .take()
+                .and_then(|stderr| stderr.join().ok())
+                .unwrap_or_default();
+            return Some(Err(FownerError::Execution(
+                String::from_utf8_lossy(&stderr).to_string(),
+            )));
+        }
+        self.parser.finish().map(Ok)
+    }
+}
+
+impl<'a> Drop for GitHistoryStream<'a> {
+    /// Stops git when the stream is abandoned before the end of the log or after an error
+    fn drop(&mut self) {
+        if !self.reaped {
+            self.kill();
+        }
+    }
+}
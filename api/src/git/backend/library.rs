@@ -0,0 +1,638 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use git2::build::RepoBuilder;
+use git2::{
+    BlameOptions, Branch, Delta, DiffFindOptions, DiffOptions, Direction, ErrorCode, FetchOptions,
+    FetchPrune, ObjectType, Oid, Patch, RemoteCallbacks, Repository, ResetType, Sort, TreeWalkMode,
+    TreeWalkResult,
+};
+
+use crate::git::backend::{GitBackend, GitHistoryIter, DEFAULT_GIT_TIMEOUT};
+use crate::git::blame::GitBlame;
+use crate::git::credentials::{map_git2_error, GitCredentials};
+use crate::git::features::FeatureExtractor;
+use crate::git::history::{
+    clamp_timestamp, GitDiffStats, GitHistory, GitIdentity, GitLineChange, GitRename, MergePolicy,
+};
+use crate::git::remote::{CloneMode, RemoteConfig, DEFAULT_REMOTE, MANAGED_REMOTE_KEY};
+use crate::git::tag::GitTag;
+use crate::FownerError;
+
+/// Reads the repository in-process through libgit2
+/// Clones and fetches are cancelled once they run longer than the timeout, reads of the local
+/// repository run in-process and cannot be interrupted
+#[derive(Clone)]
+pub struct LibraryBackend {
+    path: PathBuf,
+    credentials: Option<GitCredentials>,
+    timeout: Duration,
+}
+
+impl LibraryBackend {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            credentials: None,
+            timeout: DEFAULT_GIT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Authenticates clones and fetches with the project's credentials
+    pub fn with_credentials(mut self, credentials: Option<GitCredentials>) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    fn remote_callbacks(&self, deadline: Instant) -> RemoteCallbacks<'_> {
+        let mut callbacks = match &self.credentials {
+            Some(credentials) => credentials.remote_callbacks(),
+            None => RemoteCallbacks::new(),
+        };
+        // Returning false cancels the transfer, checked whenever the remote sends data
+        callbacks.transfer_progress(move |_| Instant::now() < deadline);
+        callbacks.sideband_progress(move |_| Instant::now() < deadline);
+        callbacks
+    }
+
+    fn fetch_options(&self, deadline: Instant) -> FetchOptions<'_> {
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(self.remote_callbacks(deadline));
+        options
+    }
+
+    /// Runs a clone, fetch or prune on a worker thread and stops waiting for it at the deadline
+    /// libgit2 only checks for cancellation when the remote sends data, so a remote that stops
+    /// answering leaves the worker behind until the connection is dropped
+    fn on_worker<T: Send + 'static>(
+        &self,
+        operation: String,
+        run: impl FnOnce(LibraryBackend, Instant) -> Result<T, FownerError> + Send + 'static,
+    ) -> Result<T, FownerError> {
+        let deadline = Instant::now() + self.timeout;
+        let backend = self.clone();
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let _ = sender.send(run(backend, deadline));
+        });
+        match receiver.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(FownerError::GitTimeout(operation, self.timeout)),
+            Err(RecvTimeoutError::Disconnected) => Err(FownerError::GitError(format!(
+                "{} stopped unexpectedly",
+                operation
+            ))),
+        }
+    }
+
+    fn clone_within(
+        &self,
+        url: &str,
+        remotes: &RemoteConfig,
+        deadline: Instant,
+    ) -> Result<(), FownerError> {
+        let repo = RepoBuilder::new()
+            .bare(remotes.clone_mode != CloneMode::Checkout)
+            .fetch_options(self.fetch_options(deadline))
+            .clone(url, &self.path)
+            .map_err(|e| self.remote_error(e, format!("clone {}", url), deadline))?;
+        if remotes.name != DEFAULT_REMOTE {
+            repo.remote_rename(DEFAULT_REMOTE, &remotes.name)?;
+        }
+        if let Some(refspec) = remotes.clone_mode.fetch_refspec() {
+            // libgit2 only sets HEAD when cloning with the default refspec, so switch to the
+            // bare or mirror refspec afterwards and drop the remote tracking branches
+            let mut config = repo.config()?;
+            config.set_str(&format!("remote.{}.fetch", remotes.name), refspec)?;
+            if remotes.clone_mode == CloneMode::Mirror {
+                config.set_bool(&format!("remote.{}.mirror", remotes.name), true)?;
+            }
+            let tracking = format!("refs/remotes/{}/*", remotes.name);
+            for reference in repo.references_glob(&tracking)? {
+                reference?.delete()?;
+            }
+        }
+        if remotes.clone_mode != CloneMode::Checkout || !remotes.remotes.is_empty() {
+            self.fetch_within(remotes, deadline)?;
+        }
+        Ok(())
+    }
+
+    fn fetch_within(&self, remotes: &RemoteConfig, deadline: Instant) -> Result<(), FownerError> {
+        let repo = self.repo()?;
+        Self::sync_remotes(&repo, remotes)?;
+        for name in remotes.names() {
+            let mut remote = repo.find_remote(&name)?;
+            let mut options = self.fetch_options(deadline);
+            options.prune(FetchPrune::On);
+            remote
+                .fetch(&[] as &[&str], Some(&mut options), None)
+                .map_err(|e| self.remote_error(e, format!("fetch {}", name), deadline))?;
+        }
+        if remotes.clone_mode != CloneMode::Checkout {
+            // The fetch already moved the branches, there is no working tree to update
+            return Ok(());
+        }
+        // Reset rather than merge so rewritten upstream history is followed
+        let upstream = Branch::wrap(repo.head()?)
+            .upstream()?
+            .into_reference()
+            .peel_to_commit()?;
+        repo.reset(upstream.as_object(), ResetType::Hard, None)?;
+        Ok(())
+    }
+
+    fn cleanup_within(&self, remotes: &RemoteConfig, deadline: Instant) -> Result<(), FownerError> {
+        let repo = self.repo()?;
+        for name in remotes.names() {
+            let mut remote = repo.find_remote(&name)?;
+            let mut connection = remote
+                .connect_auth(
+                    Direction::Fetch,
+                    Some(self.remote_callbacks(deadline)),
+                    None,
+                )
+                .map_err(|e| self.remote_error(e, format!("prune {}", name), deadline))?;
+            connection.remote().prune(None)?;
+            drop(connection);
+            remote.disconnect()?;
+        }
+        Ok(())
+    }
+
+    /// Reports operations cancelled at the deadline as timeouts
+    fn remote_error(&self, e: git2::Error, operation: String, deadline: Instant) -> FownerError {
+        if Instant::now() >= deadline {
+            FownerError::GitTimeout(operation, self.timeout)
+        } else {
+            map_git2_error(e)
+        }
+    }
+
+    /// Adds the project's extra remotes, updates their urls and removes the extra remotes
+    /// no longer configured, remotes fowner did not add are left alone
+    fn sync_remotes(repo: &Repository, remotes: &RemoteConfig) -> Result<(), FownerError> {
+        let existing: Vec<String> = repo.remotes()?.iter().flatten().map(String::from).collect();
+        for remote in &remotes.remotes {
+            if existing.contains(&remote.name) {
+                repo.remote_set_url(&remote.name, &remote.url)?;
+                continue;
+            }
+            repo.remote_with_fetch(&remote.name, &remote.url, &remote.fetch_refspec())?;
+            repo.config()?.set_bool(
+                &format!("remote.{}.{}", remote.name, MANAGED_REMOTE_KEY),
+                true,
+            )?;
+        }
+        let names = remotes.names();
+        let config = repo.config()?;
+        for name in existing {
+            let managed = config
+                .get_bool(&format!("remote.{}.{}", name, MANAGED_REMOTE_KEY))
+                .unwrap_or_default();
+            if managed && !names.contains(&name) {
+                repo.remote_delete(&name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn repo(&self) -> Result<Repository, FownerError> {
+        Ok(Repository::open(&self.path)?)
+    }
+
+    /// The commits reachable from `refs` but not `exclude`, in `sort` order
+    fn walk(
+        repo: &Repository,
+        refs: &[String],
+        exclude: &[String],
+        paths: &[String],
+        merge_policy: Option<MergePolicy>,
+        sorting: Sort,
+    ) -> Result<Vec<Oid>, FownerError> {
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(sorting)?;
+        if merge_policy == Some(MergePolicy::FirstParent) {
+            revwalk.simplify_first_parent()?;
+        }
+        if refs.is_empty() {
+            revwalk.push_head()?;
+        }
+        for git_ref in refs {
+            revwalk.push(repo.revparse_single(git_ref)?.peel_to_commit()?.id())?;
+        }
+        for sha in exclude {
+            revwalk.hide(Oid::from_str(sha)?)?;
+        }
+        let oids = revwalk.collect::<Result<Vec<Oid>, git2::Error>>()?;
+        if merge_policy != Some(MergePolicy::Ignore) && paths.is_empty() {
+            return Ok(oids);
+        }
+        let mut walked = vec![];
+        for oid in oids {
+            let commit = repo.find_commit(oid)?;
+            if merge_policy == Some(MergePolicy::Ignore) && commit.parent_count() > 1 {
+                continue;
+            }
+            let first_parent = merge_policy == Some(MergePolicy::FirstParent);
+            if paths.is_empty() || Self::touches(repo, &commit, paths, first_parent)? {
+                walked.push(oid);
+            }
+        }
+        Ok(walked)
+    }
+
+    fn diff_options(paths: &[String]) -> DiffOptions {
+        let mut options = DiffOptions::new();
+        for path in paths {
+            options.pathspec(path);
+        }
+        options
+    }
+
+    /// Like `git log -- <paths>`, a commit is kept unless it matches one of its parents
+    /// under the paths
+    fn touches(
+        repo: &Repository,
+        commit: &git2::Commit,
+        paths: &[String],
+        first_parent: bool,
+    ) -> Result<bool, FownerError> {
+        let tree = commit.tree()?;
+        if commit.parent_count() == 0 {
+            let diff =
+                repo.diff_tree_to_tree(None, Some(&tree), Some(&mut Self::diff_options(paths)))?;
+            return Ok(diff.deltas().len() > 0);
+        }
+        let parents = if first_parent {
+            1
+        } else {
+            commit.parent_count()
+        };
+        for idx in 0..parents {
+            let parent_tree = commit.parent(idx)?.tree()?;
+            let diff = repo.diff_tree_to_tree(
+                Some(&parent_tree),
+                Some(&tree),
+                Some(&mut Self::diff_options(paths)),
+            )?;
+            if diff.deltas().len() == 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn commit_diff_stats(
+        repo: &Repository,
+        commit: &git2::Commit,
+        paths: &[String],
+        diff_merges: bool,
+    ) -> Result<GitDiffStats, FownerError> {
+        let mut stats = GitDiffStats::default();
+        // Like `git log --raw`, merges are only diffed against their first parent when asked to
+        if commit.parent_count() > 1 && !diff_merges {
+            return Ok(stats);
+        }
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+        let mut diff = repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut Self::diff_options(paths)),
+        )?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+        let path = |file: git2::DiffFile| {
+            file.path()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        for (idx, delta) in diff.deltas().enumerate() {
+            let to = path(delta.new_file());
+            if delta.status() == Delta::Renamed {
+                stats.renames.push(GitRename {
+                    from: path(delta.old_file()),
+                    to: to.clone(),
+                });
+            }
+            // Binary files have no lines, matching the `-` numstat reports for them
+            let (additions, deletions) = match Patch::from_diff(&diff, idx)? {
+                Some(patch) => {
+                    let (_, additions, deletions) = patch.line_stats()?;
+                    (additions as u32, deletions as u32)
+                }
+                None => (0, 0),
+            };
+            stats.files.push(to.clone());
+            stats.line_changes.push(GitLineChange {
+                path: to,
+                additions,
+                deletions,
+            });
+        }
+        Ok(stats)
+    }
+}
+
+impl GitBackend for LibraryBackend {
+    fn is_valid_repo(&self) -> bool {
+        Repository::open(&self.path).is_ok()
+    }
+
+    fn clone_repo(&self, url: &str, remotes: &RemoteConfig) -> Result<(), FownerError> {
+        let url = url.to_string();
+        let remotes = remotes.clone();
+        self.on_worker(format!("clone {}", url), move |backend, deadline| {
+            backend.clone_within(&url, &remotes, deadline)
+        })
+    }
+
+    fn fetch(&self, remotes: &RemoteConfig) -> Result<(), FownerError> {
+        let remotes = remotes.clone();
+        self.on_worker("fetch".to_string(), move |backend, deadline| {
+            backend.fetch_within(&remotes, deadline)
+        })
+    }
+
+    fn cleanup(&self, remotes: &RemoteConfig) -> Result<String, FownerError> {
+        let remotes = remotes.clone();
+        self.on_worker("prune".to_string(), move |backend, deadline| {
+            backend.cleanup_within(&remotes, deadline)
+        })?;
+        Ok(String::new())
+    }
+
+    fn history<'a>(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        paths: &[String],
+        merge_policy: Option<MergePolicy>,
+        feature_extractor: &'a FeatureExtractor,
+    ) -> Result<GitHistoryIter<'a>, FownerError> {
+        let repo = self.repo()?;
+        let oids = Self::walk(
+            &repo,
+            refs,
+            exclude,
+            paths,
+            merge_policy,
+            // The order `git log --reverse` uses, so both backends ingest commits alike
+            Sort::TIME | Sort::REVERSE,
+        )?;
+        Ok(Box::new(LibraryHistory {
+            repo,
+            oids: oids.into_iter(),
+            paths: paths.to_vec(),
+            diff_merges: merge_policy.is_some(),
+            feature_extractor,
+        }))
+    }
+
+    fn count_history(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        paths: &[String],
+        merge_policy: Option<MergePolicy>,
+    ) -> Result<usize, FownerError> {
+        Ok(Self::walk(
+            &self.repo()?,
+            refs,
+            exclude,
+            paths,
+            merge_policy,
+            Sort::NONE,
+        )?
+        .len())
+    }
+
+    fn diff_stats(&self, sha: &str) -> Result<GitDiffStats, FownerError> {
+        let repo = self.repo()?;
+        let commit = repo.find_commit(Oid::from_str(sha)?)?;
+        Self::commit_diff_stats(&repo, &commit, &[], false)
+    }
+
+    fn blame(&self, path: &str) -> Result<Vec<GitBlame>, FownerError> {
+        let repo = self.repo()?;
+        let mut options = BlameOptions::new();
+        options.newest_commit(repo.head()?.peel_to_commit()?.id());
+        let blame = repo.blame_file(Path::new(path), Some(&mut options))?;
+        let mut result: Vec<GitBlame> = vec![];
+        for hunk in blame.iter() {
+            // libgit2 leaves the hunk's signature null when the author has no email
+            let commit = repo.find_commit(hunk.final_commit_id())?;
+            let signature = commit.author();
+            let email = signature
+                .email()
+                .filter(|email| !email.is_empty())
+                .map(String::from);
+            GitBlame::add_lines(
+                &mut result,
+                signature.name().unwrap_or_default(),
+                email,
+                hunk.lines_in_hunk() as u32,
+            );
+        }
+        Ok(result)
+    }
+
+    fn tags(&self) -> Result<Vec<GitTag>, FownerError> {
+        let repo = self.repo()?;
+        let mut tags = vec![];
+        for name in repo.tag_names(None)?.iter().flatten() {
+            let object = repo.revparse_single(&format!("refs/tags/{}", name))?;
+            let commit = match object.peel_to_commit() {
+                Ok(commit) => commit,
+                Err(_) => continue,
+            };
+            // Annotated tags are dated by their tagger, lightweight tags by their commit
+            let timestamp = object
+                .as_tag()
+                .and_then(|tag| tag.tagger())
+                .map(|tagger| tagger.when().seconds())
+                .unwrap_or_else(|| commit.committer().when().seconds());
+            tags.push(GitTag {
+                name: name.to_string(),
+                sha: commit.id().to_string(),
+                timestamp,
+            });
+        }
+        Ok(tags)
+    }
+
+    fn current_branch(&self) -> Result<String, FownerError> {
+        let repo = self.repo()?;
+        if repo.head_detached()? {
+            return Ok("HEAD".to_string());
+        }
+        let head = repo.head()?;
+        Ok(head.shorthand().unwrap_or("HEAD").to_string())
+    }
+
+    fn verify_ref(&self, git_ref: &str) -> Option<String> {
+        self.repo()
+            .ok()?
+            .revparse_single(&format!("{}^{{commit}}", git_ref))
+            .ok()
+            .map(|_| git_ref.to_string())
+    }
+
+    fn rev_list(&self, git_ref: &str) -> Result<Vec<String>, FownerError> {
+        let oids = Self::walk(
+            &self.repo()?,
+            &[git_ref.to_string()],
+            &[],
+            &[],
+            None,
+            Sort::NONE,
+        )?;
+        Ok(oids.into_iter().map(|oid| oid.to_string()).collect())
+    }
+
+    fn rev_parse(&self, git_ref: &str) -> Result<String, FownerError> {
+        let repo = self.repo()?;
+        let commit = repo.revparse_single(git_ref)?.peel_to_commit()?;
+        Ok(commit.id().to_string())
+    }
+
+    fn commit_exists(&self, sha: &str) -> Result<bool, FownerError> {
+        let oid = match Oid::from_str(sha) {
+            Ok(oid) => oid,
+            Err(_) => return Ok(false),
+        };
+        Ok(self.repo()?.find_commit(oid).is_ok())
+    }
+
+    fn files_at_head(&self) -> Result<Vec<String>, FownerError> {
+        let repo = self.repo()?;
+        let tree = repo.head()?.peel_to_tree()?;
+        let mut files = vec![];
+        tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            // Submodules are listed alongside files, as `git ls-tree` does
+            if matches!(entry.kind(), Some(ObjectType::Blob | ObjectType::Commit)) {
+                if let Some(name) = entry.name() {
+                    files.push(format!("{}{}", root, name));
+                }
+            }
+            TreeWalkResult::Ok
+        })?;
+        Ok(files)
+    }
+
+    fn read_at_head(&self, path: &str) -> Result<Option<String>, FownerError> {
+        let repo = self.repo()?;
+        let tree = repo.head()?.peel_to_tree()?;
+        let entry = match tree.get_path(Path::new(path)) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let blob = entry.to_object(&repo)?.peel_to_blob()?;
+        Ok(Some(String::from_utf8_lossy(blob.content()).to_string()))
+    }
+
+    fn staged_files(&self) -> Result<Vec<String>, FownerError> {
+        let repo = self.repo()?;
+        // Nothing is committed yet on an unborn branch, everything in the index is staged
+        let head = match repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+        let diff = repo.diff_tree_to_index(head.as_ref(), None, None)?;
+        let mut files: Vec<String> = diff
+            .deltas()
+            .filter_map(|delta| match delta.status() {
+                Delta::Deleted => delta.old_file().path(),
+                _ => delta.new_file().path(),
+            })
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    fn hooks_dir(&self) -> Result<PathBuf, FownerError> {
+        let repo = self.repo()?;
+        match repo.config()?.get_path("core.hooksPath") {
+            // Git runs hooks from the working tree, so a relative hooks path starts there
+            Ok(path) => Ok(repo.workdir().unwrap_or_else(|| repo.path()).join(path)),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(repo.path().join("hooks")),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Reads each commit and its diff only when the iterator reaches it
+struct LibraryHistory<'a> {
+    repo: Repository,
+    oids: std::vec::IntoIter<Oid>,
+    paths: Vec<String>,
+    diff_merges: bool,
+    feature_extractor: &'a FeatureExtractor,
+}
+
+impl<'a> LibraryHistory<'a> {
+    fn history(&self, oid: Oid) -> Result<Option<GitHistory>, FownerError> {
+        let commit = self.repo.find_commit(oid)?;
+        let stats =
+            LibraryBackend::commit_diff_stats(&self.repo, &commit, &self.paths, self.diff_merges)?;
+        // `git log -- .` leaves out commits that change nothing
+        if commit.parent_count() == 1 && stats.files.is_empty() {
+            return Ok(None);
+        }
+        let author = commit.author();
+        let committer = commit.committer();
+        let co_authors = git2::message_trailers_strs(commit.message().unwrap_or_default())?
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("Co-authored-by"))
+            .filter_map(|(_, identity)| GitIdentity::from_str(identity).ok())
+            .collect();
+        let mut history = GitHistory {
+            handle: author.name().unwrap_or_default().to_string(),
+            email: author.email().unwrap_or_default().to_string(),
+            committer_handle: committer.name().unwrap_or_default().to_string(),
+            committer_email: committer.email().unwrap_or_default().to_string(),
+            sha: oid.to_string(),
+            parent_sha: match commit.parent_count() {
+                0 => None,
+                _ => Some(commit.parent_ids().map(|id| id.to_string()).collect()),
+            },
+            timestamp: clamp_timestamp(author.when().seconds()),
+            committer_timestamp: clamp_timestamp(committer.when().seconds()),
+            co_authors,
+            summary: commit.summary().unwrap_or_default().to_string(),
+            body: commit.body().unwrap_or_default().trim().to_string(),
+            ..GitHistory::default()
+        };
+        history.extract_features(self.feature_extractor);
+        history.set_diff_stats(stats);
+        Ok(Some(history))
+    }
+}
+
+impl<'a> Iterator for LibraryHistory<'a> {
+    type Item = Result<GitHistory, FownerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(oid) = self.oids.next() {
+            match self.history(oid) {
+                Ok(Some(history)) => return Some(Ok(history)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,349 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::git::blame::GitBlame;
+use crate::git::credentials::GitCredentials;
+use crate::git::features::FeatureExtractor;
+use crate::git::history::{GitDiffStats, GitHistory, MergePolicy};
+use crate::git::remote::RemoteConfig;
+use crate::git::tag::GitTag;
+use crate::FownerError;
+
+pub mod cli;
+pub mod library;
+
+/// How long a git command or network operation may run before it is stopped
+pub const DEFAULT_GIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The timeout for the git commands run while serving requests
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CommandTimeout(pub Duration);
+
+impl Default for CommandTimeout {
+    fn default() -> Self {
+        Self(DEFAULT_GIT_TIMEOUT)
+    }
+}
+
+/// Commits in chronological ASC order, read lazily from the repository
+pub type GitHistoryIter<'a> = Box<dyn Iterator<Item = Result<GitHistory, FownerError>> + 'a>;
+
+/// Selects how fowner talks to the repositories it monitors
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GitBackendKind {
+    /// Shell out to the `git` binary
+    #[default]
+    Cli,
+    /// Use libgit2 in-process, no `git` binary required
+    Library,
+}
+
+impl GitBackendKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GitBackendKind::Cli => "cli",
+            GitBackendKind::Library => "library",
+        }
+    }
+
+    /// Opens a backend whose clones and fetches authenticate with `credentials` and whose
+    /// git commands are stopped after `timeout`
+    pub fn open(
+        &self,
+        path: &Path,
+        credentials: Option<GitCredentials>,
+        timeout: Duration,
+    ) -> Box<dyn GitBackend> {
+        match self {
+            GitBackendKind::Cli => Box::new(
+                cli::CliBackend::new(path)
+                    .with_credentials(credentials)
+                    .with_timeout(timeout),
+            ),
+            GitBackendKind::Library => Box::new(
+                library::LibraryBackend::new(path)
+                    .with_credentials(credentials)
+                    .with_timeout(timeout),
+            ),
+        }
+    }
+}
+
+/// The repository operations fowner relies on, each backend works on the repository at one path
+pub trait GitBackend {
+    fn is_valid_repo(&self) -> bool;
+
+    /// Clones `url` into the backend's path as the named remote, then adds any extra remotes
+    fn clone_repo(&self, url: &str, remotes: &RemoteConfig) -> Result<(), FownerError>;
+
+    /// Fetches every remote, checkouts are then moved to their upstream, following force-pushes
+    fn fetch(&self, remotes: &RemoteConfig) -> Result<(), FownerError>;
+
+    /// Removes remote tracking branches that no longer exist on the remotes
+    fn cleanup(&self, remotes: &RemoteConfig) -> Result<String, FownerError>;
+
+    /// Walks the commits reachable from `refs`, or HEAD when empty, skipping those reachable
+    /// from any of the `exclude` shas
+    /// Without a `merge_policy` merges are walked but report no changes
+    /// Non-empty `paths` limit the history to the commits and files under them
+    fn history<'a>(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        paths: &[String],
+        merge_policy: Option<MergePolicy>,
+        feature_extractor: &'a FeatureExtractor,
+    ) -> Result<GitHistoryIter<'a>, FownerError>;
+
+    /// Counts the commits `history` will walk for the same arguments
+    fn count_history(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        paths: &[String],
+        merge_policy: Option<MergePolicy>,
+    ) -> Result<usize, FownerError>;
+
+    /// The files changed by a single commit, merges report no changes
+    fn diff_stats(&self, sha: &str) -> Result<GitDiffStats, FownerError>;
+
+    /// Returns the number of lines each author still owns in `path` at HEAD
+    fn blame(&self, path: &str) -> Result<Vec<GitBlame>, FownerError>;
+
+    /// Returns every tag with the commit it points to
+    fn tags(&self) -> Result<Vec<GitTag>, FownerError>;
+
+    /// Returns the name of the checked out branch, or `HEAD` when detached
+    fn current_branch(&self) -> Result<String, FownerError>;
+
+    /// Returns `git_ref` if it resolves to a commit
+    fn verify_ref(&self, git_ref: &str) -> Option<String>;
+
+    /// Returns the sha of every commit reachable from `git_ref`
+    fn rev_list(&self, git_ref: &str) -> Result<Vec<String>, FownerError>;
+
+    /// Returns the sha `git_ref` currently points to
+    fn rev_parse(&self, git_ref: &str) -> Result<String, FownerError>;
+
+    /// Checks the commit exists in the local repository
+    fn commit_exists(&self, sha: &str) -> Result<bool, FownerError>;
+
+    /// Returns the paths of every file tracked at HEAD
+    fn files_at_head(&self) -> Result<Vec<String>, FownerError>;
+
+    /// Returns the contents of `path` at HEAD, `None` when it is not tracked
+    fn read_at_head(&self, path: &str) -> Result<Option<String>, FownerError>;
+
+    /// Returns the paths staged for the next commit, renames are listed as both paths
+    fn staged_files(&self) -> Result<Vec<String>, FownerError>;
+
+    /// Returns the directory git runs the repository's hooks from
+    fn hooks_dir(&self) -> Result<PathBuf, FownerError>;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::backend::{GitBackend, GitBackendKind, DEFAULT_GIT_TIMEOUT};
+    use crate::git::features::FeatureExtractor;
+    use crate::git::history::MergePolicy;
+    use crate::test::builders::repo_builder::RepoBuilder;
+    use crate::test::tests::TestHandler;
+
+    #[test]
+    fn backends_agree() {
+        let handler = TestHandler::init();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        let initial = repo.commit_at(1655391971, "Initial commit [Core]");
+        repo.git(&["tag", "v1.0"]);
+        repo.write("src/main.rs", "fn main() {\n    run()\n}\n");
+        repo.write("README.md", "# Demo\n\nRuns things\n");
+        // Git quotes paths with non-ASCII characters, quotes and tabs unless told not to
+        repo.write("docs/naïve \"notes\".md", "Résumé\n");
+        repo.commit_at(
+            1655391981,
+            "feat(cli): Run things\n\nDetails [Docs]\n\nCo-authored-by: Pair <pair@example.com>",
+        );
+        repo.git(&["mv", "README.md", "docs.md"]);
+        let renamed = repo.commit_at(1655391991, "Move the readme");
+        repo.git(&["tag", "-a", "v1.1", "-m", "Release 1.1"]);
+
+        let extractor = FeatureExtractor::for_commits().with_conventional_commits(true);
+        let cli = GitBackendKind::Cli.open(&repo.path, None, DEFAULT_GIT_TIMEOUT);
+        let library = GitBackendKind::Library.open(&repo.path, None, DEFAULT_GIT_TIMEOUT);
+        let history = |backend: &dyn GitBackend, exclude: &[String]| {
+            backend
+                .history(&[], exclude, &[], None, &extractor)
+                .unwrap()
+                .map(|h| serde_json::to_value(h.unwrap()).unwrap())
+                .collect::<Vec<serde_json::Value>>()
+        };
+        let cli_history = history(cli.as_ref(), &[]);
+        assert_eq!(cli_history.len(), 3);
+        assert_eq!(
+            cli_history[1]["files"],
+            serde_json::json!(["README.md", "docs/naïve \"notes\".md", "src/main.rs"])
+        );
+        assert_eq!(cli_history, history(library.as_ref(), &[]));
+        let exclude = vec![initial.clone()];
+        assert_eq!(
+            history(cli.as_ref(), &exclude),
+            history(library.as_ref(), &exclude)
+        );
+        assert_eq!(
+            cli.count_history(&[], &exclude, &[], None).unwrap(),
+            library.count_history(&[], &exclude, &[], None).unwrap()
+        );
+
+        let stats = library.diff_stats(&renamed).unwrap();
+        assert_eq!(stats.renames.len(), 1);
+        assert_eq!(stats, cli.diff_stats(&renamed).unwrap());
+        assert_eq!(
+            cli.blame("src/main.rs").unwrap(),
+            library.blame("src/main.rs").unwrap()
+        );
+        let tags = |backend: &dyn GitBackend| {
+            let mut tags = backend.tags().unwrap();
+            tags.sort_by(|a, b| a.name.cmp(&b.name));
+            tags
+        };
+        assert_eq!(tags(cli.as_ref()).len(), 2);
+        assert_eq!(tags(cli.as_ref()), tags(library.as_ref()));
+
+        assert_eq!(
+            cli.current_branch().unwrap(),
+            library.current_branch().unwrap()
+        );
+        assert_eq!(cli.verify_ref("v1.0"), library.verify_ref("v1.0"));
+        assert_eq!(library.verify_ref("missing"), None);
+        assert_eq!(
+            cli.rev_parse("HEAD").unwrap(),
+            library.rev_parse("HEAD").unwrap()
+        );
+        let mut shas = library.rev_list("HEAD").unwrap();
+        shas.sort();
+        let mut cli_shas = cli.rev_list("HEAD").unwrap();
+        cli_shas.sort();
+        assert_eq!(shas, cli_shas);
+        assert!(library.commit_exists(&initial).unwrap());
+        assert!(!library.commit_exists("not-a-sha").unwrap());
+        assert_eq!(
+            cli.files_at_head().unwrap(),
+            library.files_at_head().unwrap()
+        );
+        assert_eq!(
+            cli.read_at_head("src/main.rs").unwrap(),
+            library.read_at_head("src/main.rs").unwrap()
+        );
+        assert!(cli.read_at_head("src/main.rs").unwrap().is_some());
+        assert_eq!(library.read_at_head(".mailmap").unwrap(), None);
+        assert_eq!(cli.read_at_head(".mailmap").unwrap(), None);
+        assert!(library.is_valid_repo());
+
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.write("src/lib.rs", "pub fn run() {}\n");
+        repo.git(&["add", "src"]);
+        repo.git(&["mv", "docs.md", "README.md"]);
+        assert_eq!(
+            cli.staged_files().unwrap(),
+            vec!["README.md", "docs.md", "src/lib.rs", "src/main.rs"]
+        );
+        assert_eq!(cli.staged_files().unwrap(), library.staged_files().unwrap());
+        assert_eq!(cli.hooks_dir().unwrap(), repo.path.join(".git/hooks"));
+        assert_eq!(cli.hooks_dir().unwrap(), library.hooks_dir().unwrap());
+        repo.git(&["config", "core.hooksPath", "githooks"]);
+        assert_eq!(library.hooks_dir().unwrap(), repo.path.join("githooks"));
+        assert_eq!(cli.hooks_dir().unwrap(), library.hooks_dir().unwrap());
+    }
+
+    #[test]
+    fn blame_without_emails() {
+        let handler = TestHandler::init();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.commit_as("Alice", "", "Initial commit");
+        repo.write("src/main.rs", "fn main() {}\nfn run() {}\nfn stop() {}\n");
+        repo.commit_as("Bob", "", "Run and stop");
+
+        let library = GitBackendKind::Library.open(&repo.path, None, DEFAULT_GIT_TIMEOUT);
+        let blame = library.blame("src/main.rs").unwrap();
+        assert_eq!(
+            blame
+                .iter()
+                .map(|b| (b.handle.as_str(), b.email.clone(), b.line_count))
+                .collect::<Vec<_>>(),
+            vec![("Alice", None, 1), ("Bob", None, 2)]
+        );
+        let cli = GitBackendKind::Cli.open(&repo.path, None, DEFAULT_GIT_TIMEOUT);
+        assert_eq!(cli.blame("src/main.rs").unwrap(), blame);
+    }
+
+    #[test]
+    fn merge_policies() {
+        let handler = TestHandler::init();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("main.rs", "fn main() {}\n");
+        repo.commit_at(1655391971, "Initial commit");
+        repo.git(&["checkout", "-q", "-b", "side"]);
+        repo.write("side.rs", "fn side() {}\n");
+        let side = repo.commit_at(1655391981, "Side work");
+        repo.git(&["checkout", "-q", "main"]);
+        repo.write("main.rs", "fn main() {\n    side()\n}\n");
+        repo.commit_at(1655391991, "Main work");
+        repo.git(&["merge", "-q", "--no-ff", "-m", "Merge side", "side"]);
+
+        let extractor = FeatureExtractor::for_commits();
+        let cli = GitBackendKind::Cli.open(&repo.path, None, DEFAULT_GIT_TIMEOUT);
+        let library = GitBackendKind::Library.open(&repo.path, None, DEFAULT_GIT_TIMEOUT);
+        let history = |backend: &dyn GitBackend, merge_policy: Option<MergePolicy>| {
+            let mut history = backend
+                .history(&[], &[], &[], merge_policy, &extractor)
+                .unwrap()
+                .map(|h| h.unwrap())
+                .map(|h| (h.sha.clone(), h.is_merge(), h.files))
+                .collect::<Vec<(String, bool, Vec<String>)>>();
+            history.sort();
+            history
+        };
+        for (merge_policy, commits, merge_files) in [
+            (None, 4, Some(vec![])),
+            (
+                Some(MergePolicy::FirstParent),
+                3,
+                Some(vec!["side.rs".to_string()]),
+            ),
+            (
+                Some(MergePolicy::DiffFirstParent),
+                4,
+                Some(vec!["side.rs".to_string()]),
+            ),
+            (Some(MergePolicy::Ignore), 3, None),
+        ] {
+            let cli_history = history(cli.as_ref(), merge_policy);
+            assert_eq!(cli_history, history(library.as_ref(), merge_policy));
+            assert_eq!(cli_history.len(), commits);
+            assert_eq!(
+                cli_history
+                    .iter()
+                    .find(|(_, is_merge, _)| *is_merge)
+                    .map(|(_, _, files)| files.clone()),
+                merge_files
+            );
+            assert_eq!(
+                cli_history.iter().any(|(sha, _, _)| sha == &side),
+                merge_policy != Some(MergePolicy::FirstParent)
+            );
+            assert_eq!(
+                cli.count_history(&[], &[], &[], merge_policy).unwrap(),
+                commits
+            );
+            assert_eq!(
+                library.count_history(&[], &[], &[], merge_policy).unwrap(),
+                commits
+            );
+        }
+    }
+}
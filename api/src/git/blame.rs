@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct GitBlame {
+    pub handle: String,
+    pub email: Option<String>,
+    pub line_count: u32,
+}
+
+impl GitBlame {
+    /// Adds `line_count` lines to the author's entry, authors without an email are keyed on their handle
+    pub fn add_lines(
+        blame: &mut Vec<GitBlame>,
+        handle: &str,
+        email: Option<String>,
+        line_count: u32,
+    ) {
+        let existing = blame.iter_mut().find(|b| match &email {
+            Some(_) => b.email == email,
+            None => b.email.is_none() && b.handle == handle,
+        });
+        match existing {
+            Some(existing) => existing.line_count += line_count,
+            None => blame.push(GitBlame {
+                handle: handle.to_string(),
+                email,
+                line_count,
+            }),
+        }
+    }
+
+    /// Processes `git blame --line-porcelain` output into the number of surviving lines per author
+    /// Authors are keyed on their email, or their handle when it is missing, and returned in
+    /// the order they first appear in the file
+    pub fn parse(blame_string: &str) -> Vec<GitBlame> {
+        let mut blame: Vec<GitBlame> = vec![];
+        let mut handle = String::new();
+        for line in blame_string.split('\n') {
+            // Every line of the file is preceded by its full commit header, including
+            // `author <name>` followed by `author-mail <<email>>`
+            if let Some(author) = line.strip_prefix("author ") {
+                handle = author.trim().to_string();
+            } else if let Some(mail) = line.strip_prefix("author-mail ") {
+                let email = mail.trim().trim_start_matches('<').trim_end_matches('>');
+                let email = (!email.is_empty()).then(|| email.to_string());
+                GitBlame::add_lines(&mut blame, &handle, email, 1);
+            }
+        }
+        blame
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::blame::GitBlame;
+
+    #[test]
+    fn parse_line_porcelain() {
+        let blame_string = r#"68520e7a0c7f40e135f18b351a294c7840c15e8d 1 1 2
+author Krakaw
+author-mail <krakaw@example.com>
+author-time 1655513635
+author-tz +0000
+committer Krakaw
+committer-mail <krakaw@example.com>
+committer-time 1655513635
+committer-tz +0000
+summary Initial commit
+boundary
+filename src/main.rs
+	fn main() {
+68520e7a0c7f40e135f18b351a294c7840c15e8d 2 2
+author Krakaw
+author-mail <krakaw@example.com>
+author-time 1655513635
+author-tz +0000
+committer Krakaw
+committer-mail <krakaw@example.com>
+committer-time 1655513635
+committer-tz +0000
+summary Initial commit
+boundary
+filename src/main.rs
+	}
+6159c3227a9d3b2e952f8e97951fb946355ffc2f 2 2 1
+author Keith Simon
+author-mail <keith@example.com>
+author-time 1655513699
+author-tz +0000
+committer Keith Simon
+committer-mail <keith@example.com>
+committer-time 1655513699
+committer-tz +0000
+summary Print a greeting
+previous 68520e7a0c7f40e135f18b351a294c7840c15e8d src/main.rs
+filename src/main.rs
+	    println!("author Someone");
+"#;
+        let blame = GitBlame::parse(blame_string);
+        assert_eq!(
+            blame,
+            vec![
+                GitBlame {
+                    handle: "Krakaw".to_string(),
+                    email: Some("krakaw@example.com".to_string()),
+                    line_count: 2
+                },
+                GitBlame {
+                    handle: "Keith Simon".to_string(),
+                    email: Some("keith@example.com".to_string()),
+                    line_count: 1
+                }
+            ]
+        );
+    }
+}
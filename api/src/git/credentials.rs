@@ -0,0 +1,455 @@
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use git2::{Cred, CredentialType, RemoteCallbacks};
+use serde::{Deserialize, Serialize};
+
+use crate::git::secret_key::SecretKey;
+use crate::FownerError;
+
+/// The username sent with an HTTPS token when none is configured, accepted by GitHub
+const DEFAULT_TOKEN_USERNAME: &str = "x-access-token";
+
+/// Keeps the credential directories of concurrent commands apart
+static CREDENTIAL_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// stderr from git and ssh when the remote rejected or never received credentials
+const AUTHENTICATION_FAILURES: &[&str] = &[
+    "authentication failed",
+    "permission denied (publickey",
+    "could not read username",
+    "could not read password",
+    "invalid username or password",
+    "access denied",
+    "terminal prompts disabled",
+    "host key verification failed",
+];
+
+/// Credentials used for a single project's clones and fetches.
+///
+/// Secrets are accepted when a project is saved but never serialized back out.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GitCredentials {
+    /// A deploy key for `ssh://` and `git@host:` remotes
+    SshKey {
+        #[serde(default, skip_serializing)]
+        private_key: String,
+        #[serde(default, skip_serializing)]
+        passphrase: Option<String>,
+    },
+    /// A personal access or deploy token for HTTPS remotes
+    HttpsToken {
+        #[serde(default, skip_serializing)]
+        token: String,
+        /// Defaults to `x-access-token`
+        username: Option<String>,
+    },
+    /// A username and password for HTTPS remotes
+    UserPassword {
+        username: String,
+        #[serde(default, skip_serializing)]
+        password: String,
+    },
+}
+
+impl fmt::Debug for GitCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitCredentials::SshKey { .. } => f.write_str("SshKey { .. }"),
+            GitCredentials::HttpsToken { username, .. } => f
+                .debug_struct("HttpsToken")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            GitCredentials::UserPassword { username, .. } => f
+                .debug_struct("UserPassword")
+                .field("username", username)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl GitCredentials {
+    pub fn validate(&self) -> Result<(), FownerError> {
+        let missing = match self {
+            GitCredentials::SshKey { private_key, .. } => {
+                private_key.trim().is_empty().then_some("private_key")
+            }
+            GitCredentials::HttpsToken { token, .. } => token.is_empty().then_some("token"),
+            GitCredentials::UserPassword { username, password } => {
+                if username.is_empty() {
+                    Some("username")
+                } else {
+                    password.is_empty().then_some("password")
+                }
+            }
+        };
+        match missing {
+            Some(field) => Err(FownerError::GitAuthentication(format!(
+                "Credentials are missing the {}",
+                field
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn username_and_password(&self) -> Option<(&str, &str)> {
+        match self {
+            GitCredentials::SshKey { .. } => None,
+            GitCredentials::HttpsToken { token, username } => {
+                Some((username.as_deref().unwrap_or(DEFAULT_TOKEN_USERNAME), token))
+            }
+            GitCredentials::UserPassword { username, password } => Some((username, password)),
+        }
+    }
+
+    /// Libgit2 callbacks that answer the remote's first credential request, later requests
+    /// mean the credentials were rejected and fail rather than retrying forever
+    pub fn remote_callbacks(&self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        let mut attempted = false;
+        callbacks.credentials(move |_url, username_from_url, allowed| {
+            if attempted {
+                return Err(git2::Error::new(
+                    git2::ErrorCode::Auth,
+                    git2::ErrorClass::Callback,
+                    "The remote rejected the project's credentials",
+                ));
+            }
+            attempted = true;
+            match self {
+                GitCredentials::SshKey {
+                    private_key,
+                    passphrase,
+                } if allowed.contains(CredentialType::SSH_KEY) => Cred::ssh_key_from_memory(
+                    username_from_url.unwrap_or("git"),
+                    None,
+                    private_key,
+                    passphrase.as_deref(),
+                ),
+                _ if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) => {
+                    match self.username_and_password() {
+                        Some((username, password)) => Cred::userpass_plaintext(username, password),
+                        None => Err(git2::Error::new(
+                            git2::ErrorCode::Auth,
+                            git2::ErrorClass::Callback,
+                            "An SSH key cannot authenticate an HTTPS remote",
+                        )),
+                    }
+                }
+                _ => Err(git2::Error::new(
+                    git2::ErrorCode::Auth,
+                    git2::ErrorClass::Callback,
+                    "The remote asked for an unsupported credential type",
+                )),
+            }
+        });
+        callbacks
+    }
+
+    /// Configures a `git` command to authenticate with these credentials instead of any
+    /// ambient ones, the returned files must outlive the command
+    pub fn configure(&self, command: &mut Command) -> Result<CredentialFiles, FownerError> {
+        let files = CredentialFiles::create()?;
+        // Ambient credential helpers would otherwise be asked first
+        command.args(["-c", "credential.helper="]);
+        match self {
+            GitCredentials::SshKey {
+                private_key,
+                passphrase,
+            } => {
+                let key_path = files.write("key", private_key, 0o600)?;
+                // Batch mode stops ssh prompting on a terminal, but would also skip the askpass
+                let batch_mode = if passphrase.is_some() { "no" } else { "yes" };
+                command.env(
+                    "GIT_SSH_COMMAND",
+                    format!(
+                        "ssh -i '{}' -o IdentitiesOnly=yes -o StrictHostKeyChecking=accept-new -o BatchMode={}",
+                        key_path.to_string_lossy(),
+                        batch_mode
+                    ),
+                );
+                if let Some(passphrase) = passphrase {
+                    let askpass = files.write(
+                        "askpass.sh",
+                        "#!/bin/sh\necho \"$FOWNER_SSH_PASSPHRASE\"\n",
+                        0o700,
+                    )?;
+                    command
+                        .env("SSH_ASKPASS", askpass)
+                        .env("SSH_ASKPASS_REQUIRE", "force")
+                        .env("DISPLAY", ":0")
+                        .env("FOWNER_SSH_PASSPHRASE", passphrase);
+                }
+            }
+            _ => {
+                let (username, password) = self.username_and_password().unwrap_or_default();
+                let askpass = files.write(
+                    "askpass.sh",
+                    "#!/bin/sh\ncase \"$1\" in\n  Username*) echo \"$FOWNER_GIT_USERNAME\" ;;\n  *) echo \"$FOWNER_GIT_PASSWORD\" ;;\nesac\n",
+                    0o700,
+                )?;
+                command
+                    .env("GIT_ASKPASS", askpass)
+                    .env("FOWNER_GIT_USERNAME", username)
+                    .env("FOWNER_GIT_PASSWORD", password);
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// The secrets of `GitCredentials`, which unlike the API form are serialized, only ever sealed
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum StoredGitCredentials {
+    SshKey {
+        private_key: String,
+        passphrase: Option<String>,
+    },
+    HttpsToken {
+        token: String,
+        username: Option<String>,
+    },
+    UserPassword {
+        username: String,
+        password: String,
+    },
+}
+
+impl From<GitCredentials> for StoredGitCredentials {
+    fn from(credentials: GitCredentials) -> Self {
+        match credentials {
+            GitCredentials::SshKey {
+                private_key,
+                passphrase,
+            } => StoredGitCredentials::SshKey {
+                private_key,
+                passphrase,
+            },
+            GitCredentials::HttpsToken { token, username } => {
+                StoredGitCredentials::HttpsToken { token, username }
+            }
+            GitCredentials::UserPassword { username, password } => {
+                StoredGitCredentials::UserPassword { username, password }
+            }
+        }
+    }
+}
+
+impl From<StoredGitCredentials> for GitCredentials {
+    fn from(stored: StoredGitCredentials) -> Self {
+        match stored {
+            StoredGitCredentials::SshKey {
+                private_key,
+                passphrase,
+            } => GitCredentials::SshKey {
+                private_key,
+                passphrase,
+            },
+            StoredGitCredentials::HttpsToken { token, username } => {
+                GitCredentials::HttpsToken { token, username }
+            }
+            StoredGitCredentials::UserPassword { username, password } => {
+                GitCredentials::UserPassword { username, password }
+            }
+        }
+    }
+}
+
+impl GitCredentials {
+    /// The kind and username, as the API shows them
+    fn without_secrets(&self) -> Self {
+        match self {
+            GitCredentials::SshKey { .. } => GitCredentials::SshKey {
+                private_key: String::new(),
+                passphrase: None,
+            },
+            GitCredentials::HttpsToken { username, .. } => GitCredentials::HttpsToken {
+                token: String::new(),
+                username: username.clone(),
+            },
+            GitCredentials::UserPassword { username, .. } => GitCredentials::UserPassword {
+                username: username.clone(),
+                password: String::new(),
+            },
+        }
+    }
+
+    /// Reads credentials stored before their secrets were sealed
+    pub fn from_plaintext_json(json: &str) -> Result<Self, FownerError> {
+        Ok(serde_json::from_str::<StoredGitCredentials>(json)?.into())
+    }
+}
+
+/// Credentials as a project stores them, the secrets are sealed with the server's `SecretKey`
+/// and only opened by `GitManager` for the clones and fetches that need them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SealedCredentials {
+    /// Without the secrets
+    #[serde(flatten)]
+    pub credentials: GitCredentials,
+    #[serde(skip)]
+    sealed: String,
+}
+
+/// The column form of `SealedCredentials`
+#[derive(Serialize, Deserialize)]
+struct StoredSealedCredentials {
+    #[serde(flatten)]
+    credentials: GitCredentials,
+    sealed: String,
+}
+
+impl SealedCredentials {
+    pub fn seal(credentials: &GitCredentials) -> Result<Self, FownerError> {
+        let secrets = serde_json::to_string(&StoredGitCredentials::from(credentials.clone()))?;
+        Ok(Self {
+            credentials: credentials.without_secrets(),
+            sealed: SecretKey::installed()?.seal(secrets.as_bytes())?,
+        })
+    }
+
+    pub fn open(&self) -> Result<GitCredentials, FownerError> {
+        let secrets = SecretKey::installed()?.open(&self.sealed)?;
+        Ok(serde_json::from_slice::<StoredGitCredentials>(&secrets)?.into())
+    }
+
+    /// Serializes the credentials for the projects table, the secrets stay sealed
+    pub fn to_stored_json(&self) -> Result<String, FownerError> {
+        Ok(serde_json::to_string(&StoredSealedCredentials {
+            credentials: self.credentials.clone(),
+            sealed: self.sealed.clone(),
+        })?)
+    }
+
+    pub fn from_stored_json(json: &str) -> Result<Self, FownerError> {
+        let stored: StoredSealedCredentials = serde_json::from_str(json)?;
+        Ok(Self {
+            credentials: stored.credentials,
+            sealed: stored.sealed,
+        })
+    }
+}
+
+/// Maps failed clones and fetches to an authentication error when stderr shows the
+/// credentials were missing or rejected
+pub fn authentication_error(stderr: &str) -> Option<FownerError> {
+    let lowercase = stderr.to_lowercase();
+    AUTHENTICATION_FAILURES
+        .iter()
+        .any(|failure| lowercase.contains(failure))
+        .then(|| FownerError::GitAuthentication(stderr.trim().to_string()))
+}
+
+/// Maps libgit2 authentication failures to an authentication error
+pub fn map_git2_error(e: git2::Error) -> FownerError {
+    if e.code() == git2::ErrorCode::Auth || e.class() == git2::ErrorClass::Ssh {
+        FownerError::GitAuthentication(e.message().to_string())
+    } else {
+        FownerError::Git2(e)
+    }
+}
+
+/// A private directory holding the key and askpass script for one git command, removed once
+/// the command is done
+pub struct CredentialFiles {
+    dir: PathBuf,
+}
+
+impl CredentialFiles {
+    fn create() -> Result<Self, FownerError> {
+        let dir = std::env::temp_dir().join(format!(
+            "fowner-credentials-{}-{}",
+            std::process::id(),
+            CREDENTIAL_DIRS.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir(&dir)?;
+        set_mode(&dir, 0o700)?;
+        Ok(Self { dir })
+    }
+
+    fn write(&self, name: &str, contents: &str, mode: u32) -> Result<PathBuf, FownerError> {
+        let path = self.dir.join(name);
+        let mut file = fs::File::create(&path)?;
+        set_mode(&path, mode)?;
+        file.write_all(contents.as_bytes())?;
+        if !contents.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+        Ok(path)
+    }
+}
+
+impl Drop for CredentialFiles {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &std::path::Path, mode: u32) -> Result<(), FownerError> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &std::path::Path, _mode: u32) -> Result<(), FownerError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::credentials::{authentication_error, GitCredentials, SealedCredentials};
+    use crate::test::tests::TestHandler;
+
+    #[test]
+    fn secrets_are_not_serialized() {
+        TestHandler::install_secret_key();
+        let credentials: GitCredentials = serde_json::from_str(
+            r#"{"kind": "https_token", "token": "ghp_secret", "username": "deploy"}"#,
+        )
+        .unwrap();
+        assert!(credentials.validate().is_ok());
+        let serialized = serde_json::to_string(&credentials).unwrap();
+        assert!(!serialized.contains("ghp_secret"));
+        assert!(serialized.contains("deploy"));
+        assert!(!format!("{:?}", credentials).contains("ghp_secret"));
+
+        let key: GitCredentials =
+            serde_json::from_str(r#"{"kind": "ssh_key", "private_key": "-----BEGIN"}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&key).unwrap(),
+            r#"{"kind":"ssh_key"}"#
+        );
+        let sealed = SealedCredentials::seal(&key).unwrap();
+        let stored = sealed.to_stored_json().unwrap();
+        assert!(!stored.contains("-----BEGIN"));
+        assert_eq!(
+            serde_json::to_string(&sealed).unwrap(),
+            r#"{"kind":"ssh_key"}"#
+        );
+        let loaded = SealedCredentials::from_stored_json(&stored).unwrap();
+        assert_eq!(loaded, sealed);
+        assert_eq!(loaded.open().unwrap(), key);
+
+        let missing: GitCredentials =
+            serde_json::from_str(r#"{"kind": "user_password", "username": "deploy"}"#).unwrap();
+        assert!(missing.validate().is_err());
+    }
+
+    #[test]
+    fn authentication_failures() {
+        assert!(authentication_error(
+            "remote: Invalid username or password.\nfatal: Authentication failed for 'https://example.com/repo.git/'"
+        )
+        .is_some());
+        assert!(authentication_error("git@example.com: Permission denied (publickey).").is_some());
+        assert!(authentication_error("fatal: couldn't find remote ref main").is_none());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::errors::FownerError;
+use regex::Regex;
+
+/// Extracts features from any [Feature,Feature] list in the commit message
+pub const DEFAULT_COMMIT_FEATURE_PATTERN: &str = r"\[([\w ,-]+)\]";
+/// Extracts features from labels prefixed with `f:`
+pub const DEFAULT_LABEL_FEATURE_PATTERN: &str = r"^[fF]:\s*";
+/// Matches a Conventional Commits header: `type(scope)!: description`
+const CONVENTIONAL_COMMIT_PATTERN: &str = r"^(\w+)(?:\(([^)]*)\))?!?:\s";
+/// Matches the task list items pull request templates add, such as `- [x] Tests`
+const CHECKBOX_PATTERN: &str = r"^\s*(?:[-*+]|\d+\.)\s+\[[ xX]\]";
+/// CI directives that share the `[...]` syntax with feature lists
+const CI_DIRECTIVES: [&str; 5] = [
+    "skip ci",
+    "ci skip",
+    "no ci",
+    "skip actions",
+    "actions skip",
+];
+
+/// The type and scopes from a Conventional Commits summary such as `feat(billing): ...`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConventionalCommit {
+    pub commit_type: String,
+    pub scopes: Vec<String>,
+}
+
+/// Applies a project's feature extraction patterns to commit messages and forge labels.
+///
+/// When a pattern has a capture group the first group holds the features, otherwise
+/// messages use the whole match and labels use whatever is left once the match is removed.
+#[derive(Clone, Debug)]
+pub struct FeatureExtractor {
+    patterns: Vec<Regex>,
+    conventional_commit: Option<Regex>,
+    checkbox: Regex,
+}
+
+impl FeatureExtractor {
+    pub fn new(patterns: &[String]) -> Result<Self, FownerError> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                if pattern.trim().is_empty() {
+                    return Err(FownerError::InvalidFeaturePattern(
+                        "Patterns cannot be empty".to_string(),
+                    ));
+                }
+                Regex::new(pattern)
+                    .map_err(|e| FownerError::InvalidFeaturePattern(format!("{}: {}", pattern, e)))
+            })
+            .collect::<Result<Vec<Regex>, FownerError>>()?;
+        Ok(Self {
+            patterns,
+            conventional_commit: None,
+            checkbox: Regex::new(CHECKBOX_PATTERN).unwrap(),
+        })
+    }
+
+    /// Also treats Conventional Commits scopes as features when enabled
+    pub fn with_conventional_commits(mut self, enabled: bool) -> Self {
+        self.conventional_commit = if enabled {
+            Some(Regex::new(CONVENTIONAL_COMMIT_PATTERN).unwrap())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Parses the summary as a Conventional Commit, returns `None` unless the mode is enabled
+    pub fn conventional_commit(&self, summary: &str) -> Option<ConventionalCommit> {
+        let captures = self
+            .conventional_commit
+            .as_ref()?
+            .captures(summary.trim())?;
+        let commit_type = captures.get(1)?.as_str().to_lowercase();
+        let scopes = captures
+            .get(2)
+            .map(|scope| {
+                scope
+                    .as_str()
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(ConventionalCommit {
+            commit_type,
+            scopes,
+        })
+    }
+
+    pub fn for_commits() -> Self {
+        Self::new(&[DEFAULT_COMMIT_FEATURE_PATTERN.to_string()]).unwrap()
+    }
+
+    pub fn for_labels() -> Self {
+        Self::new(&[DEFAULT_LABEL_FEATURE_PATTERN.to_string()]).unwrap()
+    }
+
+    /// Collects the unique features matched anywhere in a commit message, skipping the
+    /// checklists, markdown links and CI directives squash merges copy into the body
+    pub fn extract_from_message(&self, message: &str) -> Vec<String> {
+        let message = message
+            .lines()
+            .filter(|line| !self.checkbox.is_match(line))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let mut features: Vec<String> = vec![];
+        for re in &self.patterns {
+            for captures in re.captures_iter(&message) {
+                let whole = captures.get(0).unwrap();
+                // A markdown link such as `[docs](https://...)`
+                if whole.as_str().ends_with(']') && message[whole.end()..].starts_with('(') {
+                    continue;
+                }
+                let names = captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|r| r.as_str().split(',').collect())
+                    .unwrap_or_else(Vec::new);
+                for name in names {
+                    let name = name.trim().to_string();
+                    if CI_DIRECTIVES.iter().any(|d| d.eq_ignore_ascii_case(&name)) {
+                        continue;
+                    }
+                    if !name.is_empty() && !features.contains(&name) {
+                        features.push(name);
+                    }
+                }
+            }
+        }
+        features
+    }
+
+    /// Returns the feature for a label if it matches any of the patterns
+    pub fn extract_from_label(&self, label: &str) -> Option<String> {
+        let label = label.trim();
+        self.patterns.iter().find_map(|re| {
+            let captures = re.captures(label)?;
+            let name = match captures.get(1) {
+                Some(feature) => feature.as_str().to_string(),
+                None => re.replace(label, "").to_string(),
+            };
+            let name = name.trim().to_string();
+            if name.is_empty() {
+                None
+            } else {
+                Some(name)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::features::{ConventionalCommit, FeatureExtractor};
+
+    #[test]
+    fn default_patterns() {
+        let commits = FeatureExtractor::for_commits();
+        assert_eq!(
+            commits.extract_from_message("Fix login [Auth, Sessions]\n\n[Auth] [Core-1]"),
+            vec!["Auth", "Sessions", "Core-1"]
+        );
+        assert!(commits.extract_from_message("No features here").is_empty());
+
+        let labels = FeatureExtractor::for_labels();
+        assert_eq!(
+            labels.extract_from_label("f: Billing"),
+            Some("Billing".to_string())
+        );
+        assert_eq!(
+            labels.extract_from_label("F:Billing"),
+            Some("Billing".to_string())
+        );
+        assert_eq!(labels.extract_from_label("bug"), None);
+        assert_eq!(labels.extract_from_label("f: "), None);
+    }
+
+    #[test]
+    fn squash_merge_body() {
+        let commits = FeatureExtractor::for_commits();
+        let message = "Add invoices (#42)
+
+* Add the invoice model [Billing]
+
+* Fix the totals [skip ci]
+
+## Checklist
+- [x] Tests
+- [ ] Docs
+1. [X] Changelog
+
+See the [docs](https://example.com/docs) and ![diagram](diagram.png), [Invoices] too
+
+Co-authored-by: Keith Simon <keith@example.com>";
+        assert_eq!(
+            commits.extract_from_message(message),
+            vec!["Billing", "Invoices"]
+        );
+    }
+
+    #[test]
+    fn custom_patterns() {
+        let commits = FeatureExtractor::new(&[
+            r"(?m)^feature:\s*(.+)$".to_string(),
+            r"#feature/([\w-]+)".to_string(),
+            r"\b[A-Z]+-\d+\b".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(
+            commits.extract_from_message(
+                "PROJ-12 Add exports #feature/reports\n\nfeature: Exports, CSV"
+            ),
+            vec!["Exports", "CSV", "reports", "PROJ-12"]
+        );
+
+        let labels = FeatureExtractor::new(&[r"^area/(.+)$".to_string()]).unwrap();
+        assert_eq!(
+            labels.extract_from_label("area/search"),
+            Some("search".to_string())
+        );
+        assert_eq!(labels.extract_from_label("f: search"), None);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(FeatureExtractor::new(&["[unclosed".to_string()]).is_err());
+        assert!(FeatureExtractor::new(&[" ".to_string()]).is_err());
+        assert!(FeatureExtractor::new(&[])
+            .unwrap()
+            .extract_from_message("[A]")
+            .is_empty());
+    }
+
+    #[test]
+    fn conventional_commits() {
+        let disabled = FeatureExtractor::for_commits();
+        assert_eq!(
+            disabled.conventional_commit("feat(billing): Add invoices"),
+            None
+        );
+
+        let extractor = FeatureExtractor::for_commits().with_conventional_commits(true);
+        assert_eq!(
+            extractor.conventional_commit("feat(billing): Add invoices"),
+            Some(ConventionalCommit {
+                commit_type: "feat".to_string(),
+                scopes: vec!["billing".to_string()]
+            })
+        );
+        assert_eq!(
+            extractor.conventional_commit("Fix(auth, sessions)!: Drop legacy tokens"),
+            Some(ConventionalCommit {
+                commit_type: "fix".to_string(),
+                scopes: vec!["auth".to_string(), "sessions".to_string()]
+            })
+        );
+        assert_eq!(
+            extractor.conventional_commit("chore: Bump deps"),
+            Some(ConventionalCommit {
+                commit_type: "chore".to_string(),
+                scopes: vec![]
+            })
+        );
+        assert_eq!(extractor.conventional_commit("Merge branch 'main'"), None);
+        assert_eq!(extractor.conventional_commit("feat:no space"), None);
+    }
+}
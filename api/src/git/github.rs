@@ -1,11 +1,12 @@
+use crate::git::features::FeatureExtractor;
 use crate::{FownerError, Project};
 use awc::Client;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 pub struct Github {
     pub api_url: String,
     api_token: Option<String>,
+    label_extractor: FeatureExtractor,
 }
 #[derive(Debug, Serialize, Deserialize)]
 struct Pr {
@@ -16,11 +17,13 @@ struct Pr {
 struct Label {
     pub name: Option<String>,
 }
-const LABEL_REGEX: &str = "^[fF]:\\s*";
-
 impl Github {
     pub fn new(api_url: String, api_token: Option<String>) -> Self {
-        Self { api_url, api_token }
+        Self {
+            api_url,
+            api_token,
+            label_extractor: FeatureExtractor::for_labels(),
+        }
     }
 
     /// https://docs.github.com/en/rest/commits/commits#list-pull-requests-associated-with-a-commit
@@ -43,19 +46,10 @@ impl Github {
         let mut res = req.send().await?;
         let pull_request_data: Vec<Pr> = res.json().await?;
         let mut labels = vec![];
-        let re = Regex::new(LABEL_REGEX).unwrap();
         for pr in pull_request_data {
             for label in pr.labels.iter().filter_map(|l| {
-                let name = l.name.clone().unwrap_or_default().trim().to_string();
-                if !re.is_match(name.as_str()) {
-                    return None;
-                }
-                let name = re.replace(name.as_str(), "").to_string();
-                if !name.is_empty() {
-                    Some(name)
-                } else {
-                    None
-                }
+                self.label_extractor
+                    .extract_from_label(l.name.clone().unwrap_or_default().as_str())
             }) {
                 labels.push(label);
             }
@@ -70,13 +64,18 @@ impl TryFrom<&Project> for Github {
     fn try_from(project: &Project) -> Result<Self, Self::Error> {
         let api_url = project.get_github_api_url()?;
         let api_token = project.github_api_token.clone();
-        Ok(Github::new(api_url, api_token))
+        Ok(Github {
+            label_extractor: project.label_feature_extractor()?,
+            ..Github::new(api_url, api_token)
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::git::features::DEFAULT_LABEL_FEATURE_PATTERN;
+    use regex::Regex;
     #[actix_web::test]
     async fn fetch_labels() {
         let github = Github::new(
@@ -90,7 +89,7 @@ mod test {
 
     #[test]
     fn label_regex() {
-        let re = Regex::new(LABEL_REGEX).unwrap();
+        let re = Regex::new(DEFAULT_LABEL_FEATURE_PATTERN).unwrap();
         assert!(re.is_match("F: Feature"));
         assert!(re.is_match("F:Feature"));
         assert!(re.is_match("F:      Feature"));
@@ -1,171 +1,553 @@
 use crate::errors::FownerError;
-use regex::Regex;
+use crate::git::features::FeatureExtractor;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
+/// Marks the start of the commit body in the git log format
+pub const GIT_BODY_START: char = '\x02';
+/// Marks the end of the commit body in the git log format
+pub const GIT_BODY_END: char = '\x03';
+
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct GitHistory {
     pub handle: String,
+    pub email: String,
+    pub committer_handle: String,
+    pub committer_email: String,
     pub sha: String,
     pub parent_sha: Option<Vec<String>>,
     pub timestamp: usize,
+    pub committer_timestamp: usize,
+    pub co_authors: Vec<GitIdentity>,
     pub summary: String,
+    pub body: String,
+    pub commit_type: Option<String>,
     pub files: Vec<String>,
+    pub renames: Vec<GitRename>,
+    pub line_changes: Vec<GitLineChange>,
     pub features: Vec<String>,
 }
 
+/// How merge commits are walked and which files they are credited with
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePolicy {
+    /// Only follow first parents, merges are diffed against their first parent and the
+    /// commits on merged branches are skipped
+    FirstParent,
+    /// Walk every commit and diff merges against their first parent
+    DiffFirstParent,
+    /// Leave merge commits out entirely
+    Ignore,
+}
+
+impl MergePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MergePolicy::FirstParent => "first_parent",
+            MergePolicy::DiffFirstParent => "diff_first_parent",
+            MergePolicy::Ignore => "ignore",
+        }
+    }
+}
+
+impl FromStr for MergePolicy {
+    type Err = FownerError;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "first_parent" => Ok(MergePolicy::FirstParent),
+            "diff_first_parent" => Ok(MergePolicy::DiffFirstParent),
+            "ignore" => Ok(MergePolicy::Ignore),
+            _ => Err(FownerError::GitError(format!(
+                "Unknown merge policy {}",
+                policy
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct GitIdentity {
+    pub handle: String,
+    pub email: String,
+}
+
+impl FromStr for GitIdentity {
+    type Err = FownerError;
+
+    /// Parses a `Name <email>` identity as used in commit trailers
+    fn from_str(identity: &str) -> Result<Self, Self::Err> {
+        let (handle, email) = identity
+            .trim()
+            .trim_end_matches('>')
+            .split_once('<')
+            .ok_or_else(|| FownerError::GitError(format!("Invalid identity {}", identity)))?;
+        Ok(GitIdentity {
+            handle: handle.trim().to_string(),
+            email: email.trim().to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct GitRename {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct GitLineChange {
+    pub path: String,
+    pub additions: u32,
+    pub deletions: u32,
+}
+
+/// The files a commit changed with the lines added and removed from each
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct GitDiffStats {
+    pub files: Vec<String>,
+    pub renames: Vec<GitRename>,
+    pub line_changes: Vec<GitLineChange>,
+}
+
+impl GitDiffStats {
+    /// Reads one line of `--raw --numstat` output
+    pub fn parse_line(&mut self, line: &str) {
+        if let Some(raw) = line.strip_prefix(':') {
+            // `--raw` entries: `:<modes> <hashes> <status>\t<path>`
+            // Renames include the previous path: `R<score>\t<from>\t<to>`
+            let parts = raw.split('\t').collect::<Vec<&str>>();
+            let status = parts
+                .first()
+                .and_then(|meta| meta.split(' ').next_back())
+                .unwrap_or_default();
+            match parts.as_slice() {
+                [_meta, from, to] if status.starts_with('R') => {
+                    self.renames.push(GitRename {
+                        from: unquote(from),
+                        to: unquote(to),
+                    });
+                    self.files.push(unquote(to));
+                }
+                [_meta, .., path] => self.files.push(unquote(path)),
+                _ => self.files.push(raw.to_string()),
+            }
+        } else {
+            // `--numstat` entries follow the raw entries in the same order: `<added>\t<deleted>\t<path>`
+            // Binary files report `-` for both counts
+            let mut parts = line.split('\t');
+            let additions = parts.next().unwrap_or_default().parse().unwrap_or(0);
+            let deletions = parts.next().unwrap_or_default().parse().unwrap_or(0);
+            if let Some(path) = self.files.get(self.line_changes.len()) {
+                self.line_changes.push(GitLineChange {
+                    path: path.clone(),
+                    additions,
+                    deletions,
+                });
+            }
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum GitState {
     Handle,
+    Email,
+    CommitterHandle,
+    CommitterEmail,
     Sha,
     ParentSha,
     Timestamp,
+    CommitterTimestamp,
+    CoAuthors,
     Summary,
+    Body,
     Files,
 }
 
 impl GitHistory {
-    /// Processes the specifically formatted git log to generate a vec of `GitHistory`
-    pub fn parse(history_string: String) -> Result<Vec<GitHistory>, FownerError> {
-        let mut history = vec![];
-        let mut row = GitHistory::default();
-        let mut state = GitState::Handle;
-        // Extracts features from any [Feature,Feature] list in the commit message
-        let re = Regex::new(r"\[([\w ,-]+)\]")?;
-
-        for line in history_string.split('\n') {
-            let line = line.trim().to_string();
-            if line == "---" {
-                // This pattern denotes the start of a new record
-                if state == GitState::Files {
-                    history.push(row.clone());
-                    state = GitState::Handle;
+    /// Extracts the features and Conventional Commits type from the summary and body
+    pub fn extract_features(&mut self, feature_extractor: &FeatureExtractor) {
+        self.features =
+            feature_extractor.extract_from_message(&format!("{}\n{}", self.summary, self.body));
+        if let Some(conventional) = feature_extractor.conventional_commit(&self.summary) {
+            for scope in conventional.scopes {
+                if !self.features.contains(&scope) {
+                    self.features.push(scope);
                 }
-                continue;
             }
+            self.commit_type = Some(conventional.commit_type);
+        }
+    }
 
-            match state {
-                GitState::Handle => {
-                    row = GitHistory {
-                        handle: line,
-                        ..GitHistory::default()
-                    };
-                    state = GitState::Sha;
-                }
-                GitState::Sha => {
-                    row.sha = line;
-                    state = GitState::ParentSha;
-                }
-                GitState::ParentSha => {
-                    row.parent_sha = match line.trim() {
-                        "" => None,
-                        _ => Some(line.trim().split(' ').map(String::from).collect()),
-                    };
-                    state = GitState::Timestamp;
-                }
-                GitState::Timestamp => {
-                    row.timestamp = usize::from_str(&line)?;
-                    state = GitState::Summary;
-                }
-                GitState::Summary => {
-                    row.summary = line.clone();
-                    if let Some(captures) = re.captures(&line) {
-                        let features = captures
-                            .get(1)
-                            .map(|r| r.as_str().split(',').collect())
-                            .unwrap_or_else(Vec::new)
-                            .iter()
-                            .map(|s| s.trim().to_string())
-                            .collect::<Vec<String>>();
-                        row.features = features;
+    pub fn is_merge(&self) -> bool {
+        self.parent_sha
+            .as_ref()
+            .map(|parents| parents.len() > 1)
+            .unwrap_or_default()
+    }
+
+    pub fn set_diff_stats(&mut self, stats: GitDiffStats) {
+        self.files = stats.files;
+        self.renames = stats.renames;
+        self.line_changes = stats.line_changes;
+    }
+
+    /// Processes a complete, specifically formatted git log to generate a vec of `GitHistory`
+    /// The log is newest first and the history is chronological ASC
+    #[cfg(test)]
+    pub fn parse(
+        history_string: String,
+        feature_extractor: &FeatureExtractor,
+    ) -> Result<Vec<GitHistory>, FownerError> {
+        let mut history = vec![];
+        let mut parser = GitHistoryParser::new(feature_extractor);
+        for raw_line in history_string.split('\n') {
+            if let Some(row) = parser.parse_line(raw_line)? {
+                history.push(row);
+            }
+        }
+        history.extend(parser.finish());
+        history.reverse();
+        Ok(history)
+    }
+}
+
+/// Parses the specifically formatted git log one line at a time, only the commit
+/// being read is held in memory
+pub struct GitHistoryParser<'a> {
+    row: GitHistory,
+    stats: GitDiffStats,
+    state: GitState,
+    feature_extractor: &'a FeatureExtractor,
+}
+
+impl<'a> GitHistoryParser<'a> {
+    pub fn new(feature_extractor: &'a FeatureExtractor) -> Self {
+        Self {
+            row: GitHistory::default(),
+            stats: GitDiffStats::default(),
+            state: GitState::Handle,
+            feature_extractor,
+        }
+    }
+
+    /// Returns the commit once `raw_line` completes it
+    pub fn parse_line(&mut self, raw_line: &str) -> Result<Option<GitHistory>, FownerError> {
+        let line = raw_line.trim().to_string();
+        // A `---` inside the commit body is part of the message, not a new record
+        if line == "---" && self.state != GitState::Body {
+            // This pattern denotes the start of a new record
+            if self.state == GitState::Files {
+                self.state = GitState::Handle;
+                return Ok(Some(self.take_row()));
+            }
+            return Ok(None);
+        }
+
+        match self.state {
+            GitState::Handle => {
+                self.row = GitHistory {
+                    handle: line,
+                    ..GitHistory::default()
+                };
+                self.state = GitState::Email;
+            }
+            GitState::Email => {
+                self.row.email = line;
+                self.state = GitState::CommitterHandle;
+            }
+            GitState::CommitterHandle => {
+                self.row.committer_handle = line;
+                self.state = GitState::CommitterEmail;
+            }
+            GitState::CommitterEmail => {
+                self.row.committer_email = line;
+                self.state = GitState::Sha;
+            }
+            GitState::Sha => {
+                self.row.sha = line;
+                self.state = GitState::ParentSha;
+            }
+            GitState::ParentSha => {
+                self.row.parent_sha = match line.trim() {
+                    "" => None,
+                    _ => Some(line.trim().split(' ').map(String::from).collect()),
+                };
+                self.state = GitState::Timestamp;
+            }
+            GitState::Timestamp => {
+                self.row.timestamp = clamp_timestamp(i64::from_str(&line)?);
+                self.state = GitState::CommitterTimestamp;
+            }
+            GitState::CommitterTimestamp => {
+                self.row.committer_timestamp = clamp_timestamp(i64::from_str(&line)?);
+                self.state = GitState::CoAuthors;
+            }
+            GitState::CoAuthors => {
+                // `Co-authored-by` trailer values separated by the unit separator
+                self.row.co_authors = line
+                    .split('\x1F')
+                    .filter_map(|identity| GitIdentity::from_str(identity).ok())
+                    .collect();
+                self.state = GitState::Summary;
+            }
+            GitState::Summary => {
+                self.row.summary = line;
+                self.state = GitState::Body;
+            }
+            GitState::Body => {
+                // The body is wrapped in STX/ETX so blank lines and `---` within it are kept intact
+                let body_line = raw_line
+                    .trim_end_matches('\r')
+                    .trim_start_matches(GIT_BODY_START);
+                match body_line.split_once(GIT_BODY_END) {
+                    Some((last_line, _)) => {
+                        self.row.body.push_str(last_line);
+                        self.row.body = self.row.body.trim().to_string();
+                        self.row.extract_features(self.feature_extractor);
+                        self.state = GitState::Files;
                     }
-                    state = GitState::Files;
+                    None => {
+                        self.row.body.push_str(body_line);
+                        self.row.body.push('\n');
+                    }
+                }
+            }
+            GitState::Files => {
+                if line.is_empty() {
+                    self.state = GitState::Handle;
+                    return Ok(Some(self.take_row()));
                 }
-                GitState::Files => {
-                    if line.is_empty() {
-                        state = GitState::Handle;
-                        history.push(row.clone());
-                    } else {
-                        row.files.push(line.to_string());
+                self.stats.parse_line(&line);
+            }
+        }
+        Ok(None)
+    }
+
+    fn take_row(&mut self) -> GitHistory {
+        let mut row = std::mem::take(&mut self.row);
+        row.set_diff_stats(std::mem::take(&mut self.stats));
+        row
+    }
+
+    /// Returns the last commit when the log ended without a trailing blank line
+    pub fn finish(&mut self) -> Option<GitHistory> {
+        if self.state == GitState::Files {
+            self.state = GitState::Handle;
+            Some(self.take_row())
+        } else {
+            None
+        }
+    }
+}
+
+/// Dates before 1970 are stored as the epoch rather than wrapping around
+pub fn clamp_timestamp(seconds: i64) -> usize {
+    seconds.max(0) as usize
+}
+
+/// Reverses the C-style quoting git applies to paths with quotes, backslashes or control
+/// characters even when `core.quotepath` is off
+pub fn unquote(path: &str) -> String {
+    let quoted = match path
+        .strip_prefix('"')
+        .and_then(|path| path.strip_suffix('"'))
+    {
+        Some(quoted) => quoted,
+        None => return path.to_string(),
+    };
+    let mut bytes = vec![];
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('a') => bytes.push(0x07),
+            Some('b') => bytes.push(0x08),
+            Some('t') => bytes.push(b'\t'),
+            Some('n') => bytes.push(b'\n'),
+            Some('v') => bytes.push(0x0B),
+            Some('f') => bytes.push(0x0C),
+            Some('r') => bytes.push(b'\r'),
+            // Non-ASCII bytes are written as three octal digits
+            Some(d @ '0'..='7') => {
+                let mut byte = d.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    if let Some(d) = chars.peek().and_then(|d| d.to_digit(8)) {
+                        byte = byte * 8 + d;
+                        chars.next();
                     }
                 }
+                bytes.push(byte as u8);
+            }
+            Some(c) => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
             }
+            None => bytes.push(b'\\'),
         }
-        history.reverse();
-        Ok(history)
     }
+    String::from_utf8_lossy(&bytes).to_string()
 }
 
 #[cfg(test)]
 mod test {
-    use crate::git::history::GitHistory;
+    use crate::git::features::FeatureExtractor;
+    use crate::git::history::{
+        clamp_timestamp, unquote, GitHistory, GitIdentity, GitLineChange, GitRename,
+    };
+
+    #[test]
+    fn negative_timestamps() {
+        assert_eq!(clamp_timestamp(1655391971), 1655391971);
+        assert_eq!(clamp_timestamp(-86400), 0);
+    }
+
+    #[test]
+    fn unquote_paths() {
+        assert_eq!(unquote("src/main.rs"), "src/main.rs");
+        assert_eq!(unquote("docs/naïve.md"), "docs/naïve.md");
+        assert_eq!(unquote(r#""docs/na\303\257ve.md""#), "docs/naïve.md");
+        assert_eq!(
+            unquote(r#""say \"hi\"\tand\\bye.md""#),
+            "say \"hi\"\tand\\bye.md"
+        );
+    }
 
     #[test]
     fn process_rows() {
         let history_string = r#"---
 Krakaw
+krakaw@example.com
+GitHub
+noreply@github.com
 74ebe78fe948f83d42a59d021b5a411b7ac13981
 685dc3d64c54719dadd46b9b7bc4cb0a994728e1 43ce09e6475f4013fc00a6bdfefc4a53e4ddff68
 1655513699
+1655513720
+
 Merge branch 'main' of github.com:Krakaw/fowner
+{STX}{ETX}
 ---
 Krakaw
+krakaw@example.com
+Krakaw
+krakaw@example.com
 685dc3d64c54719dadd46b9b7bc4cb0a994728e1
 790f3db24ba26480725dbfc52594b4ce5d4a0b13
 1655513635
+1655513635
+
 Added models and initial DB interactions
-.gitignore
-Cargo.toml
-src/db/migrations.rs
-src/db/mod.rs
-src/db/models/commit.rs
-src/db/models/feature.rs
-src/db/models/file.rs
-src/db/models/mod.rs
-src/db/models/owner.rs
-src/db/models/project.rs
-src/git/history.rs
-src/git/mod.rs
-src/git/repo.rs
-src/main.rs
+{STX}Squashed from the models branch
+
+---
+  * Added [Models, Migrations]
+[Core_Feature-1]{ETX}
+:100644 100644 0000000 0000000 M	.gitignore
+:100644 100644 0000000 0000000 M	Cargo.toml
+:100644 100644 0000000 0000000 M	src/db/migrations.rs
+:100644 100644 0000000 0000000 M	src/db/mod.rs
+:100644 100644 0000000 0000000 M	src/db/models/commit.rs
+:100644 100644 0000000 0000000 M	src/db/models/feature.rs
+:100644 100644 0000000 0000000 M	src/db/models/file.rs
+:100644 100644 0000000 0000000 M	src/db/models/mod.rs
+:100644 100644 0000000 0000000 M	src/db/models/owner.rs
+:100644 100644 0000000 0000000 M	src/db/models/project.rs
+:100644 100644 0000000 0000000 M	src/git/history.rs
+:100644 100644 0000000 0000000 M	src/git/mod.rs
+:100644 100644 0000000 0000000 M	src/git/repo.rs
+:100644 100644 0000000 0000000 M	src/main.rs
+2	1	.gitignore
+2	1	Cargo.toml
+2	1	src/db/migrations.rs
+2	1	src/db/mod.rs
+2	1	src/db/models/commit.rs
+2	1	src/db/models/feature.rs
+2	1	src/db/models/file.rs
+2	1	src/db/models/mod.rs
+2	1	src/db/models/owner.rs
+2	1	src/db/models/project.rs
+2	1	src/git/history.rs
+2	1	src/git/mod.rs
+2	1	src/git/repo.rs
+2	1	src/main.rs
 
 ---
 Krakaw
+krakaw@example.com
+Krakaw
+krakaw@example.com
 43ce09e6475f4013fc00a6bdfefc4a53e4ddff68
 790f3db24ba26480725dbfc52594b4ce5d4a0b13
 1655451712
+1655451712
+Keith Simon <keith@example.com>{US}Bob <bob@example.com>
 Initial commit of feature [AnyFeature] tracking based on git history
-src/unknown.rs
+{STX}{ETX}
+:100644 100644 0000000 0000000 M	src/unknown.rs
+2	1	src/unknown.rs
 
 ---
 Krakaw
+krakaw@example.com
+Krakaw
+krakaw@example.com
 790f3db24ba26480725dbfc52594b4ce5d4a0b13
 c60c24663d3b67fdee8079a18cbe40c843932b48
 1655395513
+1655395513
+
 Initial commit of feature tracking based on git history [Core_Feature-1, History 2]
-.gitignore
-Cargo.toml
-README.md
-src/git/history.rs
-src/git/mod.rs
-src/main.rs
+{STX}{ETX}
+:100644 100644 0000000 0000000 M	.gitignore
+:100644 100644 0000000 0000000 M	Cargo.toml
+:100644 100644 0000000 0000000 M	README.md
+:100644 100644 0000000 0000000 M	src/git/history.rs
+:100644 100644 0000000 0000000 M	src/git/mod.rs
+:100644 100644 0000000 0000000 M	src/main.rs
+2	1	.gitignore
+2	1	Cargo.toml
+2	1	README.md
+2	1	src/git/history.rs
+2	1	src/git/mod.rs
+2	1	src/main.rs
 
 ---
 Keith Simon
+keith.simon@example.com
+Keith Simon
+keith.simon@example.com
 c60c24663d3b67fdee8079a18cbe40c843932b48
 
 1655391971
+1655391971
+
 Initial commit [Core_Feature-1]
-.gitignore
-README.md
+{STX}{ETX}
+:000000 100644 0000000 0000000 A	.gitignore
+:000000 100644 0000000 0000000 A	README.md
+10	0	.gitignore
+9	0	README.md
 
 "#
-        .to_string();
-        let history = GitHistory::parse(history_string).unwrap();
+        .replace("{US}", "\x1F")
+        .replace("{STX}", "\x02")
+        .replace("{ETX}", "\x03");
+        let history = GitHistory::parse(history_string, &FeatureExtractor::for_commits()).unwrap();
         assert_eq!(history.len(), 5);
         let initial = history.first().unwrap();
         assert_eq!(initial.sha, "c60c24663d3b67fdee8079a18cbe40c843932b48");
         assert_eq!(initial.handle, "Keith Simon");
+        assert_eq!(initial.email, "keith.simon@example.com");
+        assert_eq!(initial.committer_handle, "Keith Simon");
+        assert_eq!(initial.committer_email, "keith.simon@example.com");
+        assert_eq!(initial.committer_timestamp, 1655391971);
         assert_eq!(initial.features, vec!["Core_Feature-1"]);
         assert_eq!(initial.files, vec![".gitignore", "README.md"]);
         assert_eq!(initial.parent_sha, None);
@@ -180,11 +562,42 @@ README.md
             Some(vec!["c60c24663d3b67fdee8079a18cbe40c843932b48".to_string()])
         );
 
+        let body = history.get(3).unwrap();
+        assert_eq!(
+            body.body,
+            "Squashed from the models branch\n\n---\n  * Added [Models, Migrations]\n[Core_Feature-1]"
+        );
+        assert_eq!(
+            body.features,
+            vec!["Models", "Migrations", "Core_Feature-1"]
+        );
+        assert_eq!(body.files.len(), 14);
+        assert_eq!(initial.body, "");
+
         let extract_anywhere = history.get(2).unwrap();
         assert_eq!(extract_anywhere.features, vec!["AnyFeature"]);
+        assert_eq!(
+            extract_anywhere.co_authors,
+            vec![
+                GitIdentity {
+                    handle: "Keith Simon".to_string(),
+                    email: "keith@example.com".to_string()
+                },
+                GitIdentity {
+                    handle: "Bob".to_string(),
+                    email: "bob@example.com".to_string()
+                }
+            ]
+        );
+        assert!(initial.co_authors.is_empty());
 
         let last = history.last().unwrap();
         assert_eq!(last.sha, "74ebe78fe948f83d42a59d021b5a411b7ac13981");
+        assert_eq!(last.email, "krakaw@example.com");
+        assert_eq!(last.committer_handle, "GitHub");
+        assert_eq!(last.committer_email, "noreply@github.com");
+        assert_eq!(last.timestamp, 1655513699);
+        assert_eq!(last.committer_timestamp, 1655513720);
         assert_eq!(
             last.parent_sha,
             Some(vec![
@@ -193,4 +606,128 @@ README.md
             ])
         );
     }
+
+    #[test]
+    fn process_renames() {
+        let history_string = r#"---
+Krakaw
+krakaw@example.com
+Krakaw
+krakaw@example.com
+9d3b2e952f8e97951fb946355ffc2f6159c3227a
+68520e7a0c7f40e135f18b351a294c7840c15e8d
+1655513699
+1655513699
+
+Moved the git modules
+{STX}{ETX}
+:100644 100644 0000000 0000000 R100	src/repo.rs	src/git/repo.rs
+:100644 100644 0000000 0000000 R087	src/history.rs	src/git/history.rs
+:100644 100644 0000000 0000000 M	src/main.rs
+:100644 000000 0000000 0000000 D	src/old.rs
+0	0	src/repo.rs => src/git/repo.rs
+0	0	src/history.rs => src/git/history.rs
+2	1	src/main.rs
+0	10	src/old.rs
+
+---
+Krakaw
+krakaw@example.com
+Krakaw
+krakaw@example.com
+68520e7a0c7f40e135f18b351a294c7840c15e8d
+
+1655513635
+1655513635
+
+Initial commit
+{STX}{ETX}
+:000000 100644 0000000 0000000 A	src/repo.rs
+:000000 100644 0000000 0000000 A	src/history.rs
+:000000 100644 0000000 0000000 A	src/main.rs
+:000000 100644 0000000 0000000 A	src/old.rs
+11	0	src/repo.rs
+14	0	src/history.rs
+11	0	src/main.rs
+10	0	src/old.rs
+
+"#
+        .replace("{STX}", "\x02")
+        .replace("{ETX}", "\x03");
+        let history = GitHistory::parse(history_string, &FeatureExtractor::for_commits()).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].renames.is_empty());
+
+        let moved = history.last().unwrap();
+        assert_eq!(
+            moved.files,
+            vec![
+                "src/git/repo.rs",
+                "src/git/history.rs",
+                "src/main.rs",
+                "src/old.rs"
+            ]
+        );
+        assert_eq!(
+            moved.renames,
+            vec![
+                GitRename {
+                    from: "src/repo.rs".to_string(),
+                    to: "src/git/repo.rs".to_string()
+                },
+                GitRename {
+                    from: "src/history.rs".to_string(),
+                    to: "src/git/history.rs".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn process_line_changes() {
+        let history_string = r#"---
+Krakaw
+krakaw@example.com
+Krakaw
+krakaw@example.com
+68520e7a0c7f40e135f18b351a294c7840c15e8d
+
+1655513635
+1655513635
+
+Initial commit
+{STX}{ETX}
+:000000 100644 0000000 0000000 A	src/main.rs
+:000000 100644 0000000 0000000 A	docs/example.png
+:100644 100644 0000000 0000000 R090	src/repo.rs	src/git/repo.rs
+120	0	src/main.rs
+-	-	docs/example.png
+3	1	src/{repo.rs => git/repo.rs}
+
+"#
+        .replace("{STX}", "\x02")
+        .replace("{ETX}", "\x03");
+        let history = GitHistory::parse(history_string, &FeatureExtractor::for_commits()).unwrap();
+        let commit = history.first().unwrap();
+        assert_eq!(
+            commit.line_changes,
+            vec![
+                GitLineChange {
+                    path: "src/main.rs".to_string(),
+                    additions: 120,
+                    deletions: 0
+                },
+                GitLineChange {
+                    path: "docs/example.png".to_string(),
+                    additions: 0,
+                    deletions: 0
+                },
+                GitLineChange {
+                    path: "src/git/repo.rs".to_string(),
+                    additions: 3,
+                    deletions: 1
+                }
+            ]
+        );
+    }
 }
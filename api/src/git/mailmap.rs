@@ -0,0 +1,191 @@
+use crate::errors::FownerError;
+
+/// The canonical name and email a mailmap resolves a commit identity to
+#[derive(Clone, Debug, PartialEq)]
+pub struct MailmapIdentity {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+/// One line of a mailmap, the proper identity followed by the commit identity it replaces
+#[derive(Clone, Debug, PartialEq)]
+struct MailmapEntry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+/// Resolves the names and emails used in commits to canonical identities, following the
+/// `.mailmap` format described in `git help gitmailmap`.
+///
+/// Emails and names are matched case-insensitively, an entry naming both the commit name
+/// and email takes precedence over one naming only the email, and later entries override
+/// earlier ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mailmap {
+    entries: Vec<MailmapEntry>,
+}
+
+impl Mailmap {
+    pub fn parse(contents: &str) -> Result<Self, FownerError> {
+        let mut entries = vec![];
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = Self::parse_line(line).ok_or_else(|| {
+                FownerError::InvalidMailmap(format!("line {}: {}", number + 1, line))
+            })?;
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Splits `Name <email> Name <email>` into its names and emails, anything after the
+    /// last email is a comment
+    fn parse_line(line: &str) -> Option<MailmapEntry> {
+        let mut pairs = vec![];
+        let mut rest = line;
+        while let Some(start) = rest.find('<') {
+            let end = start + rest[start..].find('>')?;
+            let name = rest[..start].trim();
+            let email = rest[start + 1..end].trim();
+            pairs.push((
+                Some(name.to_string()).filter(|n| !n.is_empty()),
+                email.to_string(),
+            ));
+            rest = &rest[end + 1..];
+        }
+        let trailing = rest.trim();
+        if !(trailing.is_empty() || trailing.starts_with('#')) {
+            return None;
+        }
+        match pairs.as_slice() {
+            [(proper_name, commit_email)] if proper_name.is_some() && !commit_email.is_empty() => {
+                Some(MailmapEntry {
+                    proper_name: proper_name.clone(),
+                    proper_email: None,
+                    commit_name: None,
+                    commit_email: commit_email.clone(),
+                })
+            }
+            [(proper_name, proper_email), (commit_name, commit_email)]
+                if !commit_email.is_empty() =>
+            {
+                Some(MailmapEntry {
+                    proper_name: proper_name.clone(),
+                    proper_email: Some(proper_email.clone()).filter(|e| !e.is_empty()),
+                    commit_name: commit_name.clone(),
+                    commit_email: commit_email.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the canonical identity for a commit's name and email, `None` when no
+    /// entry matches
+    pub fn resolve(&self, name: &str, email: &str) -> Option<MailmapIdentity> {
+        let matches_email = |entry: &&MailmapEntry| entry.commit_email.eq_ignore_ascii_case(email);
+        let entry = self
+            .entries
+            .iter()
+            .rev()
+            .filter(matches_email)
+            .find(|entry| {
+                entry
+                    .commit_name
+                    .as_ref()
+                    .map(|n| n.eq_ignore_ascii_case(name))
+                    .unwrap_or_default()
+            })
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .rev()
+                    .filter(matches_email)
+                    .find(|entry| entry.commit_name.is_none())
+            })?;
+        Some(MailmapIdentity {
+            name: entry.proper_name.clone(),
+            email: entry
+                .proper_email
+                .clone()
+                .unwrap_or_else(|| email.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::mailmap::{Mailmap, MailmapIdentity};
+
+    #[test]
+    fn resolve() {
+        let mailmap = Mailmap::parse(
+            r#"
+# Keep the canonical identities first
+Keith Simon <keith@example.com>
+<keith@example.com> <ksimon@old-company.com>
+Keith Simon <keith@example.com> keith <keith@localhost>
+Jane Doe <jane@example.com> Jane <shared@example.com> # pairing machine
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            mailmap.resolve("Kieth", "KEITH@example.com"),
+            Some(MailmapIdentity {
+                name: Some("Keith Simon".to_string()),
+                email: "KEITH@example.com".to_string()
+            })
+        );
+        assert_eq!(
+            mailmap.resolve("Keith", "ksimon@old-company.com"),
+            Some(MailmapIdentity {
+                name: None,
+                email: "keith@example.com".to_string()
+            })
+        );
+        assert_eq!(
+            mailmap.resolve("keith", "keith@localhost").map(|i| i.email),
+            Some("keith@example.com".to_string())
+        );
+        // Entries with a commit name only match that name
+        assert_eq!(mailmap.resolve("root", "keith@localhost"), None);
+        assert_eq!(
+            mailmap
+                .resolve("jane", "shared@example.com")
+                .map(|i| i.email),
+            Some("jane@example.com".to_string())
+        );
+        assert_eq!(mailmap.resolve("Bob", "shared@example.com"), None);
+    }
+
+    #[test]
+    fn later_entries_win() {
+        let mailmap = Mailmap::parse(
+            "<old@example.com> <keith@example.com>\n<new@example.com> <keith@example.com>",
+        )
+        .unwrap();
+        assert_eq!(
+            mailmap
+                .resolve("Keith", "keith@example.com")
+                .map(|i| i.email),
+            Some("new@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!(Mailmap::parse("Keith Simon").is_err());
+        assert!(Mailmap::parse("<keith@example.com").is_err());
+        assert!(Mailmap::parse("<keith@example.com> trailing").is_err());
+        assert!(Mailmap::parse("# only comments\n\n").unwrap().is_empty());
+    }
+}
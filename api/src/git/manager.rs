@@ -1,22 +1,46 @@
 use std::path::PathBuf;
-use std::process::Command;
+use std::time::Duration;
 
-use chrono::{Duration, NaiveDateTime};
-use log::{debug, trace};
-
-use crate::git::history::GitHistory;
+use crate::git::backend::{GitBackend, GitBackendKind, GitHistoryIter};
+use crate::git::blame::GitBlame;
+use crate::git::credentials::SealedCredentials;
+use crate::git::features::FeatureExtractor;
+use crate::git::history::{GitDiffStats, GitHistory, MergePolicy};
+use crate::git::remote::RemoteConfig;
+use crate::git::subpaths::Subpaths;
+use crate::git::tag::GitTag;
 use crate::FownerError;
 
-const GIT_HISTORY_LOG_FORMAT: &str = "---%n%an%n%H%n%P%n%ad%n%s";
-
+#[derive(Clone)]
 pub struct GitManager {
     pub path: PathBuf,
     pub url: Option<String>,
+    pub backend: GitBackendKind,
+    /// Used for this repository's clones and fetches instead of any ambient credentials
+    pub credentials: Option<SealedCredentials>,
+    /// The remotes the repository is cloned from and fetches, and how it is cloned
+    pub remotes: RemoteConfig,
+    /// How long each git command may run before it is killed
+    pub timeout: Duration,
 }
 
 impl GitManager {
-    pub fn init(path: PathBuf, url: Option<String>) -> Result<Self, FownerError> {
-        let git_manager = Self { path, url };
+    pub fn init(
+        path: PathBuf,
+        url: Option<String>,
+        backend: GitBackendKind,
+        credentials: Option<SealedCredentials>,
+        remotes: RemoteConfig,
+        timeout: Duration,
+    ) -> Result<Self, FownerError> {
+        let git_manager = Self {
+            path,
+            url,
+            backend,
+            credentials,
+            remotes,
+            timeout,
+        };
         if !git_manager.path.exists() {
             // Create the path if it doesn't exist
             std::fs::create_dir_all(&git_manager.path)?;
@@ -28,19 +52,23 @@ impl GitManager {
         Ok(git_manager)
     }
 
+    fn backend(&self) -> Box<dyn GitBackend> {
+        self.backend.open(&self.path, None, self.timeout)
+    }
+
+    /// A backend for the clones, fetches and prunes, the only place the credentials are opened
+    fn remote_backend(&self) -> Result<Box<dyn GitBackend>, FownerError> {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .map(SealedCredentials::open)
+            .transpose()?;
+        Ok(self.backend.open(&self.path, credentials, self.timeout))
+    }
+
     pub fn cleanup(&self) -> Result<String, FownerError> {
         if self.is_valid_repo()? {
-            // TODO: Should probably allow custom origins
-            let args = vec!["remote", "prune", "origin"];
-            let result = Command::new("git")
-                .current_dir(&self.path)
-                .args(args)
-                .arg(".")
-                .output()?;
-            if !result.status.success() {
-                return Err(FownerError::Execution(String::from_utf8(result.stderr)?));
-            }
-            Ok(String::from_utf8(result.stdout)?)
+            self.remote_backend()?.cleanup(&self.remotes)
         } else {
             Err(FownerError::GitError(
                 "Cannot cleanup a non-existent repo".to_string(),
@@ -50,104 +78,161 @@ impl GitManager {
 
     /// Parse the git log output and return GitHistory
     /// The history is chronological ASC
-    /// If `since` is passed in it only takes commits 1 second AFTER that datetime
+    /// `refs` are logged together, an empty list logs HEAD
+    /// Commits reachable from any of the `exclude` shas are skipped
+    /// Only the changes under the `subpaths` are kept, with paths relative to them
     pub fn parse_history(
         &self,
-        since: Option<NaiveDateTime>,
+        refs: &[String],
+        exclude: &[String],
+        subpaths: &Subpaths,
+        merge_policy: Option<MergePolicy>,
+        feature_extractor: &FeatureExtractor,
     ) -> Result<Vec<GitHistory>, FownerError> {
-        GitHistory::parse(self.history(since)?)
-    }
-
-    /// Returns the raw git log history string
-    pub fn history(&self, since: Option<NaiveDateTime>) -> Result<String, FownerError> {
-        let mut args = vec![
-            "--no-pager".to_string(),
-            "log".to_string(),
-            "--name-only".to_string(),
-            format!("--pretty=format:{}", GIT_HISTORY_LOG_FORMAT),
-            "--date=unix".to_string(),
-        ];
-
-        if let Some(since) = since {
-            let after = format!(
-                "--after=\"{}\"",
-                since
-                    .checked_add_signed(Duration::seconds(1))
-                    .unwrap_or(since)
-                    .format("%Y-%m-%dT%H:%M:%S.0Z")
-            );
-            debug!("Fetching Commits After: {}", after);
-            args.push(after);
-        }
-        trace!("git {}", args.join(" "));
-        let result = Command::new("git")
-            .current_dir(&self.path)
-            .args(args)
-            .arg(".")
-            .output()?;
-        if !result.status.success() {
-            return Err(FownerError::Execution(String::from_utf8(result.stderr)?));
-        }
-        Ok(String::from_utf8(result.stdout)?)
+        self.history_stream(refs, exclude, subpaths, merge_policy, feature_extractor)?
+            .collect()
+    }
+
+    /// Streams the history oldest first, parsing each commit only once it is reached
+    pub fn history_stream<'a>(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        subpaths: &Subpaths,
+        merge_policy: Option<MergePolicy>,
+        feature_extractor: &'a FeatureExtractor,
+    ) -> Result<GitHistoryIter<'a>, FownerError> {
+        let history = self.backend().history(
+            refs,
+            exclude,
+            &subpaths.pathspecs(),
+            merge_policy,
+            feature_extractor,
+        )?;
+        let subpaths = subpaths.clone();
+        Ok(Box::new(history.map(move |git_history| {
+            git_history.map(|mut git_history| {
+                subpaths.apply(&mut git_history);
+                git_history
+            })
+        })))
+    }
+
+    /// Counts the commits `history_stream` will walk for the same arguments
+    pub fn count_history(
+        &self,
+        refs: &[String],
+        exclude: &[String],
+        subpaths: &Subpaths,
+        merge_policy: Option<MergePolicy>,
+    ) -> Result<usize, FownerError> {
+        self.backend()
+            .count_history(refs, exclude, &subpaths.pathspecs(), merge_policy)
+    }
+
+    /// Returns the name of the checked out branch, or `HEAD` when detached
+    pub fn current_branch(&self) -> Result<String, FownerError> {
+        self.backend().current_branch()
+    }
+
+    /// Resolves a branch name to the ref to log, preferring the remote tracking branch
+    /// so branches that are not checked out locally are still up to date after a fetch
+    pub fn resolve_ref(&self, name: &str) -> Result<String, FownerError> {
+        let backend = self.backend();
+        [
+            format!("refs/remotes/{}/{}", self.remotes.name, name),
+            format!("refs/heads/{}", name),
+            name.to_string(),
+        ]
+        .iter()
+        .find_map(|candidate| backend.verify_ref(candidate))
+        .ok_or_else(|| FownerError::GitError(format!("Unknown ref {}", name)))
+    }
+
+    /// Returns the sha of every commit reachable from `git_ref`
+    pub fn rev_list(&self, git_ref: &str) -> Result<Vec<String>, FownerError> {
+        self.backend().rev_list(git_ref)
+    }
+
+    /// Returns every tag with the commit it points to
+    pub fn tags(&self) -> Result<Vec<GitTag>, FownerError> {
+        self.backend().tags()
+    }
+
+    /// Returns the sha `git_ref` currently points to
+    pub fn rev_parse(&self, git_ref: &str) -> Result<String, FownerError> {
+        self.backend().rev_parse(git_ref)
+    }
+
+    /// Checks the commit exists in the local repository
+    pub fn commit_exists(&self, sha: &str) -> Result<bool, FownerError> {
+        self.backend().commit_exists(sha)
+    }
+
+    /// Returns the sha HEAD currently points to
+    pub fn head_sha(&self) -> Result<String, FownerError> {
+        self.backend().rev_parse("HEAD")
+    }
+
+    /// Returns the paths of every file tracked at HEAD
+    pub fn files_at_head(&self) -> Result<Vec<String>, FownerError> {
+        self.backend().files_at_head()
+    }
+
+    /// Returns the contents of `path` at HEAD, `None` when it is not tracked
+    pub fn read_at_head(&self, path: &str) -> Result<Option<String>, FownerError> {
+        self.backend().read_at_head(path)
     }
 
+    /// Returns the paths staged for the next commit
+    pub fn staged_files(&self) -> Result<Vec<String>, FownerError> {
+        self.backend().staged_files()
+    }
+
+    /// Returns the directory git runs the repository's hooks from
+    pub fn hooks_dir(&self) -> Result<PathBuf, FownerError> {
+        self.backend().hooks_dir()
+    }
+
+    /// Returns the files changed by the commit with the lines added and removed
+    #[allow(dead_code)]
+    pub fn diff_stats(&self, sha: &str) -> Result<GitDiffStats, FownerError> {
+        self.backend().diff_stats(sha)
+    }
+
+    /// Returns the number of lines each author still owns in `path` at HEAD
+    pub fn blame(&self, path: &str) -> Result<Vec<GitBlame>, FownerError> {
+        self.backend().blame(path)
+    }
+
+    /// Fetches every remote, checkouts are then moved to their upstream, following force-pushes
     pub fn fetch(&self) -> Result<(), FownerError> {
-        Command::new("git")
-            .current_dir(&self.path)
-            .arg("reset")
-            .arg("--hard")
-            .output()
-            .map_err(|e| FownerError::GitError(format!("Reset error {}", e)))?;
-        let result = Command::new("git")
-            .current_dir(&self.path)
-            .arg("pull")
-            .output()
-            .map_err(|e| FownerError::GitError(format!("Fetch error {}", e)))?;
-        if !result.status.success() {
-            return Err(FownerError::Execution(String::from_utf8(result.stderr)?));
-        } else {
-            debug!("{}", String::from_utf8(result.stdout)?);
-        }
-        Ok(())
+        self.remote_backend()?.fetch(&self.remotes)
     }
 
     pub fn is_valid_repo(&self) -> Result<bool, FownerError> {
-        let result = Command::new("git")
-            .current_dir(&self.path)
-            .arg("status")
-            .output()
-            .map_err(|e| FownerError::GitError(format!("Status error {}", e)));
-        match result {
-            Ok(output) => Ok(output.status.success()),
-            Err(e) => {
-                debug!("Checking if repo is valid failed {:?}", e);
-                Ok(false)
-            }
-        }
+        Ok(self.backend().is_valid_repo())
     }
 
     pub fn clone(&self) -> Result<(), FownerError> {
-        let result = Command::new("git")
-            .current_dir(&self.path)
-            .arg("clone")
-            .arg(&self.url.clone().unwrap_or_default())
-            .arg(".")
-            .output()
-            .map_err(|e| FownerError::GitError(format!("Clone error {}", e)))?;
-        if !result.status.success() {
-            return Err(FownerError::Execution(String::from_utf8(result.stderr)?));
-        } else {
-            debug!("{}", String::from_utf8(result.stdout)?);
-        }
-
-        Ok(())
+        self.remote_backend()?
+            .clone_repo(&self.url.clone().unwrap_or_default(), &self.remotes)
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::git::backend::{GitBackendKind, DEFAULT_GIT_TIMEOUT};
+    use crate::git::credentials::{GitCredentials, SealedCredentials};
+    use crate::git::features::FeatureExtractor;
     use crate::git::manager::GitManager;
+    use crate::git::remote::{CloneMode, GitRemote, RemoteConfig};
+    use crate::git::subpaths::Subpaths;
+    use crate::test::builders::repo_builder::RepoBuilder;
     use crate::test::tests::TestHandler;
+    use crate::FownerError;
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn initialize_new_remote_repo() {
@@ -157,14 +242,324 @@ mod test {
         let git_manager = GitManager::init(
             repo_dir.clone(),
             Some("https://github.com/Krakaw/empty.git".to_string()),
+            GitBackendKind::Cli,
+            None,
+            RemoteConfig::default(),
+            DEFAULT_GIT_TIMEOUT,
         )
         .unwrap();
-        let history = git_manager.history(None).unwrap();
+        let history = git_manager
+            .parse_history(
+                &[],
+                &[],
+                &Subpaths::default(),
+                None,
+                &FeatureExtractor::for_commits(),
+            )
+            .unwrap();
 
-        assert!(history.starts_with("---"));
-        assert!(history.contains("d2b7bc86de36a40c2f32cf44c1931a38163bfb51"));
+        assert!(history
+            .iter()
+            .any(|h| h.sha == "d2b7bc86de36a40c2f32cf44c1931a38163bfb51"));
 
         // Fetch should succeed
         assert!(git_manager.fetch().is_ok());
     }
+
+    #[test]
+    fn clone_missing_repo() {
+        let handler = TestHandler::init();
+        for backend in [GitBackendKind::Cli, GitBackendKind::Library] {
+            let result = GitManager::init(
+                handler.tmp_dir.join(format!("{:?}", backend)),
+                Some(
+                    handler
+                        .tmp_dir
+                        .join("missing")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+                backend,
+                None,
+                RemoteConfig::default(),
+                DEFAULT_GIT_TIMEOUT,
+            );
+            assert!(result.is_err(), "{:?} cloned a missing repository", backend);
+        }
+    }
+
+    #[test]
+    fn fetch_without_upstream() {
+        let handler = TestHandler::init();
+        let origin = RepoBuilder::init(&handler.tmp_dir.join("origin"));
+        origin.write("src/main.rs", "fn main() {}\n");
+        origin.commit("First");
+        let git_manager = GitManager::init(
+            handler.tmp_dir.join("clone"),
+            Some(origin.path.to_string_lossy().to_string()),
+            GitBackendKind::Cli,
+            None,
+            RemoteConfig::default(),
+            DEFAULT_GIT_TIMEOUT,
+        )
+        .unwrap();
+        assert!(git_manager.fetch().is_ok());
+        // The reset to the upstream fails, the fetch must not report success
+        RepoBuilder {
+            path: git_manager.path.clone(),
+        }
+        .git(&["branch", "--unset-upstream"]);
+        assert!(git_manager.fetch().is_err());
+    }
+
+    #[test]
+    fn clone_with_credentials() {
+        let handler = TestHandler::init();
+        let origin = RepoBuilder::init(&handler.tmp_dir.join("origin"));
+        origin.write("src/main.rs", "fn main() {}\n");
+        let sha = origin.commit("First");
+        let credentials = GitCredentials::HttpsToken {
+            token: "unused".to_string(),
+            username: None,
+        };
+        for backend in [GitBackendKind::Cli, GitBackendKind::Library] {
+            let git_manager = GitManager::init(
+                handler.tmp_dir.join(format!("{:?}", backend)),
+                Some(origin.path.to_string_lossy().to_string()),
+                backend,
+                Some(SealedCredentials::seal(&credentials).unwrap()),
+                RemoteConfig::default(),
+                DEFAULT_GIT_TIMEOUT,
+            )
+            .unwrap();
+            assert_eq!(git_manager.head_sha().unwrap(), sha);
+            assert!(git_manager.fetch().is_ok());
+        }
+    }
+
+    #[test]
+    fn remotes_and_clone_modes() {
+        let handler = TestHandler::init();
+        let origin = RepoBuilder::init(&handler.tmp_dir.join("origin"));
+        origin.write("src/main.rs", "fn main() {}\n");
+        let first = origin.commit("First");
+        let fork = RepoBuilder::init(&handler.tmp_dir.join("fork"));
+        fork.write("fork.rs", "fn fork() {}\n");
+        let fork_sha = fork.commit("Fork");
+        let fork_remote = GitRemote {
+            name: "fork".to_string(),
+            url: fork.path.to_string_lossy().to_string(),
+        };
+        for backend in [GitBackendKind::Cli, GitBackendKind::Library] {
+            for clone_mode in [CloneMode::Checkout, CloneMode::Bare, CloneMode::Mirror] {
+                let path = handler
+                    .tmp_dir
+                    .join(format!("{:?}-{}", backend, clone_mode.as_str()));
+                let mut git_manager = GitManager::init(
+                    path.clone(),
+                    Some(origin.path.to_string_lossy().to_string()),
+                    backend,
+                    None,
+                    RemoteConfig {
+                        name: "upstream".to_string(),
+                        clone_mode,
+                        remotes: vec![fork_remote.clone()],
+                    },
+                    DEFAULT_GIT_TIMEOUT,
+                )
+                .unwrap();
+                let resolve = |git_manager: &GitManager, name: &str| {
+                    git_manager
+                        .rev_parse(&git_manager.resolve_ref(name).unwrap())
+                        .unwrap()
+                };
+                assert_eq!(git_manager.head_sha().unwrap(), first);
+                assert_eq!(resolve(&git_manager, "main"), first);
+                assert_eq!(resolve(&git_manager, "fork/main"), fork_sha);
+                assert_eq!(
+                    path.join("src/main.rs").exists(),
+                    clone_mode == CloneMode::Checkout
+                );
+
+                origin.write("src/main.rs", "fn main() { run() }\n");
+                let second = origin.commit("Second");
+                git_manager.fetch().unwrap();
+                assert_eq!(git_manager.head_sha().unwrap(), second);
+                assert_eq!(resolve(&git_manager, "main"), second);
+                assert!(git_manager.cleanup().is_ok());
+
+                // Dropping a remote removes it from the clone
+                git_manager.remotes.remotes.clear();
+                git_manager.fetch().unwrap();
+                assert!(git_manager.resolve_ref("fork/main").is_err());
+                origin.git(&["reset", "-q", "--hard", &first]);
+            }
+        }
+    }
+
+    #[test]
+    fn fetch_timeout() {
+        let handler = TestHandler::init();
+        let origin = RepoBuilder::init(&handler.tmp_dir.join("origin"));
+        origin.write("src/main.rs", "fn main() {}\n");
+        origin.commit("First");
+        let mut git_manager = GitManager::init(
+            handler.tmp_dir.join("clone"),
+            Some(origin.path.to_string_lossy().to_string()),
+            GitBackendKind::Cli,
+            None,
+            RemoteConfig::default(),
+            DEFAULT_GIT_TIMEOUT,
+        )
+        .unwrap();
+        // A remote that stalls before answering
+        let clone = RepoBuilder {
+            path: git_manager.path.clone(),
+        };
+        clone.git(&[
+            "config",
+            "remote.origin.uploadpack",
+            "sleep 5; git-upload-pack",
+        ]);
+        git_manager.timeout = Duration::from_millis(200);
+        let started = Instant::now();
+        let result = git_manager.fetch();
+        assert!(started.elapsed() < Duration::from_secs(4));
+        match result {
+            Err(FownerError::GitTimeout(command, timeout)) => {
+                assert!(command.contains("fetch"));
+                assert_eq!(timeout, Duration::from_millis(200));
+            }
+            other => panic!("Expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn library_timeout() {
+        let handler = TestHandler::init();
+        // A server that accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/repo.git", listener.local_addr().unwrap());
+        let started = Instant::now();
+        let result = GitManager::init(
+            handler.tmp_dir.join("clone"),
+            Some(url),
+            GitBackendKind::Library,
+            None,
+            RemoteConfig::default(),
+            Duration::from_millis(300),
+        );
+        assert!(started.elapsed() < Duration::from_secs(3));
+        match result {
+            Err(FownerError::GitTimeout(operation, timeout)) => {
+                assert!(operation.starts_with("clone"));
+                assert_eq!(timeout, Duration::from_millis(300));
+            }
+            Err(e) => panic!("Expected a timeout, got {:?}", e),
+            Ok(_) => panic!("Expected a timeout"),
+        }
+        drop(listener);
+    }
+
+    #[test]
+    fn history_timeout() {
+        let handler = TestHandler::init();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.commit("First");
+        // A signed commit whose signature check stalls the log
+        let commit = format!(
+            "tree {}\nauthor Krakaw <krakaw@example.com> 1655391971 +0000\ncommitter Krakaw <krakaw@example.com> 1655391971 +0000\ngpgsig -----BEGIN PGP SIGNATURE-----\n \n -----END PGP SIGNATURE-----\n\nSigned\n",
+            repo.git(&["rev-parse", "HEAD^{tree}"])
+        );
+        std::fs::write(handler.tmp_dir.join("commit"), commit).unwrap();
+        let commit_path = handler.tmp_dir.join("commit");
+        let sha = repo.git(&[
+            "hash-object",
+            "-t",
+            "commit",
+            "-w",
+            &commit_path.to_string_lossy(),
+        ]);
+        repo.git(&["update-ref", "HEAD", &sha]);
+        let gpg = handler.tmp_dir.join("slow-gpg");
+        std::fs::write(&gpg, "#!/bin/sh\nsleep 5\n").unwrap();
+        repo.git(&["config", "gpg.program", &gpg.to_string_lossy()]);
+        repo.git(&["config", "log.showSignature", "true"]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&gpg, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let git_manager = GitManager {
+            timeout: Duration::from_millis(200),
+            ..TestHandler::git_manager(&repo.path, GitBackendKind::Cli)
+        };
+        let extractor = FeatureExtractor::for_commits();
+        let started = Instant::now();
+        let mut stream = git_manager
+            .history_stream(&[], &[], &Subpaths::default(), None, &extractor)
+            .unwrap();
+        match stream.next() {
+            Some(Err(FownerError::GitTimeout(command, _))) => assert!(command.contains("log")),
+            other => panic!(
+                "Expected a timeout, got {:?}",
+                other.map(|h| h.map(|h| h.sha))
+            ),
+        }
+        assert!(stream.next().is_none());
+        drop(stream);
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn stream_history() {
+        let handler = TestHandler::init();
+        let repo = RepoBuilder::init(&handler.tmp_dir.join("repo"));
+        repo.write("src/main.rs", "fn main() {}\n");
+        let first = repo.commit("First [Core]");
+        repo.write("src/lib.rs", "fn lib() {}\n");
+        let second = repo.commit("Second");
+        repo.write("src/lib.rs", "fn lib() { main() }\n");
+        let third = repo.commit("Third");
+        let git_manager = TestHandler::git_manager(&repo.path, GitBackendKind::Cli);
+        let extractor = FeatureExtractor::for_commits();
+
+        assert_eq!(
+            git_manager
+                .count_history(&[], &[], &Subpaths::default(), None)
+                .unwrap(),
+            3
+        );
+        let shas: Vec<String> = git_manager
+            .history_stream(&[], &[], &Subpaths::default(), None, &extractor)
+            .unwrap()
+            .map(|h| h.unwrap().sha)
+            .collect();
+        assert_eq!(shas, vec![first.clone(), second, third.clone()]);
+
+        // Abandoning the stream part way stops git
+        let mut stream = git_manager
+            .history_stream(&[], &[], &Subpaths::default(), None, &extractor)
+            .unwrap();
+        let oldest = stream.next().unwrap().unwrap();
+        assert_eq!(oldest.features, vec!["Core"]);
+        drop(stream);
+
+        let exclude = vec![first];
+        assert_eq!(
+            git_manager
+                .count_history(&[], &exclude, &Subpaths::default(), None)
+                .unwrap(),
+            2
+        );
+        let newest = git_manager
+            .history_stream(&[], &exclude, &Subpaths::default(), None, &extractor)
+            .unwrap()
+            .last()
+            .unwrap()
+            .unwrap();
+        assert_eq!(newest.sha, third);
+        assert_eq!(newest.files, vec!["src/lib.rs"]);
+    }
 }
@@ -1,3 +1,13 @@
+pub mod backend;
+pub mod blame;
+pub mod credentials;
+pub mod features;
 pub mod github;
 pub mod history;
+pub mod mailmap;
 pub mod manager;
+pub mod path_filter;
+pub mod remote;
+pub mod secret_key;
+pub mod subpaths;
+pub mod tag;
@@ -0,0 +1,136 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::errors::FownerError;
+use crate::git::history::GitHistory;
+
+/// Decides which paths of a project are ingested from its include and exclude globs.
+///
+/// Globs match the paths stored for the project and `*` also matches `/`, so `*.lock`
+/// excludes lockfiles in every directory.
+#[derive(Clone, Debug)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(
+        include_globs: &Option<Vec<String>>,
+        exclude_globs: &Option<Vec<String>>,
+    ) -> Result<Self, FownerError> {
+        let include = match include_globs {
+            Some(globs) if !globs.is_empty() => Some(Self::glob_set(globs)?),
+            _ => None,
+        };
+        let exclude = Self::glob_set(exclude_globs.as_deref().unwrap_or_default())?;
+        Ok(Self { include, exclude })
+    }
+
+    fn glob_set(globs: &[String]) -> Result<GlobSet, FownerError> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            if glob.trim().is_empty() {
+                return Err(FownerError::InvalidPathGlob(
+                    "Globs cannot be empty".to_string(),
+                ));
+            }
+            builder.add(
+                Glob::new(glob.trim())
+                    .map_err(|e| FownerError::InvalidPathGlob(format!("{}: {}", glob, e)))?,
+            );
+        }
+        builder
+            .build()
+            .map_err(|e| FownerError::InvalidPathGlob(e.to_string()))
+    }
+
+    /// Paths must match an include glob, when there are any, and no exclude glob
+    pub fn is_included(&self, path: &str) -> bool {
+        self.include
+            .as_ref()
+            .map(|include| include.is_match(path))
+            .unwrap_or(true)
+            && !self.exclude.is_match(path)
+    }
+
+    /// Drops the changes to paths that are not included, a file renamed to an excluded path
+    /// is deleted instead of keeping its old path
+    pub fn apply(&self, git_history: &mut GitHistory) {
+        git_history.files.retain(|f| self.is_included(f));
+        for rename in &git_history.renames {
+            if !self.is_included(&rename.to)
+                && self.is_included(&rename.from)
+                && !git_history.files.contains(&rename.from)
+            {
+                git_history.files.push(rename.from.clone());
+            }
+        }
+        git_history.renames.retain(|r| self.is_included(&r.to));
+        git_history
+            .line_changes
+            .retain(|l| self.is_included(&l.path));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::history::{GitHistory, GitRename};
+    use crate::git::path_filter::PathFilter;
+
+    #[test]
+    fn include_and_exclude() {
+        let filter = PathFilter::new(
+            &Some(vec!["src/**".to_string(), "Cargo.*".to_string()]),
+            &Some(vec!["*.lock".to_string(), "**/snapshots/**".to_string()]),
+        )
+        .unwrap();
+        assert!(filter.is_included("src/main.rs"));
+        assert!(filter.is_included("Cargo.toml"));
+        assert!(!filter.is_included("Cargo.lock"));
+        assert!(!filter.is_included("src/snapshots/view.snap"));
+        assert!(!filter.is_included("README.md"));
+
+        let everything = PathFilter::new(&None, &Some(vec![])).unwrap();
+        assert!(everything.is_included("README.md"));
+        let excluded = PathFilter::new(&None, &Some(vec!["vendor/**".to_string()])).unwrap();
+        assert!(excluded.is_included("src/vendor.rs"));
+        assert!(!excluded.is_included("vendor/lib/mod.rs"));
+    }
+
+    #[test]
+    fn renames_to_excluded_paths() {
+        let filter = PathFilter::new(&None, &Some(vec!["vendor/**".to_string()])).unwrap();
+        let mut git_history = GitHistory {
+            files: vec!["src/api.rs".to_string(), "vendor/lib.rs".to_string()],
+            renames: vec![
+                GitRename {
+                    from: "src/client.rs".to_string(),
+                    to: "src/api.rs".to_string(),
+                },
+                GitRename {
+                    from: "src/lib.rs".to_string(),
+                    to: "vendor/lib.rs".to_string(),
+                },
+            ],
+            ..GitHistory::default()
+        };
+        filter.apply(&mut git_history);
+        assert_eq!(
+            git_history.files,
+            vec!["src/api.rs".to_string(), "src/lib.rs".to_string()]
+        );
+        assert_eq!(
+            git_history.renames,
+            vec![GitRename {
+                from: "src/client.rs".to_string(),
+                to: "src/api.rs".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn invalid_globs() {
+        assert!(PathFilter::new(&Some(vec!["src/[".to_string()]), &None).is_err());
+        assert!(PathFilter::new(&None, &Some(vec![" ".to_string()])).is_err());
+    }
+}
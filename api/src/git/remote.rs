@@ -0,0 +1,173 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::FownerError;
+
+/// The remote a repository is cloned from when the project does not name one
+pub const DEFAULT_REMOTE: &str = "origin";
+
+/// Set in the config of the extra remotes fowner adds, so only those are ever removed
+pub const MANAGED_REMOTE_KEY: &str = "fownermanaged";
+
+/// How a project's repository is cloned and kept up to date
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CloneMode {
+    /// A working tree that is reset to the upstream of the checked out branch on each fetch
+    #[default]
+    Checkout,
+    /// No working tree, fetches update the local branches directly
+    Bare,
+    /// No working tree, fetches update every remote ref including pull request refs
+    Mirror,
+}
+
+impl CloneMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloneMode::Checkout => "checkout",
+            CloneMode::Bare => "bare",
+            CloneMode::Mirror => "mirror",
+        }
+    }
+
+    /// The refspec the project's remote fetches with, `None` keeps git's default
+    pub fn fetch_refspec(&self) -> Option<&'static str> {
+        match self {
+            CloneMode::Checkout => None,
+            CloneMode::Bare => Some("+refs/heads/*:refs/heads/*"),
+            CloneMode::Mirror => Some("+refs/*:refs/*"),
+        }
+    }
+}
+
+impl FromStr for CloneMode {
+    type Err = FownerError;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "checkout" => Ok(CloneMode::Checkout),
+            "bare" => Ok(CloneMode::Bare),
+            "mirror" => Ok(CloneMode::Mirror),
+            _ => Err(FownerError::GitError(format!(
+                "Unknown clone mode {}",
+                mode
+            ))),
+        }
+    }
+}
+
+/// An extra remote fetched alongside the project's own, such as a fork
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GitRemote {
+    pub name: String,
+    pub url: String,
+}
+
+impl GitRemote {
+    /// The refspec extra remotes fetch with, their branches are kept apart from the project's
+    pub fn fetch_refspec(&self) -> String {
+        format!("+refs/heads/*:refs/remotes/{}/*", self.name)
+    }
+}
+
+/// The remotes a repository is cloned from and fetches
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteConfig {
+    /// The name of the remote the repository is cloned from
+    pub name: String,
+    pub clone_mode: CloneMode,
+    /// Fetched after the project's remote, their branches are `<name>/<branch>`
+    pub remotes: Vec<GitRemote>,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_REMOTE.to_string(),
+            clone_mode: CloneMode::default(),
+            remotes: vec![],
+        }
+    }
+}
+
+impl RemoteConfig {
+    /// Every remote name fetched, the project's remote first
+    pub fn names(&self) -> Vec<String> {
+        std::iter::once(self.name.clone())
+            .chain(self.remotes.iter().map(|r| r.name.clone()))
+            .collect()
+    }
+}
+
+/// Checks a remote name can be used as a git config section and ref prefix
+pub fn validate_remote_name(name: &str) -> Result<(), FownerError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && !name.starts_with('.')
+        && !name.ends_with(".lock")
+        && !name.contains("..")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(FownerError::InvalidRemote(format!(
+            "Invalid remote name '{}'",
+            name
+        )))
+    }
+}
+
+/// Checks every extra remote has a valid, unique name that differs from the project's remote
+pub fn validate_remotes(name: &str, remotes: &Option<Vec<GitRemote>>) -> Result<(), FownerError> {
+    validate_remote_name(name)?;
+    let mut names = vec![name];
+    for remote in remotes.iter().flatten() {
+        validate_remote_name(&remote.name)?;
+        if remote.url.trim().is_empty() {
+            return Err(FownerError::InvalidRemote(format!(
+                "Remote '{}' is missing a url",
+                remote.name
+            )));
+        }
+        if names.contains(&remote.name.as_str()) {
+            return Err(FownerError::InvalidRemote(format!(
+                "Remote '{}' is configured more than once",
+                remote.name
+            )));
+        }
+        names.push(&remote.name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::remote::{validate_remotes, GitRemote};
+    use crate::FownerError;
+
+    #[test]
+    fn remote_names() {
+        let fork = |name: &str| GitRemote {
+            name: name.to_string(),
+            url: "https://example.com/fork.git".to_string(),
+        };
+        assert!(validate_remotes("origin", &None).is_ok());
+        assert!(validate_remotes("upstream", &Some(vec![fork("fork-1")])).is_ok());
+        assert!(validate_remotes("origin", &Some(vec![fork("origin")])).is_err());
+        assert!(validate_remotes("origin", &Some(vec![fork("a"), fork("a")])).is_err());
+        for invalid in ["", "-x", "a/b", "a b", "a..b", "x.lock"] {
+            assert!(
+                matches!(
+                    validate_remotes(invalid, &None),
+                    Err(FownerError::InvalidRemote(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::FownerError;
+
+/// Holds a base64 encoded 32 byte key, takes precedence over the key file
+pub const SECRET_KEY_ENV: &str = "FOWNER_SECRET_KEY";
+
+/// Prefixes sealed values so the format can change later
+const SEALED_PREFIX: &str = "v1:";
+
+/// The key stored credentials are sealed with, installed once at startup
+static SECRET_KEY: OnceLock<SecretKey> = OnceLock::new();
+
+/// Encrypts the secrets fowner stores with a key that is kept out of the database
+pub struct SecretKey {
+    key: LessSafeKey,
+}
+
+impl SecretKey {
+    /// Reads a base64 encoded 32 byte key, as `openssl rand -base64 32` prints
+    pub fn from_base64(encoded: &str) -> Result<Self, FownerError> {
+        let bytes = base64::decode(encoded.trim())
+            .map_err(|e| FownerError::SecretKey(format!("The key is not base64: {}", e)))?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| FownerError::SecretKey("The key must be 32 bytes".to_string()))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    /// Loads the key from `FOWNER_SECRET_KEY`, or else the file at `path`
+    pub fn load(path: Option<&Path>) -> Result<Option<Self>, FownerError> {
+        if let Ok(encoded) = std::env::var(SECRET_KEY_ENV) {
+            return Self::from_base64(&encoded).map(Some);
+        }
+        match path {
+            Some(path) => Self::from_base64(&fs::read_to_string(path)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Makes this the key credentials are sealed with for the rest of the process,
+    /// a key that is already installed is kept
+    pub fn install(self) {
+        let _ = SECRET_KEY.set(self);
+    }
+
+    pub fn installed() -> Result<&'static Self, FownerError> {
+        SECRET_KEY.get().ok_or_else(|| {
+            FownerError::SecretKey(format!(
+                "Credentials need a secret key, set {} or pass --secret-key-file",
+                SECRET_KEY_ENV
+            ))
+        })
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<String, FownerError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| FownerError::SecretKey("Could not generate a nonce".to_string()))?;
+        let mut sealed = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .map_err(|_| FownerError::SecretKey("Could not encrypt".to_string()))?;
+        let mut encoded = nonce.to_vec();
+        encoded.extend(sealed);
+        Ok(format!("{}{}", SEALED_PREFIX, base64::encode(encoded)))
+    }
+
+    pub fn open(&self, sealed: &str) -> Result<Vec<u8>, FownerError> {
+        let invalid = || FownerError::SecretKey("Sealed value is invalid".to_string());
+        let encoded = sealed.strip_prefix(SEALED_PREFIX).ok_or_else(invalid)?;
+        let bytes = base64::decode(encoded).map_err(|_| invalid())?;
+        if bytes.len() < NONCE_LEN {
+            return Err(invalid());
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| invalid())?;
+        let mut sealed = sealed.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| {
+                FownerError::SecretKey(
+                    "Could not decrypt, the value was sealed with another key".to_string(),
+                )
+            })?;
+        Ok(plaintext.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::secret_key::SecretKey;
+
+    #[test]
+    fn seal_and_open() {
+        let key = SecretKey::from_base64("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=").unwrap();
+        let sealed = key.seal(b"token").unwrap();
+        assert!(!sealed.contains("token"));
+        assert_ne!(sealed, key.seal(b"token").unwrap());
+        assert_eq!(key.open(&sealed).unwrap(), b"token");
+
+        let other = SecretKey::from_base64("ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=").unwrap();
+        assert!(other.open(&sealed).is_err());
+        assert!(key.open("v1:bm90IHNlYWxlZA==").is_err());
+        assert!(SecretKey::from_base64("c2hvcnQ=").is_err());
+    }
+}
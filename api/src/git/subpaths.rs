@@ -0,0 +1,236 @@
+use crate::errors::FownerError;
+use crate::git::history::{GitHistory, GitLineChange, GitRename};
+
+/// The directories of a repository a project covers.
+///
+/// Paths are stored relative to the deepest directory containing every subpath, so a
+/// project with a single subpath stores its files relative to that subpath.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Subpaths {
+    subpaths: Vec<String>,
+    root: String,
+}
+
+impl Subpaths {
+    pub fn new(subpaths: &Option<Vec<String>>) -> Self {
+        let subpaths = subpaths.clone().unwrap_or_default();
+        let mut root: Vec<&str> = subpaths
+            .first()
+            .map(|s| s.split('/').collect())
+            .unwrap_or_default();
+        for subpath in subpaths.iter().skip(1) {
+            let shared = root
+                .iter()
+                .zip(subpath.split('/'))
+                .take_while(|(a, b)| *a == b)
+                .count();
+            root.truncate(shared);
+        }
+        let root = root.join("/");
+        Self { subpaths, root }
+    }
+
+    /// Trims, sorts and removes duplicate subpaths, an empty list covers the whole repository
+    pub fn normalize(subpaths: Option<Vec<String>>) -> Result<Option<Vec<String>>, FownerError> {
+        let mut normalized = vec![];
+        for subpath in subpaths.unwrap_or_default() {
+            let trimmed = subpath.trim().trim_start_matches("./").trim_matches('/');
+            if trimmed.is_empty()
+                || subpath.trim().starts_with('/')
+                || trimmed
+                    .split('/')
+                    .any(|p| p.is_empty() || p == "." || p == "..")
+            {
+                return Err(FownerError::InvalidSubpath(subpath));
+            }
+            normalized.push(trimmed.to_string());
+        }
+        normalized.sort();
+        normalized.dedup();
+        Ok(Some(normalized).filter(|n| !n.is_empty()))
+    }
+
+    /// The pathspecs that limit the history to the subpaths
+    pub fn pathspecs(&self) -> Vec<String> {
+        self.subpaths.clone()
+    }
+
+    fn contains(&self, repo_path: &str) -> bool {
+        self.subpaths.is_empty()
+            || self.subpaths.iter().any(|s| {
+                repo_path
+                    .strip_prefix(s.as_str())
+                    .map(|rest| rest.starts_with('/'))
+                    .unwrap_or_default()
+            })
+    }
+
+    /// Converts a path from the repository root to the path stored for the project,
+    /// `None` when it is outside every subpath
+    pub fn to_project(&self, repo_path: &str) -> Option<String> {
+        if !self.contains(repo_path) {
+            return None;
+        }
+        if self.root.is_empty() {
+            return Some(repo_path.to_string());
+        }
+        repo_path
+            .strip_prefix(&format!("{}/", self.root))
+            .map(String::from)
+    }
+
+    /// Converts a stored project path back to a path from the repository root
+    pub fn to_repo(&self, project_path: &str) -> String {
+        if self.root.is_empty() {
+            project_path.to_string()
+        } else {
+            format!("{}/{}", self.root, project_path)
+        }
+    }
+
+    /// Drops the changes outside the subpaths and makes the rest relative to the project,
+    /// a file renamed out of the subpaths is deleted from the project
+    pub fn apply(&self, git_history: &mut GitHistory) {
+        let mut files: Vec<String> = git_history
+            .files
+            .iter()
+            .filter_map(|f| self.to_project(f))
+            .collect();
+        let mut renames = vec![];
+        for rename in &git_history.renames {
+            match (self.to_project(&rename.from), self.to_project(&rename.to)) {
+                (Some(from), Some(to)) => renames.push(GitRename { from, to }),
+                (Some(from), None) if !files.contains(&from) => files.push(from),
+                _ => {}
+            }
+        }
+        git_history.files = files;
+        git_history.renames = renames;
+        git_history.line_changes = git_history
+            .line_changes
+            .iter()
+            .filter_map(|l| {
+                Some(GitLineChange {
+                    path: self.to_project(&l.path)?,
+                    ..l.clone()
+                })
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::errors::FownerError;
+    use crate::git::history::{GitHistory, GitRename};
+    use crate::git::subpaths::Subpaths;
+
+    #[test]
+    fn single_subpath() {
+        let subpaths = Subpaths::new(&Some(vec!["products/billing".to_string()]));
+        assert_eq!(subpaths.pathspecs(), vec!["products/billing"]);
+        assert_eq!(
+            subpaths.to_project("products/billing/src/main.rs"),
+            Some("src/main.rs".to_string())
+        );
+        assert_eq!(subpaths.to_project("products/billing-v2/main.rs"), None);
+        assert_eq!(subpaths.to_project("README.md"), None);
+        assert_eq!(
+            subpaths.to_repo("src/main.rs"),
+            "products/billing/src/main.rs"
+        );
+    }
+
+    #[test]
+    fn several_subpaths() {
+        let subpaths = Subpaths::new(&Some(vec![
+            "libs/billing".to_string(),
+            "products/billing".to_string(),
+        ]));
+        assert_eq!(
+            subpaths.to_project("libs/billing/lib.rs"),
+            Some("libs/billing/lib.rs".to_string())
+        );
+        let nested = Subpaths::new(&Some(vec![
+            "products/billing/api".to_string(),
+            "products/billing/web".to_string(),
+        ]));
+        assert_eq!(
+            nested.to_project("products/billing/web/index.ts"),
+            Some("web/index.ts".to_string())
+        );
+        assert_eq!(nested.to_project("products/billing/README.md"), None);
+        assert_eq!(
+            nested.to_repo("api/main.rs"),
+            "products/billing/api/main.rs"
+        );
+
+        let whole = Subpaths::new(&None);
+        assert!(whole.pathspecs().is_empty());
+        assert_eq!(whole.to_project("README.md"), Some("README.md".to_string()));
+    }
+
+    #[test]
+    fn renames_across_the_subpath() {
+        let subpaths = Subpaths::new(&Some(vec!["products/billing".to_string()]));
+        let mut git_history = GitHistory {
+            files: vec![
+                "products/billing/invoice.rs".to_string(),
+                "archive/totals.rs".to_string(),
+                "products/billing/tax.rs".to_string(),
+            ],
+            renames: vec![
+                GitRename {
+                    from: "products/billing/bill.rs".to_string(),
+                    to: "products/billing/invoice.rs".to_string(),
+                },
+                GitRename {
+                    from: "products/billing/totals.rs".to_string(),
+                    to: "archive/totals.rs".to_string(),
+                },
+                GitRename {
+                    from: "legacy/tax.rs".to_string(),
+                    to: "products/billing/tax.rs".to_string(),
+                },
+            ],
+            ..GitHistory::default()
+        };
+        subpaths.apply(&mut git_history);
+        // Moving out deletes the file, moving in adds it
+        assert_eq!(
+            git_history.files,
+            vec![
+                "invoice.rs".to_string(),
+                "tax.rs".to_string(),
+                "totals.rs".to_string()
+            ]
+        );
+        assert_eq!(
+            git_history.renames,
+            vec![GitRename {
+                from: "bill.rs".to_string(),
+                to: "invoice.rs".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            Subpaths::normalize(Some(vec![
+                " ./products/web/ ".to_string(),
+                "libs".to_string(),
+                "products/web".to_string()
+            ]))
+            .unwrap(),
+            Some(vec!["libs".to_string(), "products/web".to_string()])
+        );
+        assert_eq!(Subpaths::normalize(Some(vec![])).unwrap(), None);
+        assert!(matches!(
+            Subpaths::normalize(Some(vec!["../other".to_string()])),
+            Err(FownerError::InvalidSubpath(_))
+        ));
+        assert!(Subpaths::normalize(Some(vec!["/etc".to_string()])).is_err());
+        assert!(Subpaths::normalize(Some(vec![" ".to_string()])).is_err());
+    }
+}
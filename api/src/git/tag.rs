@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct GitTag {
+    pub name: String,
+    /// The commit the tag points to, annotated tags are peeled to their commit
+    pub sha: String,
+    pub timestamp: i64,
+}
+
+impl GitTag {
+    /// Processes `git for-each-ref refs/tags` output formatted as
+    /// `<name>\t<peeled sha>\t<object sha>\t<unix date>`
+    /// Lightweight tags have no peeled sha and point straight at their commit
+    pub fn parse(tags_string: &str) -> Vec<GitTag> {
+        tags_string
+            .lines()
+            .filter_map(|line| {
+                let parts = line.split('\t').collect::<Vec<&str>>();
+                match parts.as_slice() {
+                    [name, peeled, object, timestamp] if !name.is_empty() => Some(GitTag {
+                        name: name.to_string(),
+                        sha: if peeled.is_empty() { object } else { peeled }.to_string(),
+                        timestamp: timestamp.trim().parse().unwrap_or_default(),
+                    }),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::tag::GitTag;
+
+    #[test]
+    fn parse() {
+        let tags_string = "v1.0\t\t5131ec449c8ac13b2d295c2ff006413c4cb16658\t1655391971\n\
+            v2.0\t77c5d38c13117d1807e3bb75d6970fc97d7d07db\t040b7e0f6761b7e6e5d9eeb228c127c863d17016\t1655513699\n";
+        let tags = GitTag::parse(tags_string);
+        assert_eq!(
+            tags,
+            vec![
+                GitTag {
+                    name: "v1.0".to_string(),
+                    sha: "5131ec449c8ac13b2d295c2ff006413c4cb16658".to_string(),
+                    timestamp: 1655391971
+                },
+                GitTag {
+                    name: "v2.0".to_string(),
+                    sha: "77c5d38c13117d1807e3bb75d6970fc97d7d07db".to_string(),
+                    timestamp: 1655513699
+                }
+            ]
+        );
+        assert!(GitTag::parse("").is_empty());
+    }
+}
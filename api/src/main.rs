@@ -7,16 +7,22 @@ mod test;
 extern crate core;
 extern crate log;
 
+use crate::db::hooks::{HookCommand, Hooks};
 use crate::db::models::file::File;
 use crate::db::models::project::Project;
 use crate::db::processor::Processor;
+use crate::db::watcher::Watcher;
 use crate::db::{Connection, Db};
 use crate::errors::FownerError;
+use crate::git::backend::{CommandTimeout, GitBackendKind};
 use crate::git::manager::GitManager;
+use crate::git::secret_key::SecretKey;
 use clap::{Parser, Subcommand};
 use env_logger::Env;
+use log::info;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -25,6 +31,19 @@ struct Cli {
     #[clap(short, long, default_value = "./.data.sqlite3")]
     database_path: PathBuf,
 
+    /// How to read repositories, `library` does not need git installed
+    #[clap(long, value_enum, default_value = "cli")]
+    git_backend: GitBackendKind,
+
+    /// Seconds a git command may run before it is killed
+    #[clap(long, default_value = "300")]
+    git_timeout: u64,
+
+    /// File holding the base64 key stored credentials are encrypted with,
+    /// `FOWNER_SECRET_KEY` takes precedence
+    #[clap(long)]
+    secret_key_file: Option<PathBuf>,
+
     /// Sub-Commands
     #[clap(subcommand)]
     command: Commands,
@@ -60,6 +79,38 @@ enum Commands {
         /// Fetch github labels
         #[clap(short, long)]
         fetch_github_labels: bool,
+        /// Delete commits that are no longer reachable instead of flagging them
+        #[clap(long)]
+        prune_unreachable: bool,
+        /// Only ingest this directory of the repository, can be repeated
+        #[clap(long)]
+        subpath: Vec<String>,
+    },
+    /// Keep the history of a local repository up to date as new commits land
+    Watch {
+        /// Path of repository to watch
+        #[clap(short, long)]
+        repo_path: PathBuf,
+        /// Git repo url
+        #[clap(short = 'u', long)]
+        repo_url: Option<String>,
+        /// Seconds between checks of the tracked branches
+        #[clap(short, long, default_value = "2")]
+        interval: u64,
+        /// Fetch github labels
+        #[clap(short, long)]
+        fetch_github_labels: bool,
+        /// Delete commits that are no longer reachable instead of flagging them
+        #[clap(long)]
+        prune_unreachable: bool,
+        /// Only ingest this directory of the repository, can be repeated
+        #[clap(long)]
+        subpath: Vec<String>,
+    },
+    /// Install and run the git hooks that report and ingest the features of new commits
+    Hooks {
+        #[clap(subcommand)]
+        command: HookCommands,
     },
     /// Generate a dotfile in the target repo containing all files and their features
     Dotfile {
@@ -72,6 +123,36 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum HookCommands {
+    /// Write the pre-commit, commit-msg and post-commit hooks into a repository
+    Install {
+        /// Path of the repository, it must have been processed with `history`
+        #[clap(short, long)]
+        repo_path: PathBuf,
+        /// Replace hooks that were not written by fowner
+        #[clap(long)]
+        force: bool,
+    },
+    /// Print the features and owners of the staged files
+    PreCommit {
+        #[clap(short, long)]
+        repo_path: PathBuf,
+    },
+    /// Warn about features tagged in the commit message that the project does not have
+    CommitMsg {
+        #[clap(short, long)]
+        repo_path: PathBuf,
+        /// File holding the commit message
+        message_file: PathBuf,
+    },
+    /// Ingest the new commit
+    PostCommit {
+        #[clap(short, long)]
+        repo_path: PathBuf,
+    },
+}
+
 #[actix_web::main]
 async fn main() -> Result<(), FownerError> {
     env_logger::Builder::from_env(Env::default().default_filter_or("fowner=info")).init();
@@ -80,9 +161,17 @@ async fn main() -> Result<(), FownerError> {
     let db = Db::new(&cli.database_path)?;
     // Init runs the migrations on every run
     db.init()?;
+    if let Some(secret_key) = SecretKey::load(cli.secret_key_file.as_deref())? {
+        secret_key.install();
+        let sealed = Project::seal_plaintext_credentials(&Connection::try_from(&db)?)?;
+        if sealed > 0 {
+            info!("Encrypted the stored credentials of {} projects", sealed);
+        }
+    }
     let mut conn = db.pool.get()?;
     let tx = conn.transaction()?;
     let conn = Connection::from(tx);
+    let git_timeout = Duration::from_secs(cli.git_timeout);
     match &cli.command {
         Commands::History {
             repo_path,
@@ -90,25 +179,101 @@ async fn main() -> Result<(), FownerError> {
             bypass_save,
             stop_at_sha,
             fetch_github_labels,
+            prune_unreachable,
+            subpath,
         } => {
             let git_manager = GitManager {
                 path: repo_path.clone(),
                 url: repo_url.clone(),
+                backend: cli.git_backend,
+                credentials: None,
+                remotes: Default::default(),
+                timeout: git_timeout,
             };
-            let processor = Processor::new(git_manager, &conn)?;
+            let subpaths = Some(subpath.clone()).filter(|s| !s.is_empty());
+            let processor = Processor::new(git_manager, subpaths, &conn)?;
             // Fetch the commits from the local repository and insert the required records
             // Projects, Owners, Files, Commits, File Owners
             if *bypass_save {
                 eprintln!(
                     "{}",
-                    serde_json::to_string(&processor.git_manager.parse_history(None)?)?
+                    serde_json::to_string(
+                        &processor.git_manager.parse_history(
+                            &processor
+                                .tracked_refs()?
+                                .into_iter()
+                                .map(|(_, git_ref)| git_ref)
+                                .collect::<Vec<String>>(),
+                            &[],
+                            &processor.project.subpaths(),
+                            processor.project.merge_policy,
+                            &processor.project.commit_feature_extractor()?
+                        )?
+                    )?
                 );
             } else {
+                let reconciled = processor.reconcile_unreachable(*prune_unreachable)?;
+                if !reconciled.commits.is_empty() {
+                    info!(
+                        "{} commits are no longer reachable",
+                        reconciled.commits.len()
+                    );
+                }
                 let _ = processor
                     .fetch_commits_and_update_db(stop_at_sha.clone(), *fetch_github_labels)
                     .await?;
             }
         }
+        Commands::Watch {
+            repo_path,
+            repo_url,
+            interval,
+            fetch_github_labels,
+            prune_unreachable,
+            subpath,
+        } => {
+            let git_manager = GitManager {
+                path: repo_path.clone(),
+                url: repo_url.clone(),
+                backend: cli.git_backend,
+                credentials: None,
+                remotes: Default::default(),
+                timeout: git_timeout,
+            };
+            let subpaths = Some(subpath.clone()).filter(|s| !s.is_empty());
+            // Each ingest runs in its own transaction so progress survives stopping the watch
+            Watcher::new(
+                db.clone(),
+                git_manager,
+                subpaths,
+                Duration::from_secs(*interval),
+            )
+            .with_github_labels(*fetch_github_labels)
+            .with_prune_unreachable(*prune_unreachable)
+            .run()
+            .await?
+        }
+        Commands::Hooks {
+            command: HookCommands::Install { repo_path, force },
+        } => {
+            let hooks = Hooks::load(repo_path, cli.git_backend, git_timeout, &conn)?;
+            let command = HookCommand {
+                executable: std::env::current_exe()?,
+                database_path: cli.database_path.canonicalize()?,
+                git_backend: cli.git_backend,
+                working_dir: std::env::current_dir()?,
+            };
+            for path in hooks.install(&command, *force)? {
+                eprintln!("installed {}", path.display());
+            }
+        }
+        Commands::Hooks { command } => {
+            // A hook failing would block the commit, so failures are only reported
+            if let Err(e) = run_hook(command, cli.git_backend, git_timeout, &conn).await {
+                eprintln!("fowner: {}", e);
+                return Ok(());
+            }
+        }
         Commands::Dotfile { repo_path, dotfile } => {
             let project = Project::load_by_path(repo_path, &conn)?;
             let dotfile_path = repo_path.join(dotfile);
@@ -121,11 +286,60 @@ async fn main() -> Result<(), FownerError> {
             storage_path,
             public_asset_path,
         } => {
-            server::api::Api::start(db, listen, public_asset_path.clone(), storage_path.clone())
-                .await?
+            server::api::Api::start(
+                db,
+                listen,
+                public_asset_path.clone(),
+                storage_path.clone(),
+                cli.git_backend,
+                CommandTimeout(git_timeout),
+            )
+            .await?
         }
     }
     conn.transaction()?.commit()?;
 
     Ok(())
 }
+
+async fn run_hook(
+    command: &HookCommands,
+    git_backend: GitBackendKind,
+    git_timeout: Duration,
+    conn: &Connection<'_>,
+) -> Result<(), FownerError> {
+    match command {
+        HookCommands::Install { .. } => {}
+        HookCommands::PreCommit { repo_path } => {
+            let hooks = Hooks::load(repo_path, git_backend, git_timeout, conn)?;
+            for file in hooks.staged_files()? {
+                if file.is_new {
+                    eprintln!("fowner: {} is new", file.path);
+                } else {
+                    eprintln!(
+                        "fowner: {} features: [{}] owners: [{}]",
+                        file.path,
+                        file.features.join(", "),
+                        file.owners.join(", ")
+                    );
+                }
+            }
+        }
+        HookCommands::CommitMsg {
+            repo_path,
+            message_file,
+        } => {
+            let hooks = Hooks::load(repo_path, git_backend, git_timeout, conn)?;
+            let message = std::fs::read_to_string(message_file)?;
+            for feature in hooks.unknown_features(&message)? {
+                eprintln!("fowner: warning: unknown feature [{}]", feature);
+            }
+        }
+        HookCommands::PostCommit { repo_path } => {
+            let hooks = Hooks::load(repo_path, git_backend, git_timeout, conn)?;
+            let commits = hooks.ingest().await?;
+            info!("Ingested {} new commits", commits);
+        }
+    }
+    Ok(())
+}
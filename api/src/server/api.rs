@@ -9,7 +9,8 @@ use actix_web::{web, App, HttpResponse, HttpServer};
 use log::{info, warn};
 use serde_json::json;
 
-use crate::server::controllers::{commits, features, files, owners, projects, stats};
+use crate::git::backend::{CommandTimeout, GitBackendKind};
+use crate::server::controllers::{commits, features, files, owners, projects, stats, tags};
 use crate::{Db, FownerError};
 
 pub struct Api;
@@ -26,6 +27,8 @@ impl Api {
         listen: &SocketAddr,
         public_asset_path: PathBuf,
         storage_path: PathBuf,
+        git_backend: GitBackendKind,
+        git_timeout: CommandTimeout,
     ) -> Result<(), FownerError> {
         info!("Starting server on {:?}", listen);
         if !public_asset_path.exists() {
@@ -37,6 +40,8 @@ impl Api {
                 .wrap(Logger::default())
                 .app_data(web::Data::new(db.clone()))
                 .app_data(web::Data::new(storage_path.clone()))
+                .app_data(web::Data::new(git_backend))
+                .app_data(web::Data::new(git_timeout))
                 .app_data(web::Data::new(AppState {
                     public_asset_path: public_asset_path.clone(),
                 }))
@@ -55,10 +60,22 @@ impl Api {
                             web::scope("/search/{owner_handle}")
                                 .route("", web::get().to(owners::get_owners_by_handle)),
                         )
+                        .service(
+                            web::scope("/suggestions")
+                                .route("", web::get().to(owners::suggestions))
+                                .route("/accept", web::post().to(owners::accept_suggestion)),
+                        )
+                        .service(
+                            web::scope("/bot_patterns")
+                                .route("", web::get().to(owners::bot_patterns))
+                                .route("", web::post().to(owners::create_bot_pattern))
+                                .route("/{id}", web::delete().to(owners::destroy_bot_pattern)),
+                        )
                         .service(
                             web::scope("/{id}")
                                 .route("", web::put().to(owners::update_owner))
-                                .route("", web::get().to(owners::load)),
+                                .route("", web::get().to(owners::load))
+                                .route("/merges", web::get().to(owners::merges)),
                         ),
                 )
                 .service(
@@ -68,6 +85,7 @@ impl Api {
                         .service(
                             web::scope("/{project_id}")
                                 .route("", web::get().to(projects::load))
+                                .route("", web::put().to(projects::update))
                                 .route("", web::delete().to(projects::destroy))
                                 .service(
                                     web::scope("/fetch")
@@ -76,17 +94,34 @@ impl Api {
                                 .service(
                                     web::scope("/clean").route("", web::get().to(projects::clean)),
                                 )
+                                .route("/mailmap", web::put().to(projects::upload_mailmap))
+                                .route(
+                                    "/credentials",
+                                    web::delete().to(projects::clear_credentials),
+                                )
                                 .service(
                                     web::scope("/files")
+                                        .route("/purge", web::post().to(files::purge_excluded))
                                         .route(
                                             "/{file_id}/features",
                                             web::delete().to(files::remove_features),
                                         )
+                                        .route("/{file_id}/renames", web::get().to(files::renames))
+                                        .route("/{file_id}/commits", web::get().to(files::commits))
+                                        .route(
+                                            "/{file_id}/current_owners",
+                                            web::get().to(files::current_owners),
+                                        )
                                         .route("", web::get().to(files::search)),
                                 )
                                 .service(
                                     web::scope("/commits")
                                         .route("", web::get().to(commits::search)),
+                                )
+                                .service(
+                                    web::scope("/tags")
+                                        .route("", web::get().to(tags::all))
+                                        .route("/changes", web::get().to(tags::changes)),
                                 ),
                         ),
                 )
@@ -1,13 +1,29 @@
 use actix_web::{web, Responder, Result};
+use serde::{Deserialize, Serialize};
 
 use crate::db::models::commit::Commit;
-use crate::server::controllers::{PagingResponse, SearchRequest};
+use crate::server::controllers::PagingResponse;
+use crate::server::paging::Paging;
 use crate::{Connection, Db};
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitSearchRequest {
+    #[serde(default)]
+    q: Option<String>,
+    /// Conventional Commits type such as feat or fix
+    #[serde(default)]
+    commit_type: Option<String>,
+    /// Only commits contained in this tracked branch
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(flatten)]
+    paging: Paging,
+}
+
 pub async fn search(
     db: web::Data<Db>,
     project_id: web::Path<u32>,
-    query: web::Query<SearchRequest>,
+    query: web::Query<CommitSearchRequest>,
 ) -> Result<impl Responder> {
     let query = query.into_inner();
     let project_id = project_id.into_inner();
@@ -17,6 +33,8 @@ pub async fn search(
     let (total_count, commits) = Commit::search(
         project_id,
         query.q,
+        query.commit_type,
+        query.branch,
         query.paging.limit,
         query.paging.offset,
         query.paging.sort,
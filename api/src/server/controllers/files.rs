@@ -1,9 +1,13 @@
 use actix_web::{web, Responder, Result};
+use log::debug;
+use serde_json::json;
 
 use crate::db::models::commit::Commit;
 use crate::db::models::file_commit::FileCommit;
-use crate::server::controllers::SearchRequest;
-use crate::{Connection, Db, File};
+use crate::db::models::file_current_owner::FileCurrentOwner;
+use crate::db::models::file_rename::FileRename;
+use crate::server::controllers::{BranchQuery, OwnersQuery, SearchRequest};
+use crate::{Connection, Db, File, Project};
 
 pub async fn search(
     db: web::Data<Db>,
@@ -17,6 +21,8 @@ pub async fn search(
     let files = File::search(
         project_id,
         query.q.unwrap_or_default(),
+        query.branch,
+        query.include_bots.unwrap_or_default(),
         query.paging.limit,
         query.paging.offset,
         &conn,
@@ -25,6 +31,20 @@ pub async fn search(
     Ok(web::Json(files))
 }
 
+/// Deletes the files that match the project's exclude globs, or miss its include globs
+pub async fn purge_excluded(
+    db: web::Data<Db>,
+    project_id: web::Path<u32>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let project = Project::load(project_id.into_inner(), &conn)?;
+    let excluded = File::flag_excluded(project.id, &project.path_filter()?, &conn)?;
+    let purged = File::purge_excluded(project.id, &conn)?;
+    debug!("Purged {} of {} excluded files", purged, excluded);
+    Ok(web::Json(json!({ "purged": purged })))
+}
+
 pub async fn remove_features(
     db: web::Data<Db>,
     project_file_id: web::Path<(u32, u32)>,
@@ -37,6 +57,48 @@ pub async fn remove_features(
     Ok(web::Json(result))
 }
 
+pub async fn renames(
+    db: web::Data<Db>,
+    project_file_id: web::Path<(u32, u32)>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let (project_id, file_id) = project_file_id.into_inner();
+    let file = File::load(project_id, file_id, &conn)?;
+    let renames = FileRename::load_by_file(file.id, &conn)?;
+    Ok(web::Json(renames))
+}
+
+pub async fn commits(
+    db: web::Data<Db>,
+    project_file_id: web::Path<(u32, u32)>,
+    query: web::Query<BranchQuery>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let (project_id, file_id) = project_file_id.into_inner();
+    let file = File::load(project_id, file_id, &conn)?;
+    let file_commits = FileCommit::load_by_file(file.id, query.into_inner().branch, &conn)?;
+    Ok(web::Json(file_commits))
+}
+
+pub async fn current_owners(
+    db: web::Data<Db>,
+    project_file_id: web::Path<(u32, u32)>,
+    query: web::Query<OwnersQuery>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let (project_id, file_id) = project_file_id.into_inner();
+    let file = File::load(project_id, file_id, &conn)?;
+    let current_owners = FileCurrentOwner::load_by_file(
+        file.id,
+        query.into_inner().include_bots.unwrap_or_default(),
+        &conn,
+    )?;
+    Ok(web::Json(current_owners))
+}
+
 pub async fn get_files_between_commits(
     db: web::Data<Db>,
     path: web::Path<(String, String)>,
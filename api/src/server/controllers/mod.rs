@@ -8,15 +8,34 @@ pub mod files;
 pub mod owners;
 pub mod projects;
 pub mod stats;
+pub mod tags;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchRequest {
     #[serde(default)]
     q: Option<String>,
+    #[serde(default)]
+    branch: Option<String>,
+    /// Bots are left out of the owners unless this is `true`
+    #[serde(default)]
+    include_bots: Option<bool>,
     #[serde(flatten)]
     paging: Paging,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BranchQuery {
+    #[serde(default)]
+    branch: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OwnersQuery {
+    /// Bots are left out of the owners unless this is `true`
+    #[serde(default)]
+    include_bots: Option<bool>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PagingResponse<T> {
     paging: Paging,
@@ -1,8 +1,25 @@
 use actix_web::{web, Responder, Result};
+use serde::{Deserialize, Serialize};
 
+use crate::db::models::bot_pattern::{BotPattern, NewBotPattern};
 use crate::db::models::owner::{Owner, UpdateOwner};
+use crate::db::models::owner_merge::{OwnerMerge, OwnerMergeSource};
+use crate::db::stats::duplicate_owners::{duplicate_owners, DEFAULT_MIN_SCORE};
 use crate::{Connection, Db};
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SuggestionsQuery {
+    project_id: Option<u32>,
+    /// Pairs scoring below this are left out, defaults to `0.5`
+    min_score: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AcceptSuggestion {
+    primary_owner_id: u32,
+    owner_ids: Vec<u32>,
+}
+
 pub async fn get_owners_by_handle(
     db: web::Data<Db>,
     path: web::Path<String>,
@@ -27,6 +44,15 @@ pub async fn load(db: web::Data<Db>, path: web::Path<u32>) -> Result<impl Respon
     Ok(web::Json(owners))
 }
 
+/// The history of the owner's primary owner and what changed it
+pub async fn merges(db: web::Data<Db>, path: web::Path<u32>) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let owner = Owner::load(path.into_inner(), &conn)?;
+    let merges = OwnerMerge::load_by_owner(owner.id, &conn)?;
+    Ok(web::Json(merges))
+}
+
 pub async fn update_owner(
     db: web::Data<Db>,
     path: web::Path<u32>,
@@ -39,3 +65,65 @@ pub async fn update_owner(
     let owner = owner.update(update_owner, &conn)?;
     Ok(web::Json(owner))
 }
+
+/// Groups of owners that are likely the same person, with the reasons each pair matched
+pub async fn suggestions(
+    db: web::Data<Db>,
+    query: web::Query<SuggestionsQuery>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let query = query.into_inner();
+    let groups = duplicate_owners(
+        query.project_id,
+        query.min_score.unwrap_or(DEFAULT_MIN_SCORE),
+        &conn,
+    )?;
+    Ok(web::Json(groups))
+}
+
+/// Points the owners of an accepted suggestion at its primary owner
+pub async fn accept_suggestion(
+    db: web::Data<Db>,
+    json: web::Json<AcceptSuggestion>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let accepted = json.into_inner();
+    let merged = OwnerMerge::merge_into(
+        accepted.primary_owner_id,
+        &accepted.owner_ids,
+        OwnerMergeSource::Suggestion,
+        &conn,
+    )?;
+    Ok(web::Json(merged))
+}
+
+pub async fn bot_patterns(db: web::Data<Db>) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let bot_patterns = BotPattern::all(&conn)?;
+    Ok(web::Json(bot_patterns))
+}
+
+/// Adds a pattern and flags the existing owners it matches as bots
+pub async fn create_bot_pattern(
+    db: web::Data<Db>,
+    json: web::Json<NewBotPattern>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let bot_pattern = json.into_inner().save(&conn)?;
+    Ok(web::Json(bot_pattern))
+}
+
+pub async fn destroy_bot_pattern(
+    db: web::Data<Db>,
+    path: web::Path<u32>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let bot_pattern = BotPattern::load(path.into_inner(), &conn)?;
+    let result = bot_pattern.destroy(&conn)?;
+    Ok(web::Json(result))
+}
@@ -5,8 +5,9 @@ use log::debug;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::db::models::project::NewProject;
+use crate::db::models::project::{NewProject, UpdateProject};
 use crate::db::Connection;
+use crate::git::backend::{CommandTimeout, GitBackendKind};
 use crate::git::manager::GitManager;
 use crate::{Db, FownerError, Processor, Project};
 
@@ -14,6 +15,41 @@ use crate::{Db, FownerError, Processor, Project};
 pub struct FetchRequest {
     pub stop_at_sha: Option<String>,
     pub skip_github_labels: Option<bool>,
+    /// Delete commits that are no longer reachable instead of flagging them
+    pub prune_unreachable: Option<bool>,
+}
+
+/// Opens the project's clone when called, cloning it first if it is missing
+fn git_manager(
+    project: &Project,
+    absolute_path: PathBuf,
+    git_backend: &GitBackendKind,
+    git_timeout: &CommandTimeout,
+) -> impl FnOnce() -> Result<GitManager, FownerError> + Send + 'static {
+    let repo_url = project.repo_url.clone();
+    let credentials = project.credentials.clone();
+    let remotes = project.remote_config();
+    let git_backend = *git_backend;
+    let git_timeout = git_timeout.0;
+    move || {
+        GitManager::init(
+            absolute_path,
+            repo_url,
+            git_backend,
+            credentials,
+            remotes,
+            git_timeout,
+        )
+    }
+}
+
+/// Runs clones, fetches and prunes on the blocking thread pool instead of an actix worker
+async fn run_git<T: Send + 'static>(
+    git: impl FnOnce() -> Result<T, FownerError> + Send + 'static,
+) -> Result<T, FownerError> {
+    web::block(git)
+        .await
+        .map_err(|e| FownerError::Internal(e.to_string()))?
 }
 
 pub async fn create(db: web::Data<Db>, json: web::Json<NewProject>) -> Result<impl Responder> {
@@ -27,7 +63,7 @@ pub async fn create(db: web::Data<Db>, json: web::Json<NewProject>) -> Result<im
         repo_url.map(|repo_url| {
             repo_url
                 .split('/')
-                .last()
+                .next_back()
                 .unwrap_or_default()
                 .to_string()
                 .replace(".git", "")
@@ -43,51 +79,64 @@ pub async fn create(db: web::Data<Db>, json: web::Json<NewProject>) -> Result<im
 pub async fn fetch_remote_repo(
     db: web::Data<Db>,
     storage_path: web::Data<PathBuf>,
+    git_backend: web::Data<GitBackendKind>,
+    git_timeout: web::Data<CommandTimeout>,
     project_id: web::Path<u32>,
     json: web::Json<FetchRequest>,
 ) -> Result<impl Responder> {
     let json = json.into_inner();
     let stop_at_sha = json.stop_at_sha;
     let skip_github_labels = json.skip_github_labels.unwrap_or_default();
+    let prune_unreachable = json.prune_unreachable.unwrap_or_default();
     debug!(
         "Fetching pulls up until {:?} skipping github labels: {}",
         stop_at_sha, skip_github_labels
     );
-    let db = db.get_ref();
-    let mut db = db.pool.get().map_err(FownerError::R2d2)?;
-    let tx = db.transaction().map_err(FownerError::Rusqlite)?;
-    let conn = Connection::from(tx);
+    let db = db.get_ref().clone();
     let project_id = project_id.into_inner();
-    let project = Project::load(project_id, &conn)?;
+    let project = Project::load(project_id, &Connection::try_from(&db)?)?;
     let absolute_path = project.get_absolute_dir(&storage_path.into_inner(), true)?;
     debug!("Fetching git repo {:?}", absolute_path.to_str());
-    let git_manager = GitManager::init(absolute_path, project.repo_url.clone())?;
-    git_manager.fetch()?;
-    debug!("Fetched git repo");
-    let processor = Processor {
-        conn: &conn,
-        git_manager,
-        project,
-    };
-    debug!("Processing commits");
-    let (commits_processed, total_commits) = processor
-        .fetch_commits_and_update_db(stop_at_sha, skip_github_labels)
-        .await?;
-    conn.transaction()?
-        .commit()
-        .map_err(FownerError::Rusqlite)?;
+    let git_manager = git_manager(&project, absolute_path, &git_backend, &git_timeout);
+    let (commits_processed, total_commits, reconciled) = run_git(move || {
+        let git_manager = git_manager()?;
+        git_manager.fetch()?;
+        debug!("Fetched git repo");
+        let mut db = db.pool.get().map_err(FownerError::R2d2)?;
+        let tx = db.transaction().map_err(FownerError::Rusqlite)?;
+        let conn = Connection::from(tx);
+        let processor = Processor {
+            conn: &conn,
+            git_manager,
+            project: Project::load(project_id, &conn)?,
+        };
+        debug!("Reconciling rewritten history");
+        let reconciled = processor.reconcile_unreachable(prune_unreachable)?;
+        debug!("Processing commits");
+        // The github label requests need a runtime of their own on the blocking thread
+        let (commits_processed, total_commits) = actix_web::rt::System::new()
+            .block_on(processor.fetch_commits_and_update_db(stop_at_sha, skip_github_labels))?;
+        drop(processor);
+        conn.transaction()?
+            .commit()
+            .map_err(FownerError::Rusqlite)?;
+        Ok((commits_processed, total_commits, reconciled))
+    })
+    .await?;
     debug!(
         "{} commits processed of {}",
         commits_processed, total_commits
     );
     Ok(web::Json(
-        json!({ "commits_processed": commits_processed, "total_commits": total_commits }),
+        json!({ "commits_processed": commits_processed, "total_commits": total_commits, "reconciled": reconciled }),
     ))
 }
 
 pub async fn clean(
     db: web::Data<Db>,
     storage_path: web::Data<PathBuf>,
+    git_backend: web::Data<GitBackendKind>,
+    git_timeout: web::Data<CommandTimeout>,
     project_id: web::Path<u32>,
 ) -> Result<impl Responder> {
     let project_id = project_id.into_inner();
@@ -96,16 +145,54 @@ pub async fn clean(
     let mut db = db.pool.get().map_err(FownerError::R2d2)?;
     let tx = db.transaction().map_err(FownerError::Rusqlite)?;
     let conn = Connection::from(tx);
-    let project_id = project_id;
     let project = Project::load(project_id, &conn)?;
     let absolute_path = project.get_absolute_dir(&storage_path.into_inner(), true)?;
     debug!("Fetching git repo {:?}", absolute_path.to_str());
-    let git_manager = GitManager::init(absolute_path, project.repo_url.clone())?;
-    let result = git_manager.cleanup()?;
+    let git_manager = git_manager(&project, absolute_path, &git_backend, &git_timeout);
+    let result = run_git(move || git_manager()?.cleanup()).await?;
 
     Ok(web::Json(json!({ "message": result })))
 }
 
+/// Stores the uploaded mailmap for the project and merges the owners it resolves
+pub async fn upload_mailmap(
+    db: web::Data<Db>,
+    storage_path: web::Data<PathBuf>,
+    git_backend: web::Data<GitBackendKind>,
+    git_timeout: web::Data<CommandTimeout>,
+    project_id: web::Path<u32>,
+    body: String,
+) -> Result<impl Responder> {
+    let project_id = project_id.into_inner();
+    let db = db.get_ref();
+    let project = Project::load(project_id, &Connection::try_from(db)?)?;
+    let absolute_path = project.get_absolute_dir(&storage_path.into_inner(), true)?;
+    let git_manager = git_manager(&project, absolute_path, &git_backend, &git_timeout);
+    let git_manager = run_git(git_manager).await?;
+    // The write transaction only opens once the clone is ready
+    let mut db = db.pool.get().map_err(FownerError::R2d2)?;
+    let tx = db.transaction().map_err(FownerError::Rusqlite)?;
+    let conn = Connection::from(tx);
+    let project = Project::load(project_id, &conn)?.update(
+        UpdateProject {
+            mailmap: Some(body),
+            ..UpdateProject::default()
+        },
+        &conn,
+    )?;
+    let processor = Processor {
+        conn: &conn,
+        git_manager,
+        project,
+    };
+    let merged = processor.resolve_mailmap()?;
+    conn.transaction()?
+        .commit()
+        .map_err(FownerError::Rusqlite)?;
+    debug!("Merged {} owners for project {}", merged, project_id);
+    Ok(web::Json(json!({ "merged": merged })))
+}
+
 pub async fn all(db: web::Data<Db>) -> Result<impl Responder> {
     let db = db.get_ref();
     let conn = Connection::try_from(db)?;
@@ -122,6 +209,28 @@ pub async fn load(db: web::Data<Db>, path: web::Path<u32>) -> Result<impl Respon
     Ok(web::Json(json!(display_project)))
 }
 
+pub async fn update(
+    db: web::Data<Db>,
+    path: web::Path<u32>,
+    json: web::Json<UpdateProject>,
+) -> Result<impl Responder> {
+    let project_id = path.into_inner();
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let project = Project::load(project_id, &conn)?;
+    let project = project.update(json.into_inner(), &conn)?;
+    Ok(web::Json(project))
+}
+
+/// Removes the project's credentials, later fetches fall back to the server's own
+pub async fn clear_credentials(db: web::Data<Db>, path: web::Path<u32>) -> Result<impl Responder> {
+    let project_id = path.into_inner();
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let project = Project::load(project_id, &conn)?.clear_credentials(&conn)?;
+    Ok(web::Json(project))
+}
+
 pub async fn destroy(db: web::Data<Db>, path: web::Path<u32>) -> Result<impl Responder> {
     let project_id = path.into_inner();
     let db = db.get_ref();
@@ -137,20 +246,24 @@ mod tests {
     use actix_web::dev::Service;
     use actix_web::{dev, error::Error as HttpError, test, web, web::Data, App};
     use serde_json::Value;
+    use std::path::Path;
 
     use crate::db::models::project::DisplayProject;
+    use crate::test::builders::repo_builder::RepoBuilder;
     use crate::test::tests::TestHandler;
 
     use super::*;
 
     async fn init(
         db: &Db,
-        tmp_dir: &PathBuf,
+        tmp_dir: &Path,
     ) -> impl Service<Request, Response = dev::ServiceResponse, Error = HttpError> {
         test::init_service(
             App::new()
                 .app_data(Data::new(db.clone()))
-                .app_data(Data::new(tmp_dir.clone()))
+                .app_data(Data::new(tmp_dir.to_path_buf()))
+                .app_data(Data::new(GitBackendKind::default()))
+                .app_data(Data::new(CommandTimeout::default()))
                 .route("/{id}/fetch", web::post().to(fetch_remote_repo))
                 .route("/{id}", web::get().to(load))
                 .route("/", web::post().to(create))
@@ -164,8 +277,8 @@ mod tests {
         let handler = TestHandler::init();
         let db = &handler.db;
         let conn = Connection::try_from(db).unwrap();
-        let app = init(&db, &handler.tmp_dir).await;
-        let req = test::TestRequest::post().uri("/").set_json(&json!({"name": "TestProject", "repo_url": "https://github.com/Krakaw/empty.git", "path": "empty", "github_labels_only": false })).to_request();
+        let app = init(db, &handler.tmp_dir).await;
+        let req = test::TestRequest::post().uri("/").set_json(json!({"name": "TestProject", "repo_url": "https://github.com/Krakaw/empty.git", "path": "empty", "github_labels_only": false })).to_request();
         let project: Project = test::call_and_read_body_json(&app, req).await;
         assert_eq!(project.id, 1);
         let db_project = Project::load(1, &conn).unwrap();
@@ -191,7 +304,7 @@ mod tests {
         assert_eq!(project.files.len(), 1);
         let req = test::TestRequest::post()
             .uri("/1/fetch")
-            .set_json(&json!({"stop_at_sha": "no_stop"}))
+            .set_json(json!({"stop_at_sha": "no_stop"}))
             .to_request();
         let commits: Value = test::call_and_read_body_json(&app, req).await;
         assert_eq!(
@@ -199,4 +312,35 @@ mod tests {
             0
         );
     }
+
+    #[actix_web::test]
+    async fn fetch_local_repo() {
+        let handler = TestHandler::init();
+        let db = &handler.db;
+        let origin = RepoBuilder::init(&handler.tmp_dir.join("origin"));
+        origin.write("src/main.rs", "fn main() {}\n");
+        origin.commit("Initial commit");
+        origin.write("src/lib.rs", "pub fn run() {}\n");
+        origin.commit("Add run");
+        let app = init(db, &handler.tmp_dir).await;
+        let req = test::TestRequest::post()
+            .uri("/")
+            .set_json(
+                json!({ "repo_url": origin.path, "path": "local", "github_labels_only": false }),
+            )
+            .to_request();
+        let project: Project = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/fetch", project.id))
+            .set_json(json!({}))
+            .to_request();
+        let commits: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(commits["commits_processed"], 2);
+        assert_eq!(commits["total_commits"], 2);
+        let req = test::TestRequest::get()
+            .uri(&format!("/{}", project.id))
+            .to_request();
+        let project: DisplayProject = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(project.files.len(), 2);
+    }
 }
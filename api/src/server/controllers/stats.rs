@@ -12,7 +12,12 @@ pub struct ContributionStatsQuery {
     start: Option<NaiveDate>,
     end: Option<NaiveDate>,
     breakdown: Option<TimeBreakdown>,
+    commit_type: Option<String>,
     merge_projects: Option<bool>,
+    /// Merge commits are counted unless this is `false`
+    include_merges: Option<bool>,
+    /// Bots are left out unless this is `true`
+    include_bots: Option<bool>,
 }
 
 pub async fn contributions(
@@ -28,7 +33,10 @@ pub async fn contributions(
         query.start,
         query.end,
         query.breakdown,
+        query.commit_type,
         query.merge_projects.unwrap_or_default(),
+        query.include_merges.unwrap_or(true),
+        query.include_bots.unwrap_or_default(),
         &conn,
     )?;
     Ok(web::Json(contributions))
@@ -0,0 +1,38 @@
+use actix_web::{web, Responder, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::tag::Tag;
+use crate::{Connection, Db};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagChangesQuery {
+    to: String,
+    /// Defaults to the tag before `to`
+    #[serde(default)]
+    from: Option<String>,
+}
+
+pub async fn all(db: web::Data<Db>, project_id: web::Path<u32>) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let tags = Tag::load_by_project(project_id.into_inner(), &conn)?;
+    Ok(web::Json(tags))
+}
+
+pub async fn changes(
+    db: web::Data<Db>,
+    project_id: web::Path<u32>,
+    query: web::Query<TagChangesQuery>,
+) -> Result<impl Responder> {
+    let db = db.get_ref();
+    let conn = Connection::try_from(db)?;
+    let project_id = project_id.into_inner();
+    let query = query.into_inner();
+    let to = Tag::load_by_name(project_id, &query.to, &conn)?;
+    let from = match query.from {
+        Some(from) => Some(Tag::load_by_name(project_id, &from, &conn)?),
+        None => None,
+    };
+    let changes = to.changes_since(from, &conn)?;
+    Ok(web::Json(changes))
+}
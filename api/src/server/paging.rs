@@ -23,9 +23,10 @@ pub struct Paging {
     pub sort_dir: Option<SortDir>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub enum SortDir {
     Asc,
+    #[default]
     Desc,
 }
 
@@ -48,12 +49,6 @@ impl ToSql for SortDir {
     }
 }
 
-impl Default for SortDir {
-    fn default() -> Self {
-        Self::Desc
-    }
-}
-
 impl Display for SortDir {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let s = match self {
@@ -73,6 +73,12 @@ impl FileBuilder {
                 parent_sha: last_sha.clone(),
                 description: commit_sha.clone(),
                 commit_time: Utc::now().naive_utc(),
+                committer_handle: None,
+                committer_email: None,
+                committer_time: None,
+                body: None,
+                commit_type: None,
+                is_merge: false,
             }
             .save(conn)
             .unwrap();
@@ -80,6 +86,8 @@ impl FileBuilder {
             FileCommit {
                 file_id: file.id,
                 commit_id: commit.id,
+                additions: 0,
+                deletions: 0,
             }
             .save(conn)
             .unwrap();
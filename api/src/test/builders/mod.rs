@@ -1,2 +1,3 @@
 pub mod file_builder;
 pub mod project_builder;
+pub mod repo_builder;
@@ -1,4 +1,5 @@
 use crate::db::models::project::NewProject;
+use crate::git::remote::CloneMode;
 use crate::{Connection, FownerError, Project};
 use std::env::temp_dir;
 use std::path::{Path, PathBuf};
@@ -44,6 +45,18 @@ impl ProjectBuilder {
             path: self.path,
             github_api_token: self.github_api_token,
             github_labels_only: false,
+            commit_feature_patterns: None,
+            label_feature_patterns: None,
+            conventional_commits: false,
+            branches: None,
+            merge_policy: None,
+            subpaths: None,
+            include_globs: None,
+            exclude_globs: None,
+            credentials: None,
+            remote_name: None,
+            clone_mode: CloneMode::Checkout,
+            remotes: None,
         }
         .save(conn)
     }
@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Creates a local git repository for tests that need real history
+#[allow(dead_code)]
+pub struct RepoBuilder {
+    pub path: PathBuf,
+}
+
+#[allow(dead_code)]
+impl RepoBuilder {
+    pub fn init(path: &Path) -> Self {
+        std::fs::create_dir_all(path).unwrap();
+        let builder = Self {
+            path: path.to_path_buf(),
+        };
+        builder.git(&["init", "-q", "-b", "main"]);
+        builder.git(&["config", "user.name", "Krakaw"]);
+        builder.git(&["config", "user.email", "krakaw@example.com"]);
+        builder.git(&["config", "commit.gpgsign", "false"]);
+        builder
+    }
+
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(&self.path)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    pub fn write(&self, file: &str, contents: &str) -> &Self {
+        let path = self.path.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+        self
+    }
+
+    /// Commits everything in the working tree and returns the new sha
+    pub fn commit(&self, message: &str) -> String {
+        self.commit_as("Krakaw", "krakaw@example.com", message)
+    }
+
+    /// Commits with an author date of `timestamp` seconds since the epoch
+    /// Commits with both the author and committer dates set to `timestamp`
+    pub fn commit_at(&self, timestamp: i64, message: &str) -> String {
+        self.git(&["add", "-A"]);
+        let date = format!("@{}", timestamp);
+        let output = Command::new("git")
+            .current_dir(&self.path)
+            .env("GIT_COMMITTER_DATE", &date)
+            .args(["commit", "-q", "--date", &date, "-m", message])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git commit failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        self.git(&["rev-parse", "HEAD"])
+    }
+
+    pub fn commit_as(&self, name: &str, email: &str, message: &str) -> String {
+        self.git(&["add", "-A"]);
+        let author = format!("{} <{}>", name, email);
+        self.git(&["commit", "-q", "--author", &author, "-m", message]);
+        self.git(&["rev-parse", "HEAD"])
+    }
+}
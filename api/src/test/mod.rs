@@ -2,6 +2,9 @@ pub mod builders;
 
 #[cfg(test)]
 pub mod tests {
+    use crate::git::backend::{GitBackendKind, DEFAULT_GIT_TIMEOUT};
+    use crate::git::manager::GitManager;
+    use crate::git::secret_key::SecretKey;
     use crate::Db;
     use log::debug;
     use r2d2_sqlite::SqliteConnectionManager;
@@ -18,13 +21,33 @@ pub mod tests {
     }
 
     impl TestHandler {
+        /// Lets tests store credentials, every test shares the same key
+        pub fn install_secret_key() {
+            SecretKey::from_base64("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=")
+                .unwrap()
+                .install();
+        }
+
         pub fn init() -> Self {
+            Self::install_secret_key();
             let tmp_dir = temp_dir().join(rand::thread_rng().gen_range(0..100_000_000).to_string());
             fs::create_dir(&tmp_dir).unwrap();
             let db = TestHandler::init_test_db(tmp_dir.as_path());
             Self { db, tmp_dir }
         }
 
+        /// Opens an existing repository without a url, credentials or extra remotes
+        pub fn git_manager(path: &Path, backend: GitBackendKind) -> GitManager {
+            GitManager {
+                path: path.to_path_buf(),
+                url: None,
+                backend,
+                credentials: None,
+                remotes: Default::default(),
+                timeout: DEFAULT_GIT_TIMEOUT,
+            }
+        }
+
         fn init_test_db(base_path: &Path) -> Db {
             let path = base_path.join(format!(
                 "{}.db.sqlite",